@@ -45,7 +45,7 @@ impl Color {
     }
 
     pub fn len(&self) -> f64 {
-        (&self).len_squared().sqrt()
+        self.len_squared().sqrt()
     }
 
     pub fn len_squared(&self) -> f64 {
@@ -61,11 +61,19 @@ impl Color {
         }
     }
 
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.r + 0.7152 * self.g + 0.0722 * self.b
+    }
+
+    pub fn max_component(&self) -> f64 {
+        self.r.max(self.g).max(self.b)
+    }
+
     pub fn dot(lhs: &Color, rhs: &Color) -> f64 {
         lhs.r * rhs.r + lhs.g * rhs.g + lhs.b * rhs.b
     }
 
-    pub fn to_rgba(&self) -> Rgba<u8> {
+    pub fn to_rgba(self) -> Rgba<u8> {
         assert!(0.0 <= self.r, "Found negative r {}", self.r);
         assert!(0.0 <= self.g, "Found negative g {}", self.g);
         assert!(0.0 <= self.b, "Found negative b {}", self.b);
@@ -82,11 +90,12 @@ impl Color {
         Rgba([r, g, b, 255])
     }
 
+    // Spectral rendering can produce colors outside of the RGB gamut, those are clipped
     pub fn gamma_2(self) -> Color {
         Color {
-            r: self.r.sqrt(),
-            g: self.g.sqrt(),
-            b: self.b.sqrt(),
+            r: self.r.max(0.0).sqrt(),
+            g: self.g.max(0.0).sqrt(),
+            b: self.b.max(0.0).sqrt(),
         }
     }
 
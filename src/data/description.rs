@@ -0,0 +1,484 @@
+use nalgebra::Vector3;
+
+// crate imports
+use crate::data::{scenes, Color};
+use crate::engine::{
+    Background, Camera, ConstantBackground, DensityField, DensityGrid, EnvironmentLight, Light,
+    LightTree, Scene, Sky, Volume,
+};
+use crate::integrators;
+use crate::materials::Medium;
+
+// std imports
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
+// Width of the map a procedural sky is baked into
+const SKY_RESOLUTION: usize = 512;
+
+// Everything needed to rebuild a Scene in another process.
+// Geometry is not shipped over the wire, only the name of the scene builder
+// (and the seed for builders that place objects randomly, which also seeds the samples).
+#[derive(Debug, Clone, PartialEq)]
+pub struct SceneDescription {
+    pub scene: String,
+    pub seed: u64,
+    pub integrator: String,
+    pub image_width: u64,
+    pub image_height: u64,
+    pub samples_per_pixel: u64,
+    pub max_depth: i32,
+    pub rr_min_depth: i32,
+    pub look_from: Vector3<f64>,
+    pub look_at: Vector3<f64>,
+    pub vup: Vector3<f64>,
+    pub vfov: f64,
+    pub aperture: f64,
+    pub focus_dist: f64,
+    // Colour seen where rays leave the scene, when given it wins over the scene's own
+    // background. Black if neither is set.
+    pub background: Option<Color>,
+    // What rays straight from the camera see instead of the background, if anything
+    pub camera_background: Option<Color>,
+    // Path of an equirectangular .hdr or .exr lighting the scene instead of background,
+    // which every worker must be able to read. Empty for none.
+    pub environment: String,
+    pub environment_rotation: f64,
+    pub environment_intensity: f64,
+    // Procedural sky instead of an environment map
+    pub sky: bool,
+    pub sun: SunPosition,
+    pub turbidity: f64,
+    pub ground_albedo: Color,
+    // Path of a density grid (see DensityGrid::load) added to the scene as a medium
+    // filling volume_min to volume_max, which every worker must be able to read.
+    // Empty for none.
+    pub volume: String,
+    pub volume_min: Vector3<f64>,
+    pub volume_max: Vector3<f64>,
+    // Extinction per unit distance where the grid is 1
+    pub volume_density: f64,
+    pub volume_albedo: Color,
+}
+
+// Where the sun of a procedural sky is
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SunPosition {
+    // Degrees above the horizon and from north (-z) towards east (+x)
+    Angles {
+        elevation: f64,
+        azimuth: f64,
+    },
+    // Seen from a place at a time: degrees north and east, day of the year from 1 and
+    // hour in UTC
+    Location {
+        latitude: f64,
+        longitude: f64,
+        day: f64,
+        hour: f64,
+    },
+}
+
+impl SunPosition {
+    fn angles(self) -> (f64, f64) {
+        match self {
+            SunPosition::Angles { elevation, azimuth } => (elevation, azimuth),
+            _ => (45.0, 180.0),
+        }
+    }
+
+    // Midsummer noon on the equator unless set
+    fn location(self) -> (f64, f64, f64, f64) {
+        match self {
+            SunPosition::Location {
+                latitude,
+                longitude,
+                day,
+                hour,
+            } => (latitude, longitude, day, hour),
+            _ => (0.0, 0.0, 172.0, 12.0),
+        }
+    }
+}
+
+impl SceneDescription {
+    pub fn cornell_box() -> SceneDescription {
+        SceneDescription {
+            scene: "cornell_box".to_string(),
+            seed: 0,
+            integrator: "path".to_string(),
+            image_width: 500,
+            image_height: 500,
+            samples_per_pixel: 100,
+            max_depth: 100,
+            rr_min_depth: 5,
+            look_from: Vector3::new(278.0, 278.0, -800.0),
+            look_at: Vector3::new(278.0, 278.0, 0.0),
+            vup: Vector3::new(0.0, 1.0, 0.0),
+            vfov: 40.0,
+            aperture: 0.0,
+            focus_dist: 10.0,
+            background: None,
+            camera_background: None,
+            environment: String::new(),
+            environment_rotation: 0.0,
+            environment_intensity: 1.0,
+            sky: false,
+            sun: SunPosition::Angles {
+                elevation: 45.0,
+                azimuth: 180.0,
+            },
+            turbidity: 3.0,
+            ground_albedo: Color::new(0.3, 0.3, 0.3),
+            volume: String::new(),
+            volume_min: Vector3::new(0.0, 0.0, 0.0),
+            volume_max: Vector3::new(555.0, 555.0, 555.0),
+            volume_density: 0.01,
+            volume_albedo: Color::new(0.8, 0.8, 0.8),
+        }
+    }
+
+    pub fn build(&self) -> Result<Scene, String> {
+        let mut world = match scenes::from_name(&self.scene, self.seed) {
+            Some(world) => world,
+            None => return Err(format!("unknown scene '{}'", self.scene)),
+        };
+        let integrator = match integrators::from_name(&self.integrator) {
+            Some(integrator) => integrator,
+            None => return Err(format!("unknown integrator '{}'", self.integrator)),
+        };
+        if !self.volume.is_empty() {
+            let grid = DensityGrid::load(&self.volume)?;
+            let volume = Volume::new(
+                self.volume_min,
+                self.volume_max,
+                grid.share(),
+                self.volume_density,
+                Medium::new(self.volume_albedo).share(),
+            );
+            world.objects.add(volume.share());
+        }
+        let mut punctual_lights = world.punctual_lights;
+        let background = match (self.sky, self.environment.is_empty()) {
+            (false, true) => match (self.background, world.background) {
+                (Some(color), _) => ConstantBackground::new(color).share(),
+                (None, Some(background)) => background,
+                (None, None) => ConstantBackground::new(Color::new(0.0, 0.0, 0.0)).share(),
+            },
+            (false, false) => EnvironmentLight::load(&self.environment)?
+                .with_rotation(self.environment_rotation)
+                .with_intensity(self.environment_intensity)
+                .share(),
+            (true, true) => {
+                let sky = match self.sun {
+                    SunPosition::Angles { elevation, azimuth } => Sky::new(elevation, azimuth),
+                    SunPosition::Location {
+                        latitude,
+                        longitude,
+                        day,
+                        hour,
+                    } => Sky::from_location(latitude, longitude, day, hour),
+                };
+                let sky = sky
+                    .with_turbidity(self.turbidity)
+                    .with_ground_albedo(self.ground_albedo)
+                    .with_intensity(self.environment_intensity);
+                punctual_lights.push(sky.sun().share());
+                sky.environment(SKY_RESOLUTION).share()
+            }
+            (true, false) => return Err("sky and environment can't both be set".to_string()),
+        };
+        let aspect_ratio = self.image_width as f64 / self.image_height as f64;
+        let cam = Camera::new(
+            self.look_from,
+            self.look_at,
+            self.vup,
+            self.vfov,
+            aspect_ratio,
+            self.aperture,
+            self.focus_dist,
+        );
+
+        let scene = Scene {
+            cam: Arc::new(cam),
+            world: Arc::new(world.objects),
+            lights: Arc::new(LightTree::new(world.lights)),
+            punctual_lights,
+            integrator,
+            samples_per_pixel: self.samples_per_pixel,
+            seed: self.seed,
+            max_depth: self.max_depth,
+            rr_min_depth: self.rr_min_depth,
+            image_width: self.image_width as f64,
+            image_height: self.image_height as f64,
+            completed: Arc::new(AtomicBool::new(false)),
+            prerender_finished: Arc::new(AtomicBool::new(false)),
+            background,
+            camera_background: self
+                .camera_background
+                .map(|color| ConstantBackground::new(color).share()),
+        };
+        scene.integrator.prepare(&scene);
+        Ok(scene)
+    }
+
+    // Single line of space separated key=value pairs, strings are escaped so paths
+    // with spaces survive
+    pub fn serialize(&self) -> String {
+        format!(
+            "scene={} seed={} integrator={} width={} height={} samples={} depth={} rr_depth={} look_from={} look_at={} vup={} vfov={} aperture={} focus_dist={} background={} camera_background={} environment={} environment_rotation={} environment_intensity={} sky={} {} turbidity={} ground_albedo={} volume={} volume_min={} volume_max={} volume_density={} volume_albedo={}",
+            escape(&self.scene),
+            self.seed,
+            escape(&self.integrator),
+            self.image_width,
+            self.image_height,
+            self.samples_per_pixel,
+            self.max_depth,
+            self.rr_min_depth,
+            format_vector(&self.look_from),
+            format_vector(&self.look_at),
+            format_vector(&self.vup),
+            self.vfov,
+            self.aperture,
+            self.focus_dist,
+            self.background
+                .map(|c| format_vector(&Vector3::new(c.r(), c.g(), c.b())))
+                .unwrap_or_default(),
+            self.camera_background
+                .map(|c| format_vector(&Vector3::new(c.r(), c.g(), c.b())))
+                .unwrap_or_default(),
+            escape(&self.environment),
+            self.environment_rotation,
+            self.environment_intensity,
+            self.sky,
+            match self.sun {
+                SunPosition::Angles { elevation, azimuth } => {
+                    format!("sun_elevation={} sun_azimuth={}", elevation, azimuth)
+                }
+                SunPosition::Location {
+                    latitude,
+                    longitude,
+                    day,
+                    hour,
+                } => format!(
+                    "latitude={} longitude={} day={} hour={}",
+                    latitude, longitude, day, hour
+                ),
+            },
+            self.turbidity,
+            format_vector(&Vector3::new(
+                self.ground_albedo.r(),
+                self.ground_albedo.g(),
+                self.ground_albedo.b()
+            )),
+            escape(&self.volume),
+            format_vector(&self.volume_min),
+            format_vector(&self.volume_max),
+            self.volume_density,
+            format_vector(&Vector3::new(
+                self.volume_albedo.r(),
+                self.volume_albedo.g(),
+                self.volume_albedo.b()
+            )),
+        )
+    }
+
+    pub fn deserialize(line: &str) -> Result<SceneDescription, String> {
+        let mut description = SceneDescription::cornell_box();
+        for pair in line.split_whitespace() {
+            description.set(&unescape(pair)?)?;
+        }
+        Ok(description)
+    }
+
+    // Overrides a single field from a key=value pair
+    pub fn set(&mut self, pair: &str) -> Result<(), String> {
+        let (key, value) = match pair.split_once('=') {
+            Some(kv) => kv,
+            None => return Err(format!("malformed pair '{}'", pair)),
+        };
+        match key {
+            "scene" => self.scene = value.to_string(),
+            "seed" => self.seed = parse_value(key, value)?,
+            "integrator" => self.integrator = value.to_string(),
+            "width" => self.image_width = parse_value(key, value)?,
+            "height" => self.image_height = parse_value(key, value)?,
+            "samples" => self.samples_per_pixel = parse_value(key, value)?,
+            "depth" => self.max_depth = parse_value(key, value)?,
+            "rr_depth" => self.rr_min_depth = parse_value(key, value)?,
+            "look_from" => self.look_from = parse_vector(key, value)?,
+            "look_at" => self.look_at = parse_vector(key, value)?,
+            "vup" => self.vup = parse_vector(key, value)?,
+            "vfov" => self.vfov = parse_value(key, value)?,
+            "aperture" => self.aperture = parse_value(key, value)?,
+            "focus_dist" => self.focus_dist = parse_value(key, value)?,
+            "background" => {
+                self.background = match value.is_empty() {
+                    true => None,
+                    false => Some(Color::from_vector(&parse_vector(key, value)?)),
+                }
+            }
+            "camera_background" => {
+                self.camera_background = match value.is_empty() {
+                    true => None,
+                    false => Some(Color::from_vector(&parse_vector(key, value)?)),
+                }
+            }
+            "environment" => self.environment = value.to_string(),
+            "environment_rotation" => self.environment_rotation = parse_value(key, value)?,
+            "environment_intensity" => self.environment_intensity = parse_value(key, value)?,
+            "sky" => self.sky = parse_value(key, value)?,
+            "sun_elevation" | "sun_azimuth" => {
+                let (mut elevation, mut azimuth) = self.sun.angles();
+                match key {
+                    "sun_elevation" => elevation = parse_value(key, value)?,
+                    _ => azimuth = parse_value(key, value)?,
+                }
+                self.sun = SunPosition::Angles { elevation, azimuth };
+            }
+            "latitude" | "longitude" | "day" | "hour" => {
+                let (mut latitude, mut longitude, mut day, mut hour) = self.sun.location();
+                let value = parse_value(key, value)?;
+                match key {
+                    "latitude" => latitude = value,
+                    "longitude" => longitude = value,
+                    "day" => day = value,
+                    _ => hour = value,
+                }
+                self.sun = SunPosition::Location {
+                    latitude,
+                    longitude,
+                    day,
+                    hour,
+                };
+            }
+            "turbidity" => self.turbidity = parse_value(key, value)?,
+            "ground_albedo" => self.ground_albedo = Color::from_vector(&parse_vector(key, value)?),
+            "volume" => self.volume = value.to_string(),
+            "volume_min" => self.volume_min = parse_vector(key, value)?,
+            "volume_max" => self.volume_max = parse_vector(key, value)?,
+            "volume_density" => self.volume_density = parse_value(key, value)?,
+            "volume_albedo" => self.volume_albedo = Color::from_vector(&parse_vector(key, value)?),
+            _ => return Err(format!("unknown key '{}'", key)),
+        }
+        Ok(())
+    }
+}
+
+// Percent encoding of the characters that would break up a serialized line
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '%' | ' ' | '\t' | '\n' | '\r' => escaped.push_str(&format!("%{:02X}", c as u8)),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn unescape(value: &str) -> Result<String, String> {
+    let mut bytes = Vec::with_capacity(value.len());
+    let mut rest = value.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte != b'%' {
+            bytes.push(byte);
+            rest = tail;
+            continue;
+        }
+        let code = tail
+            .get(..2)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            .ok_or_else(|| format!("bad escape in '{}'", value))?;
+        bytes.push(code);
+        rest = &tail[2..];
+    }
+    String::from_utf8(bytes).map_err(|_| format!("bad escape in '{}'", value))
+}
+
+fn format_vector(v: &Vector3<f64>) -> String {
+    format!("{},{},{}", v[0], v[1], v[2])
+}
+
+fn parse_value<T: std::str::FromStr>(key: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value '{}' for '{}'", value, key))
+}
+
+fn parse_vector(key: &str, value: &str) -> Result<Vector3<f64>, String> {
+    let parts: Vec<&str> = value.split(',').collect();
+    if parts.len() != 3 {
+        return Err(format!("expected 3 components for '{}'", key));
+    }
+    Ok(Vector3::new(
+        parse_value(key, parts[0])?,
+        parse_value(key, parts[1])?,
+        parse_value(key, parts[2])?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serialize_round_trip() {
+        let mut description = SceneDescription::cornell_box();
+        description.scene = "product_shot".to_string();
+        description.seed = 42;
+        description.environment = "/tmp/my maps/100% sky\tnoon.hdr".to_string();
+        description.background = Some(Color::new(0.5, 0.7, 1.0));
+        description.camera_background = Some(Color::new(0.1, 0.2, 0.3));
+        description.look_from = Vector3::new(1.5, -2.0, 3.25);
+        description.volume = "/tmp/grids/ring.txt".to_string();
+        description.volume_density = 0.05;
+        description.sky = true;
+        description.turbidity = 4.5;
+        for sun in [
+            SunPosition::Angles {
+                elevation: 12.5,
+                azimuth: -30.0,
+            },
+            SunPosition::Location {
+                latitude: 51.5,
+                longitude: -0.1,
+                day: 80.0,
+                hour: 15.25,
+            },
+        ]
+        .iter()
+        {
+            description.sun = *sun;
+            let line = description.serialize();
+            assert_eq!(
+                SceneDescription::deserialize(&line),
+                Ok(description.clone())
+            );
+        }
+
+        let line = description.serialize();
+        assert!(!line.contains('\t') && !line.contains('\n'));
+        assert_eq!(SceneDescription::deserialize(&line), Ok(description));
+    }
+
+    #[test]
+    fn deserialize_rejects_bad_escapes() {
+        assert!(SceneDescription::deserialize("environment=sky%2").is_err());
+        assert!(SceneDescription::deserialize("environment=sky%zz.hdr").is_err());
+    }
+
+    #[test]
+    fn explicit_background_wins_over_scene() {
+        let mut description = SceneDescription::cornell_box();
+        description.scene = "random_world_sky".to_string();
+        let up = Vector3::new(0.0, 1.0, 0.0);
+        let sky = description.build().unwrap().background.radiance(&up);
+        assert_eq!(sky, Color::new(0.5, 0.7, 1.0));
+
+        description.background = Some(Color::new(0.2, 0.2, 0.2));
+        let background = description.build().unwrap().background.radiance(&up);
+        assert_eq!(background, Color::new(0.2, 0.2, 0.2));
+    }
+}
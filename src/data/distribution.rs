@@ -0,0 +1,162 @@
+use crate::data::rand_float01;
+
+// Piecewise constant density over [0, 1) with one bucket per value of a function
+pub struct Distribution1D {
+    func: Vec<f64>,
+    // cdf[i] is the probability of landing below bucket i, one longer than func
+    cdf: Vec<f64>,
+    integral: f64,
+}
+
+impl Distribution1D {
+    pub fn new(func: Vec<f64>) -> Distribution1D {
+        let n = func.len().max(1);
+        let mut cdf = vec![0.0; n + 1];
+        for i in 0..func.len() {
+            cdf[i + 1] = cdf[i] + func[i].max(0.0) / n as f64;
+        }
+        let integral = cdf[n];
+        for (i, c) in cdf.iter_mut().enumerate().skip(1) {
+            // All zero falls back to uniform
+            *c = match integral > 0.0 {
+                true => *c / integral,
+                false => i as f64 / n as f64,
+            };
+        }
+        Distribution1D {
+            func,
+            cdf,
+            integral,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.cdf.len() - 1
+    }
+
+    // Average of the function
+    pub fn integral(&self) -> f64 {
+        self.integral
+    }
+
+    // Point in [0, 1), its density and the bucket it fell in
+    pub fn sample(&self, u: f64) -> (f64, f64, usize) {
+        let n = self.len();
+        // Last bucket whose start is at most u
+        let index = self.cdf.partition_point(|&c| c <= u).clamp(1, n) - 1;
+        let width = self.cdf[index + 1] - self.cdf[index];
+        let offset = match width > 0.0 {
+            true => (u - self.cdf[index]) / width,
+            false => 0.0,
+        };
+        let x = ((index as f64 + offset) / n as f64).min(1.0 - f64::EPSILON);
+        (x, self.pdf(index), index)
+    }
+
+    // Density of bucket index
+    pub fn pdf(&self, index: usize) -> f64 {
+        match self.integral > 0.0 {
+            true => self.func.get(index).map_or(0.0, |f| f.max(0.0) / self.integral),
+            false => 1.0,
+        }
+    }
+}
+
+// Piecewise constant density over the unit square, from a function given row by row.
+// A row is picked from the marginal density, then a column within it.
+pub struct Distribution2D {
+    rows: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+impl Distribution2D {
+    pub fn new(func: &[f64], width: usize, height: usize) -> Distribution2D {
+        let rows: Vec<Distribution1D> = (0..height)
+            .map(|y| Distribution1D::new(func[y * width..(y + 1) * width].to_vec()))
+            .collect();
+        let marginal = Distribution1D::new(rows.iter().map(|row| row.integral()).collect());
+        Distribution2D { rows, marginal }
+    }
+
+    // Point (u along rows, v across them) and its density
+    pub fn sample(&self) -> ((f64, f64), f64) {
+        let (v, pdf_v, row) = self.marginal.sample(rand_float01());
+        let (u, pdf_u, _) = self.rows[row].sample(rand_float01());
+        ((u, v), pdf_u * pdf_v)
+    }
+
+    pub fn pdf(&self, u: f64, v: f64) -> f64 {
+        let row = ((v * self.marginal.len() as f64) as usize).min(self.marginal.len() - 1);
+        let columns = self.rows[row].len();
+        let column = ((u * columns as f64) as usize).min(columns - 1);
+        self.marginal.pdf(row) * self.rows[row].pdf(column)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{set_sample_source, SeededSource};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn assert_close(a: f64, b: f64, tolerance: f64) {
+        assert!((a - b).abs() <= tolerance, "{} is not close to {}", a, b);
+    }
+
+    #[test]
+    fn distribution_1d_sample_matches_pdf() {
+        for func in [vec![1.0, 0.0, 3.0, 2.0], vec![0.0, 0.0, 0.0]].iter() {
+            let distribution = Distribution1D::new(func.clone());
+            let n = distribution.len();
+            let total: f64 = (0..n).map(|i| distribution.pdf(i) / n as f64).sum();
+            assert_close(total, 1.0, 1e-12);
+
+            let samples = 10000;
+            let mut counts = vec![0; n];
+            for k in 0..samples {
+                let (x, pdf, index) = distribution.sample((k as f64 + 0.5) / samples as f64);
+                assert!((0.0..1.0).contains(&x));
+                assert_eq!(((x * n as f64) as usize).min(n - 1), index);
+                assert_eq!(pdf, distribution.pdf(index));
+                assert!(pdf > 0.0);
+                counts[index] += 1;
+            }
+            for (i, &count) in counts.iter().enumerate() {
+                let expected = distribution.pdf(i) / n as f64;
+                assert_close(count as f64 / samples as f64, expected, 1e-3);
+            }
+        }
+    }
+
+    #[test]
+    fn distribution_2d_sample_matches_pdf() {
+        let (width, height) = (5, 3);
+        let func: Vec<f64> = (0..width * height).map(|i| ((i * 7) % 4) as f64).collect();
+        let distribution = Distribution2D::new(&func, width, height);
+        let cell = |i: usize, n: usize| (i as f64 + 0.5) / n as f64;
+        let total: f64 = (0..width * height)
+            .map(|i| distribution.pdf(cell(i % width, width), cell(i / width, height)))
+            .sum::<f64>()
+            / (width * height) as f64;
+        assert_close(total, 1.0, 1e-12);
+
+        let previous = set_sample_source(Some(Rc::new(RefCell::new(SeededSource::new(7)))));
+        let samples = 100000;
+        let mut counts = vec![0; width * height];
+        for _ in 0..samples {
+            let ((u, v), pdf) = distribution.sample();
+            assert_close(pdf, distribution.pdf(u, v), 1e-12);
+            assert!(pdf > 0.0);
+            let x = ((u * width as f64) as usize).min(width - 1);
+            let y = ((v * height as f64) as usize).min(height - 1);
+            counts[y * width + x] += 1;
+        }
+        set_sample_source(previous);
+        for (i, &count) in counts.iter().enumerate() {
+            let expected = distribution.pdf(cell(i % width, width), cell(i / width, height))
+                / (width * height) as f64;
+            assert_close(count as f64 / samples as f64, expected, 5e-3);
+        }
+    }
+}
@@ -1,16 +1,29 @@
 pub mod color;
+pub mod description;
+pub mod distribution;
+pub mod onb;
 pub mod utils;
 pub mod vector;
 pub mod scenes;
+pub mod spectrum;
 
 pub use crate::data::color::Color;
+pub use crate::data::description::SceneDescription;
+pub use crate::data::distribution::Distribution2D;
+pub use crate::data::onb::Onb;
 // pub use crate::data::vector::Vector;
+pub use crate::data::spectrum::{
+    blackbody, set_wavelength, spectrum_to_rgb, upsample, wavelength, wavelength_to_rgb,
+    xyz_to_rgb, MAX_WAVELENGTH, MIN_WAVELENGTH,
+};
 pub use crate::data::utils::{
-    rand_float, rand_float01, rand_int 
+    rand_float, rand_float01, rand_int, set_sample_source, stream_seed, SampleSource,
+    SeededSource,
 };
 
 pub use crate::data::vector::{
     vrandom, vrandom_in_unit_disk,
-    vrandom_in_unit_sphere, vrandom_range, vunit, vlen,
+    vrandom_in_unit_sphere, vunit, vlen,
+    vrandom_cosine_direction, vrandom_to_sphere,
     reflect, refract
 };
\ No newline at end of file
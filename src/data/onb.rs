@@ -0,0 +1,50 @@
+use nalgebra::Vector3;
+use crate::data::vunit;
+
+// Orthonormal basis, w is aligned with the vector it was built from
+#[derive(Debug, Clone, Copy)]
+pub struct Onb {
+    u: Vector3<f64>,
+    v: Vector3<f64>,
+    w: Vector3<f64>,
+}
+
+impl Onb {
+    pub fn build_from_w(n: &Vector3<f64>) -> Onb {
+        let w = vunit(n);
+        let a = if w[0].abs() > 0.9 {
+            Vector3::new(0.0, 1.0, 0.0)
+        } else {
+            Vector3::new(1.0, 0.0, 0.0)
+        };
+        let v = vunit(&w.cross(&a));
+        let u = w.cross(&v);
+        Onb { u, v, w }
+    }
+
+    // Basis with u following the direction of u_hint within the plane normal to n,
+    // e.g. the tangent of a surface. Any basis around n when u_hint is parallel to it.
+    pub fn build_from_wu(n: &Vector3<f64>, u_hint: &Vector3<f64>) -> Onb {
+        let w = vunit(n);
+        let u = u_hint - w * w.dot(u_hint);
+        if u.norm() < 1e-9 {
+            return Onb::build_from_w(n);
+        }
+        let u = vunit(&u);
+        let v = w.cross(&u);
+        Onb { u, v, w }
+    }
+
+    pub fn local(&self, a: f64, b: f64, c: f64) -> Vector3<f64> {
+        a * self.u + b * self.v + c * self.w
+    }
+
+    pub fn local_vec(&self, a: &Vector3<f64>) -> Vector3<f64> {
+        self.local(a[0], a[1], a[2])
+    }
+
+    // Coordinates of a in this basis, the inverse of local_vec
+    pub fn world_to_local(&self, a: &Vector3<f64>) -> Vector3<f64> {
+        Vector3::new(a.dot(&self.u), a.dot(&self.v), a.dot(&self.w))
+    }
+}
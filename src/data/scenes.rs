@@ -1,13 +1,84 @@
 use nalgebra::Vector3;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::f64::consts::PI;
+use std::sync::Arc;
 
 // crate imports
+use crate::data::vlen;
 use crate::data::Color;
-use crate::data::{rand_float, rand_float01, vlen, vrandom_range};
-use crate::engine::{Hittable, HittableList, Sphere, XYRect, XZRect, YZRect};
-use crate::materials::{Dielectric, DiffuseLight, Lambertian, Metal};
-use crate::textures::{CheckerTexture, ImageTexture, NoiseTexture, Texture};
+use crate::engine::{
+    Background, BvhNode, DensityField, DensityGrid, DirectionalLight, GradientBackground,
+    Hittable, HittableList, Light, NoiseDensity, PointLight, Sphere, SpotLight, TextureBackground,
+    Volume, XYRect, XZRect, YZRect,
+};
+use crate::materials::{
+    BumpMap, Coated, Dielectric, DiffuseLight, DoubleHenyeyGreenstein, Hair, HenyeyGreenstein,
+    Lambertian, Medium, Metal, MixMaterial, NormalMap, PhaseFunction, Principled,
+    RoughDielectric, Subsurface, ThinFilm,
+};
+use crate::textures::{
+    CheckerTexture, ImageTexture, NoiseTexture, SolidColor, Texture, TileNormals,
+};
 
-pub fn random_world() -> HittableList {
+pub struct World {
+    pub objects: HittableList,
+    // Emitting objects (also present in objects) which integrators may sample directly
+    pub lights: HittableList,
+    // Point, spot and directional lights, which have no surface in objects
+    pub punctual_lights: Vec<Arc<dyn Light>>,
+    // Used instead of the background colour of the scene description unless that is set
+    pub background: Option<Arc<dyn Background>>,
+}
+
+impl World {
+    pub fn new(objects: HittableList) -> World {
+        World {
+            objects,
+            lights: HittableList::new(),
+            punctual_lights: Vec::new(),
+            background: None,
+        }
+    }
+}
+
+// Scene builders are looked up by name so that other processes can rebuild the same scene
+pub fn from_name(name: &str, seed: u64) -> Option<World> {
+    match name {
+        "random_world" => Some(random_world(seed)),
+        "random_world_sky" => Some(random_world_sky(seed)),
+        "two_spheres_checker" => Some(two_spheres_checker()),
+        "two_perlin_spheres" => Some(two_perlin_spheres()),
+        "two_perlin_spheres_marbled" => Some(two_perlin_spheres_marbled()),
+        "earth" => Some(earth()),
+        "simple_light" => Some(simple_light()),
+        "simple_light_punctual" => Some(simple_light_punctual()),
+        "cornell_box" => Some(cornell_box()),
+        "cornell_glass" => Some(cornell_glass()),
+        "cornell_metals" => Some(cornell_metals()),
+        "cornell_frosted" => Some(cornell_frosted()),
+        "cornell_principled" => Some(cornell_principled()),
+        "cornell_tinted" => Some(cornell_tinted()),
+        "cornell_dispersion" => Some(cornell_dispersion()),
+        "cornell_rust" => Some(cornell_rust()),
+        "cornell_bumpy" => Some(cornell_bumpy()),
+        "cornell_tiled" => Some(cornell_tiled()),
+        "cornell_coated" => Some(cornell_coated()),
+        "cornell_subsurface" => Some(cornell_subsurface()),
+        "cornell_iridescent" => Some(cornell_iridescent()),
+        "cornell_fibers" => Some(cornell_fibers()),
+        "cornell_smoke" => Some(cornell_smoke()),
+        "cornell_blackbody" => Some(cornell_blackbody()),
+        "cornell_spotlight" => Some(cornell_spotlight()),
+        "cornell_many_lights" => Some(cornell_many_lights(seed)),
+        "product_shot" => Some(product_shot()),
+        _ => None,
+    }
+}
+
+// Seeded so every process building this scene gets the same spheres
+pub fn random_world(seed: u64) -> World {
+    let mut rng = StdRng::seed_from_u64(seed);
     let mut world = HittableList::new();
 
     let checker = CheckerTexture::new(Color::new(0.5, 0.5, 0.5), Color::new(0.1, 0.1, 0.1)).share();
@@ -18,20 +89,25 @@ pub fn random_world() -> HittableList {
         for b in -11..11 {
             let a = a as f64;
             let b = b as f64;
-            let choose_material = rand_float01();
-            let center = Vector3::new(a + 0.9 * rand_float01(), 0.2, b + 0.9 * rand_float01());
+            let choose_material: f64 = rng.gen();
+            let center = Vector3::new(a + 0.9 * rng.gen::<f64>(), 0.2, b + 0.9 * rng.gen::<f64>());
 
             if vlen(&(center - Vector3::new(4.0, 0.2, 0.0))) <= 0.9 {
                 continue;
             }
             if choose_material < 0.8 {
-                let albedo = Color::random() * Color::random();
+                let albedo = Color::new(rng.gen(), rng.gen(), rng.gen())
+                    * Color::new(rng.gen(), rng.gen(), rng.gen());
                 let sphere_material = Lambertian::from_color(albedo).share();
                 world.add(Sphere::new(center, 0.2, sphere_material).share())
             } else if choose_material < 0.95 {
-                let albedo = Color::from_vector(&vrandom_range(0.5, 1.0));
-                let fuzz = rand_float(0.0, 0.5);
-                let sphere_material = Metal::new(albedo, fuzz).share();
+                let albedo = Color::new(
+                    rng.gen_range(0.5, 1.0),
+                    rng.gen_range(0.5, 1.0),
+                    rng.gen_range(0.5, 1.0),
+                );
+                let roughness = rng.gen_range(0.0, 0.5);
+                let sphere_material = Metal::new(albedo, roughness).share();
                 world.add(Sphere::new(center, 0.2, sphere_material).share())
             } else {
                 let sphere_material = Dielectric::new(1.5).share();
@@ -49,20 +125,28 @@ pub fn random_world() -> HittableList {
     let material3 = Metal::new(Color::new(0.7, 0.6, 0.5), 0.0).share();
     world.add(Sphere::new(Vector3::new(4.0, 1.0, 0.0), 1.0, material3).share());
 
+    return World::new(world);
+}
+
+// The random world lit by the white to blue sky alone, as in Ray Tracing in One Weekend
+pub fn random_world_sky(seed: u64) -> World {
+    let mut world = random_world(seed);
+    let sky = GradientBackground::new(Color::new(1.0, 1.0, 1.0), Color::new(0.5, 0.7, 1.0));
+    world.background = Some(sky.share());
     return world;
 }
 
-pub fn two_spheres_checker() -> HittableList {
+pub fn two_spheres_checker() -> World {
     let mut objects = HittableList::new();
 
     let checker = CheckerTexture::new(Color::new(0.2, 0.3, 0.1), Color::new(0.9, 0.9, 0.9)).share();
     let lamb = Lambertian::from_texture(checker).share();
     objects.add(Sphere::new(Vector3::new(0.0, -10.0, 0.0), 10.0, lamb.clone()).share());
     objects.add(Sphere::new(Vector3::new(0.0, 10.0, 0.0), 10.0, lamb.clone()).share());
-    return objects;
+    return World::new(objects);
 }
 
-pub fn two_perlin_spheres() -> HittableList {
+pub fn two_perlin_spheres() -> World {
     let mut objects = HittableList::new();
 
     let pertext = NoiseTexture::new(2.0).share();
@@ -70,10 +154,18 @@ pub fn two_perlin_spheres() -> HittableList {
     objects.add(Sphere::new(Vector3::new(0.0, -1000.0, 0.0), 1000.0, lamb.clone()).share());
     objects.add(Sphere::new(Vector3::new(0.0, 2.0, 0.0), 2.0, lamb.clone()).share());
 
-    return objects;
+    return World::new(objects);
+}
+
+// The perlin spheres lit by a marbled sky
+pub fn two_perlin_spheres_marbled() -> World {
+    let mut world = two_perlin_spheres();
+    let sky = TextureBackground::new(NoiseTexture::new(4.0).share());
+    world.background = Some(sky.share());
+    return world;
 }
 
-pub fn earth() -> HittableList {
+pub fn earth() -> World {
     let earth_texture = ImageTexture::new(
         "/home/mikew/Documents/Programming/rust/rust_tracer/res/earthmap.jpg".to_string(),
     )
@@ -82,11 +174,12 @@ pub fn earth() -> HittableList {
     let globe = Sphere::new(Vector3::new(0.0, 0.0, 0.0), 2.0, earth_surface).share();
     let mut world = HittableList::new();
     world.add(globe.clone());
-    return world;
+    return World::new(world);
 }
 
-pub fn simple_light() -> HittableList {
+pub fn simple_light() -> World {
     let mut objects = HittableList::new();
+    let mut lights = HittableList::new();
 
     let pertext = NoiseTexture::new(2.0).share();
     let lamb = Lambertian::from_texture(pertext.clone()).share();
@@ -94,13 +187,49 @@ pub fn simple_light() -> HittableList {
     let sun = DiffuseLight::from_color(Color::new(0.5, 0.5, 0.5)).share();
     objects.add(Sphere::new(Vector3::new(0.0, -1000.0, 0.0), 1000.0, lamb.clone()).share());
     objects.add(Sphere::new(Vector3::new(0.0, 2.0, 0.0), 2.0, lamb.clone()).share());
-    objects.add(Sphere::new(Vector3::new(0.0, 500.0, 0.0), 400.0, sun.clone()).share());
-    objects.add(XYRect::new(3.0, 5.0, 1.0, 3.0, -2.0, diff_light.clone()).share());
-    return objects;
+    let sun = Sphere::new(Vector3::new(0.0, 500.0, 0.0), 400.0, sun.clone()).share();
+    let lamp = XYRect::new(3.0, 5.0, 1.0, 3.0, -2.0, diff_light.clone()).share();
+    objects.add(sun.clone());
+    objects.add(lamp.clone());
+    lights.add(sun);
+    lights.add(lamp);
+    return World {
+        objects,
+        lights,
+        punctual_lights: Vec::new(),
+        background: None,
+    };
 }
 
-pub fn cornell_box() -> HittableList {
+// The same marbled ball lit only by lights without a surface: a faint sun, a warm spot
+// from above and behind and a bluish point light where the lamp was
+pub fn simple_light_punctual() -> World {
+    let pertext = NoiseTexture::new(2.0).share();
+    let lamb = Lambertian::from_texture(pertext).share();
     let mut objects = HittableList::new();
+    objects.add(Sphere::new(Vector3::new(0.0, -1000.0, 0.0), 1000.0, lamb.clone()).share());
+    objects.add(Sphere::new(Vector3::new(0.0, 2.0, 0.0), 2.0, lamb).share());
+
+    let sun = DirectionalLight::new(Vector3::new(-0.3, 1.0, 0.4), Color::new(0.1, 0.1, 0.1))
+        .with_angular_diameter(0.53);
+    let spot = SpotLight::new(
+        Vector3::new(0.0, 10.0, 5.0),
+        Vector3::new(0.0, -2.0, -1.0),
+        Color::new(90.0, 75.0, 60.0),
+        25.0,
+    )
+    .with_falloff(5.0);
+    let point = PointLight::new(Vector3::new(4.0, 2.0, -2.0), Color::new(6.0, 8.0, 12.0));
+    let mut world = World::new(objects);
+    world.punctual_lights.push(sun.share());
+    world.punctual_lights.push(spot.share());
+    world.punctual_lights.push(point.share());
+    return world;
+}
+
+pub fn cornell_box() -> World {
+    let mut objects = HittableList::new();
+    let mut lights = HittableList::new();
 
     let red = Lambertian::from_color(Color::new(0.65, 0.05, 0.05)).share();
     let white = Lambertian::from_color(Color::new(0.73, 0.73, 0.73)).share();
@@ -109,10 +238,396 @@ pub fn cornell_box() -> HittableList {
 
     objects.add(YZRect::new(0.0, 555.0, 0.0, 555.0, 555.0, green.clone()).share());
     objects.add(YZRect::new(0.0, 555.0, 0.0, 555.0, 0.0, red.clone()).share());
-    objects.add(XZRect::new(113.0, 443.0, 127.0, 432.0, 554.0, light.clone()).share());
+    let lamp = XZRect::new(113.0, 443.0, 127.0, 432.0, 554.0, light.clone()).share();
+    objects.add(lamp.clone());
+    lights.add(lamp);
+    objects.add(XZRect::new(0.0, 555.0, 0.0, 555.0, 555.0, white.clone()).share());
+    objects.add(XZRect::new(0.0, 555.0, 0.0, 555.0, 0.0, white.clone()).share());
+    objects.add(XYRect::new(0.0, 555.0, 0.0, 555.0, 555.0, white.clone()).share());
+
+    return World {
+        objects,
+        lights,
+        punctual_lights: Vec::new(),
+        background: None,
+    };
+}
+// Glass ball on the floor of the cornell box, throws a caustic below it
+pub fn cornell_glass() -> World {
+    let mut world = cornell_box();
+    let glass = Dielectric::new(1.5).share();
+    world
+        .objects
+        .add(Sphere::new(Vector3::new(278.0, 150.0, 250.0), 100.0, glass).share());
+    return world;
+}
+
+// Row of gold, copper, aluminium and silver balls, rougher from right to left.
+// The silver one has marbled roughness.
+pub fn cornell_metals() -> World {
+    let mut world = cornell_box();
+    let metals = vec![
+        Metal::gold(0.0),
+        Metal::copper(0.2),
+        Metal::aluminium(0.4),
+        Metal::silver(0.6).with_roughness_texture(NoiseTexture::new(0.05).share()),
+    ];
+    for (i, metal) in metals.into_iter().enumerate() {
+        let center = Vector3::new(453.0 - 117.0 * i as f64, 60.0, 250.0);
+        world.objects.add(Sphere::new(center, 55.0, metal.share()).share());
+    }
+    return world;
+}
+
+// A frosted glass ball and one etched in a marble pattern
+pub fn cornell_frosted() -> World {
+    let mut world = cornell_box();
+    let frosted = RoughDielectric::new(1.5, 0.3).share();
+    world
+        .objects
+        .add(Sphere::new(Vector3::new(190.0, 100.0, 250.0), 100.0, frosted).share());
+    let etched = RoughDielectric::new(1.5, 0.0)
+        .with_roughness_texture(NoiseTexture::new(0.05).share())
+        .share();
+    world
+        .objects
+        .add(Sphere::new(Vector3::new(400.0, 80.0, 180.0), 80.0, etched).share());
+    return world;
+}
+
+// Principled material presets: lacquered plastic with marbled clearcoat, gold,
+// velvet and green glass
+pub fn cornell_principled() -> World {
+    let mut world = cornell_box();
+    let scalar = |value: f64| SolidColor::scalar(value).share();
+    let materials = vec![
+        // Marble under a patchy, slightly blurry clearcoat
+        Principled::new(Color::new(0.9, 0.9, 0.9))
+            .with_base_color(NoiseTexture::new(0.1).share())
+            .with_specular(scalar(0.8))
+            .with_clearcoat(NoiseTexture::new(0.05).share())
+            .with_clearcoat_roughness(scalar(0.25)),
+        Principled::new(Color::new(1.0, 0.78, 0.34))
+            .with_metallic(scalar(1.0))
+            .with_roughness(scalar(0.3)),
+        // Velvet, its sheen picking up the blue
+        Principled::new(Color::new(0.2, 0.2, 0.6))
+            .with_roughness(scalar(0.9))
+            .with_sheen(scalar(1.0))
+            .with_sheen_tint(scalar(1.0)),
+        // Frosted fused silica
+        Principled::new(Color::new(0.6, 0.9, 0.6))
+            .with_transmission(scalar(1.0))
+            .with_roughness(scalar(0.1))
+            .with_ior(1.46),
+    ];
+    for (i, material) in materials.into_iter().enumerate() {
+        let center = Vector3::new(453.0 - 117.0 * i as f64, 60.0, 250.0);
+        world.objects.add(Sphere::new(center, 55.0, material.share()).share());
+    }
+    return world;
+}
+
+// Large and small ball of the same amber glass, the large one is noticeably darker
+pub fn cornell_tinted() -> World {
+    let mut world = cornell_box();
+    let absorption = Color::new(0.002, 0.008, 0.02);
+    let large = Dielectric::new(1.5).with_absorption(absorption).share();
+    world
+        .objects
+        .add(Sphere::new(Vector3::new(380.0, 120.0, 250.0), 120.0, large).share());
+    let small = Dielectric::new(1.5).with_absorption(absorption).share();
+    world
+        .objects
+        .add(Sphere::new(Vector3::new(150.0, 40.0, 200.0), 40.0, small).share());
+    return world;
+}
+
+// Diamond, crown glass and fused silica balls, their caustics split into colours when
+// rendered with a spectral integrator
+pub fn cornell_dispersion() -> World {
+    let mut world = cornell_box();
+    let diamond = Dielectric::diamond().share();
+    world
+        .objects
+        .add(Sphere::new(Vector3::new(380.0, 100.0, 250.0), 100.0, diamond).share());
+    let glass = Dielectric::crown_glass().share();
+    world
+        .objects
+        .add(Sphere::new(Vector3::new(150.0, 70.0, 200.0), 70.0, glass).share());
+    let silica = Dielectric::fused_silica().share();
+    world
+        .objects
+        .add(Sphere::new(Vector3::new(260.0, 45.0, 80.0), 45.0, silica).share());
+    return world;
+}
+
+// Polished steel ball with marbled rust patches
+pub fn cornell_rust() -> World {
+    let mut world = cornell_box();
+    let steel = Metal::new(Color::new(0.6, 0.6, 0.62), 0.15).share();
+    let rust = Lambertian::from_color(Color::new(0.45, 0.18, 0.07)).share();
+    let rusty = MixMaterial::new(steel, rust, NoiseTexture::new(0.05).share()).share();
+    world
+        .objects
+        .add(Sphere::new(Vector3::new(278.0, 150.0, 250.0), 150.0, rusty).share());
+    return world;
+}
+
+// Plaster and hammered copper balls, their bumps only faked by shading normals
+pub fn cornell_bumpy() -> World {
+    let mut world = cornell_box();
+    let plaster = Lambertian::from_color(Color::new(0.73, 0.73, 0.73)).share();
+    let plaster = BumpMap::new(plaster, NoiseTexture::new(0.1).share(), 4.0).share();
+    world
+        .objects
+        .add(Sphere::new(Vector3::new(160.0, 100.0, 250.0), 100.0, plaster).share());
+    let hammered = BumpMap::new(Metal::copper(0.2).share(), NoiseTexture::new(0.2).share(), 2.0);
+    world
+        .objects
+        .add(Sphere::new(Vector3::new(400.0, 100.0, 250.0), 100.0, hammered.share()).share());
+    return world;
+}
+
+// Tiled floor and a tiled copper ball, the tiles only exist in their normal maps
+pub fn cornell_tiled() -> World {
+    let mut world = cornell_box();
+    let tile = Lambertian::from_color(Color::new(0.73, 0.73, 0.73)).share();
+    let tiled = NormalMap::new(tile, TileNormals::new(8.0, 0.06).share()).share();
+    // Just above the plain floor
+    world
+        .objects
+        .add(XZRect::new(0.0, 555.0, 0.0, 555.0, 0.5, tiled).share());
+    let copper = NormalMap::new(Metal::copper(0.2).share(), TileNormals::new(12.0, 0.1).share());
+    world
+        .objects
+        .add(Sphere::new(Vector3::new(278.0, 120.0, 250.0), 120.0, copper.share()).share());
+    return world;
+}
+
+// Glossy red paint, amber varnish over white and copper under worn lacquer
+pub fn cornell_coated() -> World {
+    let mut world = cornell_box();
+    let paint = Lambertian::from_color(Color::new(0.7, 0.05, 0.05)).share();
+    let varnished = Lambertian::from_color(Color::new(0.73, 0.73, 0.73)).share();
+    let materials = vec![
+        Coated::new(paint, 1.5, 0.0),
+        Coated::new(varnished, 1.5, 0.1).with_absorption(Color::new(0.5, 1.5, 4.0), 0.2),
+        // Lacquer worn dull in patches
+        Coated::new(Metal::copper(0.4).share(), 1.5, 0.0)
+            .with_roughness_texture(NoiseTexture::new(0.05).share()),
+    ];
+    for (i, material) in materials.into_iter().enumerate() {
+        let center = Vector3::new(420.0 - 140.0 * i as f64, 65.0, 250.0);
+        world.objects.add(Sphere::new(center, 65.0, material.share()).share());
+    }
+    return world;
+}
+
+// Marble, candle wax and skin, with light wandering a few centimetres into each
+pub fn cornell_subsurface() -> World {
+    let mut world = cornell_box();
+    let materials = vec![
+        Subsurface::new(1.5, Color::new(0.99, 0.99, 0.98), Color::new(8.0, 7.0, 6.0)),
+        Subsurface::new(1.45, Color::new(0.99, 0.95, 0.8), Color::new(15.0, 10.0, 5.0))
+            .with_phase(DoubleHenyeyGreenstein::new(0.6, -0.3, 0.8).share()),
+        Subsurface::new(1.4, Color::new(0.95, 0.8, 0.7), Color::new(10.0, 4.0, 2.0))
+            .with_anisotropy(0.8),
+    ];
+    for (i, material) in materials.into_iter().enumerate() {
+        let center = Vector3::new(420.0 - 140.0 * i as f64, 65.0, 250.0);
+        world.objects.add(Sphere::new(center, 65.0, material.share()).share());
+    }
+    return world;
+}
+
+// Heat tinted titanium, a coated lens and a soap bubble with swirls of varying thickness
+pub fn cornell_iridescent() -> World {
+    let mut world = cornell_box();
+    let swirls = NoiseTexture::new(0.05).share();
+    let materials = vec![
+        Metal::new(Color::new(0.55, 0.55, 0.55), 0.15)
+            .with_thin_film(ThinFilm::new(250.0, 2.4))
+            .share(),
+        Dielectric::new(1.5)
+            .with_thin_film(ThinFilm::new(100.0, 1.38))
+            .share(),
+        Dielectric::new(1.0)
+            .with_thin_film(ThinFilm::new(900.0, 1.33).with_thickness_texture(swirls))
+            .share(),
+    ];
+    for (i, material) in materials.into_iter().enumerate() {
+        let center = Vector3::new(420.0 - 140.0 * i as f64, 65.0, 250.0);
+        world.objects.add(Sphere::new(center, 65.0, material).share());
+    }
+    return world;
+}
+
+// Aluminium brushed around the vertical axis, and balls shaded as brown hair, blond
+// hair and blue dyed nylon fibres combed the same way. Only a preview of the fibre
+// BSDFs: the balls are solid, light the lobes send through a fibre enters the ball
+// instead, so they look like neither fur nor single strands.
+pub fn cornell_fibers() -> World {
+    let mut world = cornell_box();
+    let materials = vec![
+        Metal::aluminium(0.0)
+            .with_anisotropic_roughness(0.05, 0.5)
+            .share(),
+        Hair::from_melanin(1.3, 0.0, 0.3, 0.3).share(),
+        Hair::from_melanin(0.3, 0.1, 0.25, 0.3).share(),
+        Hair::from_color(Color::new(0.01, 0.05, 0.4), 0.2, 0.3)
+            .with_scale_tilt(0.5)
+            .with_ior(1.53)
+            .share(),
+    ];
+    for (i, material) in materials.into_iter().enumerate() {
+        let center = Vector3::new(453.0 - 117.0 * i as f64, 55.0, 250.0);
+        world.objects.add(Sphere::new(center, 55.0, material).share());
+    }
+    return world;
+}
+
+// Forward scattering noise cloud above a smoke ring stored in a density grid
+pub fn cornell_smoke() -> World {
+    let mut world = cornell_box();
+    let cloud = Medium::new(Color::new(0.95, 0.95, 0.95))
+        .with_phase(HenyeyGreenstein::new(0.7).share())
+        .share();
+    let cloud = Volume::new(
+        Vector3::new(130.0, 220.0, 130.0),
+        Vector3::new(430.0, 460.0, 430.0),
+        NoiseDensity::new(3.0, 0.65).share(),
+        0.1,
+        cloud,
+    );
+
+    // Ring around the vertical axis, denser towards its core
+    let n = 48;
+    let mut values = Vec::with_capacity(n * n * n);
+    for k in 0..n {
+        for j in 0..n {
+            for i in 0..n {
+                let p = |i: usize| (i as f64 + 0.5) / n as f64 * 2.0 - 1.0;
+                let (x, y, z) = (p(i), p(j), p(k));
+                let core = ((x * x + z * z).sqrt() - 0.6).hypot(y * 2.0);
+                values.push((1.0 - core / 0.35).max(0.0));
+            }
+        }
+    }
+    let smoke = Medium::new(Color::new(0.6, 0.6, 0.65)).share();
+    let ring = Volume::new(
+        Vector3::new(180.0, 0.0, 150.0),
+        Vector3::new(380.0, 100.0, 350.0),
+        DensityGrid::new(n, n, n, values).share(),
+        0.08,
+        smoke,
+    );
+    world
+        .objects
+        .add(BvhNode::new(vec![cloud.share(), ring.share()]).share());
+    return world;
+}
+
+// Glowing balls: a candle flame and a halogen bulb by colour temperature, and a warm
+// white lamp by its luminous flux
+pub fn cornell_blackbody() -> World {
+    let mut world = cornell_box();
+    let radius = 40.0;
+    let area = 4.0 * PI * radius * radius;
+    let lamps = vec![
+        DiffuseLight::from_temperature(1900.0, 1.0),
+        DiffuseLight::from_temperature(3200.0, 1.0),
+        DiffuseLight::from_lumens(Color::new(1.0, 0.85, 0.7), 4.1e7, area),
+    ];
+    for (i, lamp) in lamps.into_iter().enumerate() {
+        let center = Vector3::new(420.0 - 140.0 * i as f64, radius, 250.0);
+        let ball = Sphere::new(center, radius, lamp.one_sided().share()).share();
+        world.objects.add(ball.clone());
+        world.lights.add(ball);
+    }
+    return world;
+}
+
+// Spot light from the ceiling picking out a ball, and a bluish point light up by the
+// red wall
+pub fn cornell_spotlight() -> World {
+    let mut world = cornell_box();
+    let white = Lambertian::from_color(Color::new(0.73, 0.73, 0.73)).share();
+    let ball = Vector3::new(278.0, 90.0, 250.0);
+    world.objects.add(Sphere::new(ball, 90.0, white).share());
+
+    let spot = SpotLight::new(
+        Vector3::new(278.0, 500.0, 250.0),
+        Vector3::new(0.0, -1.0, 0.0),
+        Color::new(1.6e6, 1.4e6, 1.2e6),
+        25.0,
+    )
+    .with_falloff(8.0);
+    let point = PointLight::new(Vector3::new(80.0, 400.0, 150.0), Color::new(1.0e5, 1.2e5, 1.5e5));
+    world.punctual_lights.push(spot.share());
+    world.punctual_lights.push(point.share());
+    return world;
+}
+
+// The cornell box lit by three hundred small lamps of every colour and strength instead
+// of the ceiling light, most of them dim. Picking one of them uniformly would mostly
+// find lamps that barely light the shading point.
+pub fn cornell_many_lights(seed: u64) -> World {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut objects = HittableList::new();
+    let mut lights = HittableList::new();
+
+    let red = Lambertian::from_color(Color::new(0.65, 0.05, 0.05)).share();
+    let white = Lambertian::from_color(Color::new(0.73, 0.73, 0.73)).share();
+    let green = Lambertian::from_color(Color::new(0.12, 0.45, 0.12)).share();
+    objects.add(YZRect::new(0.0, 555.0, 0.0, 555.0, 555.0, green).share());
+    objects.add(YZRect::new(0.0, 555.0, 0.0, 555.0, 0.0, red).share());
     objects.add(XZRect::new(0.0, 555.0, 0.0, 555.0, 555.0, white.clone()).share());
     objects.add(XZRect::new(0.0, 555.0, 0.0, 555.0, 0.0, white.clone()).share());
     objects.add(XYRect::new(0.0, 555.0, 0.0, 555.0, 555.0, white.clone()).share());
+    objects.add(Sphere::new(Vector3::new(278.0, 100.0, 300.0), 100.0, white).share());
+
+    let mut lamps: Vec<Arc<dyn Hittable>> = Vec::new();
+    for _ in 0..300 {
+        let center = Vector3::new(
+            rng.gen_range(20.0, 535.0),
+            rng.gen_range(220.0, 535.0),
+            rng.gen_range(20.0, 535.0),
+        );
+        let color = Color::new(rng.gen(), rng.gen(), rng.gen());
+        // Strengths spread over three orders of magnitude
+        let strength = 10f64.powf(rng.gen_range(-1.0, 2.0));
+        let lamp = DiffuseLight::from_color(color * strength).share();
+        lamps.push(Sphere::new(center, 4.0, lamp).share());
+    }
+    for lamp in lamps.iter() {
+        lights.add(lamp.clone());
+    }
+    objects.add(BvhNode::new(lamps).share());
 
-    return objects;
-}
\ No newline at end of file
+    return World {
+        objects,
+        lights,
+        punctual_lights: Vec::new(),
+        background: None,
+    };
+}
+
+// Gold, glass and plastic balls on an open floor without any lights, meant to be lit
+// by an environment map (environment=sky.hdr)
+pub fn product_shot() -> World {
+    let mut objects = HittableList::new();
+    let floor = Lambertian::from_color(Color::new(0.6, 0.6, 0.6)).share();
+    objects.add(XZRect::new(-5000.0, 5000.0, -5000.0, 5000.0, 0.0, floor).share());
+
+    let materials = vec![
+        Metal::gold(0.1).share(),
+        Dielectric::new(1.5).share(),
+        Principled::new(Color::new(0.1, 0.3, 0.8)).share(),
+    ];
+    for (i, material) in materials.into_iter().enumerate() {
+        let center = Vector3::new(478.0 - 200.0 * i as f64, 90.0, 200.0);
+        objects.add(Sphere::new(center, 90.0, material).share());
+    }
+    return World::new(objects);
+}
@@ -0,0 +1,160 @@
+use crate::data::Color;
+use std::cell::Cell;
+use std::sync::OnceLock;
+
+// Spectral rendering. While a wavelength is set for the current thread, every Color a
+// path carries is a single spectral sample at that wavelength, stored in all three
+// channels. RGB inputs (textures, emission, constants of materials) are turned into such
+// samples by upsample, the film gets the sample back as RGB from wavelength_to_rgb.
+
+pub const MIN_WAVELENGTH: f64 = 380.0;
+pub const MAX_WAVELENGTH: f64 = 780.0;
+
+thread_local! {
+    static WAVELENGTH: Cell<Option<f64>> = const { Cell::new(None) };
+}
+
+// Wavelength in nanometres the current thread traces at, None when rendering RGB
+pub fn wavelength() -> Option<f64> {
+    WAVELENGTH.with(|w| w.get())
+}
+
+// Sets the wavelength for the current thread, returns the previous one
+pub fn set_wavelength(wavelength: Option<f64>) -> Option<f64> {
+    WAVELENGTH.with(|w| w.replace(wavelength))
+}
+
+// Smits' basis spectra for RGB to spectrum conversion, 10 bins over 380-720nm
+const SMITS_WHITE: [f64; 10] = [1.0, 1.0, 0.9999, 0.9993, 0.9992, 0.9998, 1.0, 1.0, 1.0, 1.0];
+const SMITS_CYAN: [f64; 10] = [
+    0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0, 0.0, 0.0,
+];
+const SMITS_MAGENTA: [f64; 10] = [
+    1.0, 1.0, 0.9685, 0.2229, 0.0, 0.0458, 0.8369, 1.0, 1.0, 0.9959,
+];
+const SMITS_YELLOW: [f64; 10] = [
+    0.0001, 0.0, 0.1088, 0.6651, 1.0, 1.0, 0.9996, 0.9586, 0.9685, 0.9840,
+];
+const SMITS_RED: [f64; 10] = [
+    0.1012, 0.0515, 0.0, 0.0, 0.0, 0.0, 0.8325, 1.0149, 1.0149, 1.0149,
+];
+const SMITS_GREEN: [f64; 10] = [
+    0.0, 0.0, 0.0273, 0.7937, 1.0, 0.9418, 0.1719, 0.0, 0.0, 0.0025,
+];
+const SMITS_BLUE: [f64; 10] = [
+    1.0, 1.0, 0.8916, 0.3323, 0.0, 0.0, 0.0003, 0.0369, 0.0483, 0.0496,
+];
+
+// Value of the smooth spectrum matching rgb at wavelength (Smits 1999)
+pub fn rgb_to_spectrum(rgb: &Color, wavelength: f64) -> f64 {
+    let bin = (((wavelength - 380.0) / 34.0) as usize).min(9);
+    let (r, g, b) = (rgb.r(), rgb.g(), rgb.b());
+    if r <= g && r <= b {
+        let rest = match g <= b {
+            true => (g - r) * SMITS_CYAN[bin] + (b - g) * SMITS_BLUE[bin],
+            false => (b - r) * SMITS_CYAN[bin] + (g - b) * SMITS_GREEN[bin],
+        };
+        r * SMITS_WHITE[bin] + rest
+    } else if g <= r && g <= b {
+        let rest = match r <= b {
+            true => (r - g) * SMITS_MAGENTA[bin] + (b - r) * SMITS_BLUE[bin],
+            false => (b - g) * SMITS_MAGENTA[bin] + (r - b) * SMITS_RED[bin],
+        };
+        g * SMITS_WHITE[bin] + rest
+    } else {
+        let rest = match r <= g {
+            true => (r - b) * SMITS_YELLOW[bin] + (g - r) * SMITS_GREEN[bin],
+            false => (g - b) * SMITS_YELLOW[bin] + (r - g) * SMITS_RED[bin],
+        };
+        b * SMITS_WHITE[bin] + rest
+    }
+}
+
+// rgb as seen at the current wavelength, unchanged when rendering RGB
+pub fn upsample(rgb: Color) -> Color {
+    match wavelength() {
+        Some(wavelength) => {
+            let value = rgb_to_spectrum(&rgb, wavelength);
+            Color::new(value, value, value)
+        }
+        None => rgb,
+    }
+}
+
+// Piecewise gaussian fit of the CIE 1931 colour matching functions (Wyman et al. 2013)
+fn cie_xyz(wavelength: f64) -> (f64, f64, f64) {
+    let g = |mu: f64, sigma_low: f64, sigma_high: f64| {
+        let sigma = if wavelength < mu {
+            sigma_low
+        } else {
+            sigma_high
+        };
+        let t = (wavelength - mu) / sigma;
+        (-0.5 * t * t).exp()
+    };
+    let x =
+        1.056 * g(599.8, 37.9, 31.0) + 0.362 * g(442.0, 16.0, 26.7) - 0.065 * g(501.1, 20.4, 26.2);
+    let y = 0.821 * g(568.8, 46.9, 40.5) + 0.286 * g(530.9, 16.3, 31.1);
+    let z = 1.217 * g(437.0, 11.8, 36.0) + 0.681 * g(459.0, 26.0, 13.8);
+    (x, y, z)
+}
+
+// Linear sRGB response to a unit of light at wavelength
+fn linear_srgb(wavelength: f64) -> Color {
+    let (x, y, z) = cie_xyz(wavelength);
+    xyz_to_rgb(x, y, z)
+}
+
+// CIE XYZ to linear sRGB with a D65 white
+pub fn xyz_to_rgb(x: f64, y: f64, z: f64) -> Color {
+    Color::new(
+        3.2406 * x - 1.5372 * y - 0.4986 * z,
+        -0.9689 * x + 1.8758 * y + 0.0415 * z,
+        0.0557 * x - 0.2040 * y + 1.0570 * z,
+    )
+}
+
+// RGB of light at a wavelength sampled uniformly from the visible range, scaled so
+// that on average a flat spectrum comes out white
+pub fn wavelength_to_rgb(wavelength: f64) -> Color {
+    static WHITE: OnceLock<Color> = OnceLock::new();
+    let white = WHITE.get_or_init(|| {
+        let steps = (MAX_WAVELENGTH - MIN_WAVELENGTH) as usize;
+        let sum: Color = (0..steps)
+            .map(|i| linear_srgb(MIN_WAVELENGTH + i as f64 + 0.5))
+            .sum();
+        sum / steps as f64
+    });
+    let rgb = linear_srgb(wavelength);
+    Color::new(
+        rgb.r() / white.r(),
+        rgb.g() / white.g(),
+        rgb.b() / white.b(),
+    )
+}
+
+// Wavelengths spectra are integrated over when turned into RGB
+const RGB_SAMPLES: usize = 40;
+
+// RGB of a spectrum given as a function of wavelength, on the same scale as the
+// spectral samples going through wavelength_to_rgb
+pub fn spectrum_to_rgb<F: Fn(f64) -> f64>(spectrum: F) -> Color {
+    let step = (MAX_WAVELENGTH - MIN_WAVELENGTH) / RGB_SAMPLES as f64;
+    let sum: Color = (0..RGB_SAMPLES)
+        .map(|i| {
+            let wavelength = MIN_WAVELENGTH + (i as f64 + 0.5) * step;
+            wavelength_to_rgb(wavelength) * spectrum(wavelength)
+        })
+        .sum();
+    sum / RGB_SAMPLES as f64
+}
+
+// Spectral radiance of a black body at temperature in Kelvin (Planck's law), relative
+// to its peak
+pub fn blackbody(wavelength: f64, temperature: f64) -> f64 {
+    const C2: f64 = 1.4387769e7; // hc / k in nm K
+    let planck = |wavelength: f64| 1.0 / (wavelength.powi(5) * ((C2 / (wavelength * temperature)).exp() - 1.0));
+    // Wien's displacement law
+    let peak = 2.8977721e6 / temperature;
+    planck(wavelength) / planck(peak)
+}
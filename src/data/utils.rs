@@ -1,15 +1,67 @@
+use rand::distributions::Open01;
+use rand::rngs::StdRng;
+use rand::{thread_rng, Rng, SeedableRng};
+use std::cell::RefCell;
+use std::rc::Rc;
 
-use rand::distributions::{Open01, Uniform};
-use rand::{thread_rng, Rng};
+// Where rand_float01 gets its numbers from. All randomness used while rendering goes
+// through rand_float01, so replacing the source (e.g. with the primary sample vector of
+// Metropolis light transport) controls every decision a path makes.
+pub trait SampleSource {
+    fn next(&mut self) -> f64;
+}
+
+// Reproducible numbers from a seed
+pub struct SeededSource {
+    rng: StdRng,
+}
+
+impl SeededSource {
+    pub fn new(seed: u64) -> SeededSource {
+        SeededSource {
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+}
+
+impl SampleSource for SeededSource {
+    fn next(&mut self) -> f64 {
+        self.rng.sample(Open01)
+    }
+}
+
+// Seed of one of many independent streams derived from seed (splitmix64 finalizer)
+pub fn stream_seed(seed: u64, stream: u64) -> u64 {
+    let mut z = seed.wrapping_add(stream.wrapping_add(1).wrapping_mul(0x9e37_79b9_7f4a_7c15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+thread_local! {
+    static SAMPLE_SOURCE: RefCell<Option<Rc<RefCell<dyn SampleSource>>>> = RefCell::new(None);
+}
+
+// Installs source for the current thread (None restores thread_rng), returns the previous one
+pub fn set_sample_source(
+    source: Option<Rc<RefCell<dyn SampleSource>>>,
+) -> Option<Rc<RefCell<dyn SampleSource>>> {
+    SAMPLE_SOURCE.with(|s| s.replace(source))
+}
 
 pub fn rand_float(from: f64, to: f64) -> f64 {
-    thread_rng().sample(Uniform::from(from..to))
+    from + (to - from) * rand_float01()
 }
 
 pub fn rand_float01() -> f64 {
-    thread_rng().sample(Open01)
+    let sample =
+        SAMPLE_SOURCE.with(|s| s.borrow().as_ref().map(|source| source.borrow_mut().next()));
+    match sample {
+        Some(sample) => sample,
+        None => thread_rng().sample(Open01),
+    }
 }
 
 pub fn rand_int(from: usize, to: usize) -> usize {
-    thread_rng().sample(Uniform::from((from as f64)..(to as f64))).round() as usize
-}
\ No newline at end of file
+    rand_float(from as f64, to as f64).round() as usize
+}
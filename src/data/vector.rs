@@ -111,13 +111,6 @@ pub fn vrandom() -> Vector3<f64> {
     Vector3::new(rand_float01(), rand_float01(), rand_float01())
 }
 
-pub fn vrandom_range(min: f64, max: f64) -> Vector3<f64> {
-    Vector3::new(
-        rand_float(min, max),
-        rand_float(min, max),
-        rand_float(min, max),
-    )
-}
 pub fn vrandom_in_unit_sphere() -> Vector3<f64> {
     let a: f64 = rand_float(0.0, 2.0 * PI);
     let z: f64 = rand_float(-1.0, 1.0);
@@ -146,6 +139,28 @@ pub fn vrandom_in_unit_disk() -> Vector3<f64> {
     }
 }
 
+// Direction about +z with pdf cos(theta) / PI
+pub fn vrandom_cosine_direction() -> Vector3<f64> {
+    let r1 = rand_float01();
+    let r2 = rand_float01();
+    let z = (1.0 - r2).sqrt();
+    let phi = 2.0 * PI * r1;
+    let x = phi.cos() * r2.sqrt();
+    let y = phi.sin() * r2.sqrt();
+    Vector3::new(x, y, z)
+}
+
+// Direction about +z towards a sphere seen under cos(theta) <= cos_theta_max
+pub fn vrandom_to_sphere(radius: f64, distance_squared: f64) -> Vector3<f64> {
+    let r1 = rand_float01();
+    let r2 = rand_float01();
+    let z = 1.0 + r2 * ((1.0 - radius * radius / distance_squared).sqrt() - 1.0);
+    let phi = 2.0 * PI * r1;
+    let x = phi.cos() * (1.0 - z * z).sqrt();
+    let y = phi.sin() * (1.0 - z * z).sqrt();
+    Vector3::new(x, y, z)
+}
+
 pub fn vunit(v: &Vector3<f64>) -> Vector3<f64>{
     Unit::new_normalize(*v).into_inner()
 }
@@ -155,11 +170,11 @@ pub fn vlen(v: &Vector3<f64>) -> f64 {
 }
 
 pub fn reflect(v: &Vector3<f64>, n: &Vector3<f64>) -> Vector3<f64> {
-    v - 2.0 * (v.dot(&n)) * n
+    v - 2.0 * (v.dot(n)) * n
 }
 
 pub fn refract(uv: &Vector3<f64>, n: &Vector3<f64>, etai_over_etat: f64) -> Vector3<f64> {
-    let cos_theta = (-1.0 * uv).dot(&n);
+    let cos_theta = (-1.0 * uv).dot(n);
     let r_out_parallel: Vector3<f64> = etai_over_etat * (uv + cos_theta * n);
     let r_out_perp: Vector3<f64> = -(1.0 - r_out_parallel.dot(&r_out_parallel)).sqrt() * n;
     return r_out_parallel + r_out_perp;
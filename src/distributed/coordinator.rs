@@ -0,0 +1,310 @@
+use indicatif::{ProgressBar, ProgressStyle};
+
+// crate imports
+use crate::data::{Color, SceneDescription};
+use crate::distributed::protocol::{read_message, write_message, Message};
+use crate::engine::ProducerRange;
+
+// std imports
+use std::collections::VecDeque;
+use std::io::{self, BufReader};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering::Relaxed};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+// How long a worker may take over a tile before it counts as lost and the tile is
+// handed to someone else
+const TILE_TIMEOUT: Duration = Duration::from_secs(600);
+const WRITE_TIMEOUT: Duration = Duration::from_secs(30);
+// How long the coordinator waits without any tile coming in, with no worker connected
+// or all of them gone, before giving up
+const IDLE_TIMEOUT: Duration = Duration::from_secs(1800);
+
+type TileQueue = Arc<Mutex<VecDeque<(u64, ProducerRange)>>>;
+
+struct TileResult {
+    range: ProducerRange,
+    pixels: Vec<Color>,
+}
+
+fn split_tiles(width: u64, height: u64, tile_size: u64) -> VecDeque<(u64, ProducerRange)> {
+    let mut tiles = VecDeque::new();
+    let mut id = 0;
+    for from_y in (0..height).step_by(tile_size as usize) {
+        for from_x in (0..width).step_by(tile_size as usize) {
+            let range = ProducerRange {
+                from_x,
+                to_x: (from_x + tile_size).min(width),
+                from_y,
+                to_y: (from_y + tile_size).min(height),
+            };
+            tiles.push_back((id, range));
+            id += 1;
+        }
+    }
+    tiles
+}
+
+// Hands out tiles to one worker. A tile that is in flight when the connection
+// fails or the worker doesn't answer in time goes back to the queue so another (or
+// the reconnected) worker picks it up.
+fn handle_worker(
+    stream: TcpStream,
+    description: SceneDescription,
+    queue: TileQueue,
+    tx: mpsc::Sender<TileResult>,
+    finished: Arc<AtomicBool>,
+    timeout: Duration,
+) -> io::Result<()> {
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+    write_message(&mut writer, &Message::Scene(Box::new(description)))?;
+
+    loop {
+        if finished.load(Relaxed) {
+            return write_message(&mut writer, &Message::Done);
+        }
+        let tile = queue.lock().unwrap().pop_front();
+        let (id, range) = match tile {
+            Some(tile) => tile,
+            None => {
+                // Other workers still hold tiles which may come back to the queue
+                thread::sleep(Duration::from_millis(50));
+                continue;
+            }
+        };
+
+        let expected = ((range.to_x - range.from_x) * (range.to_y - range.from_y)) as usize;
+        let result = write_message(&mut writer, &Message::Tile { id, range })
+            .and_then(|_| read_message(&mut reader, expected));
+        match result {
+            Ok(Message::Result { id: rid, pixels }) if rid == id => {
+                tx.send(TileResult { range, pixels }).unwrap();
+            }
+            Ok(_) => {
+                queue.lock().unwrap().push_back((id, range));
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "unexpected reply to tile",
+                ));
+            }
+            Err(e) => {
+                queue.lock().unwrap().push_back((id, range));
+                return Err(e);
+            }
+        }
+    }
+}
+
+fn accept_workers(
+    listener: TcpListener,
+    description: SceneDescription,
+    queue: TileQueue,
+    tx: mpsc::Sender<TileResult>,
+    finished: Arc<AtomicBool>,
+    timeout: Duration,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        listener.set_nonblocking(true).unwrap();
+        let mut handlers = Vec::new();
+        while !finished.load(Relaxed) {
+            match listener.accept() {
+                Ok((stream, peer)) => {
+                    println!("Worker connected: {}", peer);
+                    stream.set_nonblocking(false).unwrap();
+                    let description = description.clone();
+                    let queue = queue.clone();
+                    let tx = tx.clone();
+                    let finished = finished.clone();
+                    handlers.push(thread::spawn(move || {
+                        let result =
+                            handle_worker(stream, description, queue, tx, finished, timeout);
+                        if let Err(e) = result {
+                            println!("Worker {} dropped: {}", peer, e);
+                        }
+                    }));
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    thread::sleep(Duration::from_millis(50));
+                }
+                Err(e) => println!("Failed to accept worker: {}", e),
+            }
+        }
+        // Let every connected worker receive DONE before shutting down
+        for handler in handlers {
+            handler.join().unwrap();
+        }
+    })
+}
+
+pub fn run_coordinator(description: SceneDescription, addr: &str, tile_size: u64, path: String) {
+    let width = description.image_width;
+    let height = description.image_height;
+    let listener = TcpListener::bind(addr).unwrap();
+    println!("Waiting for workers on {}", addr);
+    let hdr = match coordinate(listener, description, tile_size, TILE_TIMEOUT, IDLE_TIMEOUT) {
+        Ok(hdr) => hdr,
+        Err(e) => {
+            println!("Giving up: {}", e);
+            return;
+        }
+    };
+
+    let mut imgbuf = image::ImageBuffer::new(width as u32, height as u32);
+    for (x, y, pixel) in imgbuf.enumerate_pixels_mut() {
+        *pixel = hdr[(y as u64 * width + x as u64) as usize]
+            .gamma_2()
+            .to_rgba();
+    }
+    println!("saving...");
+    imgbuf.save(path).unwrap();
+}
+
+// Renders the description on the workers connecting to listener, returns the HDR
+// image row by row. Fails when no tile comes in for idle_timeout.
+fn coordinate(
+    listener: TcpListener,
+    description: SceneDescription,
+    tile_size: u64,
+    timeout: Duration,
+    idle_timeout: Duration,
+) -> Result<Vec<Color>, String> {
+    let width = description.image_width;
+    let height = description.image_height;
+
+    let queue = Arc::new(Mutex::new(split_tiles(width, height, tile_size)));
+    let n_tiles = queue.lock().unwrap().len();
+    let finished = Arc::new(AtomicBool::new(false));
+    let (tx, rx) = mpsc::channel();
+    let accept_handle = accept_workers(listener, description, queue, tx, finished.clone(), timeout);
+
+    let pb = ProgressBar::new(width * height);
+    pb.set_style(
+        ProgressStyle::default_bar()
+            .template("{spinner:.green} [{elapsed}] [{wide_bar}] {pos}/{len} ({eta})"),
+    );
+
+    // Merge HDR tiles as they come in, a tile rendered twice is only counted once
+    let mut hdr = vec![Color::new(0.0, 0.0, 0.0); (width * height) as usize];
+    let mut received = vec![false; (width * height) as usize];
+    let mut tiles_done = 0;
+    while tiles_done < n_tiles {
+        let result = match rx.recv_timeout(idle_timeout) {
+            Ok(result) => result,
+            Err(_) => {
+                // Connected workers stop at their next tile, hung ones are left behind
+                finished.store(true, Relaxed);
+                pb.abandon();
+                return Err(format!(
+                    "no tile finished in {:?}, {} of {} tiles outstanding",
+                    idle_timeout,
+                    n_tiles - tiles_done,
+                    n_tiles
+                ));
+            }
+        };
+        let range = result.range;
+        let index = (range.from_y * width + range.from_x) as usize;
+        if received[index] {
+            continue;
+        }
+        received[index] = true;
+
+        let mut pixels = result.pixels.iter();
+        for y in range.from_y..range.to_y {
+            for x in range.from_x..range.to_x {
+                hdr[(y * width + x) as usize] = *pixels.next().unwrap();
+            }
+        }
+        pb.inc((range.to_x - range.from_x) * (range.to_y - range.from_y));
+        tiles_done += 1;
+    }
+    finished.store(true, Relaxed);
+    pb.finish_with_message("Your image is complete, sir! Enjoy!");
+
+    accept_handle.join().unwrap();
+    Ok(hdr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::distributed::run_worker;
+    use crate::distributed::worker::render_tile;
+
+    fn small_scene() -> SceneDescription {
+        let mut description = SceneDescription::cornell_box();
+        description.image_width = 32;
+        description.image_height = 24;
+        description.samples_per_pixel = 4;
+        description.seed = 7;
+        description
+    }
+
+    // What the coordinator got against rendering the whole image here, as sent over
+    // the wire
+    fn assert_matches_local(description: &SceneDescription, hdr: &[Color]) {
+        let scene = Arc::new(description.build().unwrap());
+        let range = ProducerRange {
+            from_x: 0,
+            to_x: description.image_width,
+            from_y: 0,
+            to_y: description.image_height,
+        };
+        let local = render_tile(scene, range, 3);
+        assert_eq!(hdr.len(), local.len());
+        for (remote, local) in hdr.iter().zip(local.iter()) {
+            let wire = |c: f64| c as f32 as f64;
+            assert_eq!(remote.r(), wire(local.r()));
+            assert_eq!(remote.g(), wire(local.g()));
+            assert_eq!(remote.b(), wire(local.b()));
+        }
+    }
+
+    #[test]
+    fn workers_match_local_render() {
+        let description = small_scene();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        for _ in 0..2 {
+            let addr = addr.clone();
+            thread::spawn(move || run_worker(&addr, 2));
+        }
+        let hdr = coordinate(listener, description.clone(), 8, TILE_TIMEOUT, IDLE_TIMEOUT);
+        assert_matches_local(&description, &hdr.unwrap());
+    }
+
+    #[test]
+    fn tile_of_silent_worker_is_handed_out_again() {
+        let description = small_scene();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+
+        // Takes the first tile and never answers
+        let silent = TcpStream::connect(&addr).unwrap();
+        let worker_addr = addr.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(300));
+            run_worker(&worker_addr, 2)
+        });
+        let timeout = Duration::from_secs(1);
+        let hdr = coordinate(listener, description.clone(), 8, timeout, IDLE_TIMEOUT);
+        drop(silent);
+        assert_matches_local(&description, &hdr.unwrap());
+    }
+
+    #[test]
+    fn coordinator_without_workers_gives_up() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let idle = Duration::from_millis(200);
+        let hdr = coordinate(listener, small_scene(), 8, TILE_TIMEOUT, idle);
+        assert_eq!(
+            hdr.err().unwrap(),
+            "no tile finished in 200ms, 12 of 12 tiles outstanding"
+        );
+    }
+}
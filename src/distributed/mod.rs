@@ -0,0 +1,5 @@
+pub mod coordinator;
+pub mod protocol;
+pub mod worker;
+pub use crate::distributed::coordinator::run_coordinator;
+pub use crate::distributed::worker::run_worker;
@@ -0,0 +1,150 @@
+use crate::data::{Color, SceneDescription};
+use crate::engine::ProducerRange;
+
+// std imports
+use std::io::{self, BufRead, Write};
+
+// Line based protocol. Every message is a single text line, tile results are
+// followed by their pixels as little endian f32 triples in row-major order.
+//
+// coordinator -> worker:   SCENE <description>
+//                          TILE <id> <from_x> <to_x> <from_y> <to_y>
+//                          DONE
+// worker -> coordinator:   RESULT <id> <n_pixels>
+pub enum Message {
+    Scene(Box<SceneDescription>),
+    Tile { id: u64, range: ProducerRange },
+    Result { id: u64, pixels: Vec<Color> },
+    Done,
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn parse_u64(token: Option<&str>) -> io::Result<u64> {
+    match token.map(|t| t.parse::<u64>()) {
+        Some(Ok(value)) => Ok(value),
+        _ => Err(invalid("expected integer in message".to_string())),
+    }
+}
+
+pub fn write_message<W: Write>(w: &mut W, message: &Message) -> io::Result<()> {
+    match message {
+        Message::Scene(description) => writeln!(w, "SCENE {}", description.serialize())?,
+        Message::Tile { id, range } => writeln!(
+            w,
+            "TILE {} {} {} {} {}",
+            id, range.from_x, range.to_x, range.from_y, range.to_y
+        )?,
+        Message::Result { id, pixels } => {
+            writeln!(w, "RESULT {} {}", id, pixels.len())?;
+            let mut payload = Vec::with_capacity(pixels.len() * 12);
+            for pixel in pixels {
+                payload.extend_from_slice(&(pixel.r() as f32).to_le_bytes());
+                payload.extend_from_slice(&(pixel.g() as f32).to_le_bytes());
+                payload.extend_from_slice(&(pixel.b() as f32).to_le_bytes());
+            }
+            w.write_all(&payload)?;
+        }
+        Message::Done => writeln!(w, "DONE")?,
+    }
+    w.flush()
+}
+
+// result_pixels is the size of the tile a RESULT is expected for, a RESULT of any
+// other size is rejected before its pixels are read
+pub fn read_message<R: BufRead>(r: &mut R, result_pixels: usize) -> io::Result<Message> {
+    let mut line = String::new();
+    if r.read_line(&mut line)? == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "connection closed",
+        ));
+    }
+    let line = line.trim_end();
+    let (kind, rest) = line.split_once(' ').unwrap_or((line, ""));
+    let mut tokens = rest.split_whitespace();
+
+    match kind {
+        "SCENE" => SceneDescription::deserialize(rest)
+            .map(|description| Message::Scene(Box::new(description)))
+            .map_err(invalid),
+        "TILE" => {
+            let id = parse_u64(tokens.next())?;
+            let range = ProducerRange {
+                from_x: parse_u64(tokens.next())?,
+                to_x: parse_u64(tokens.next())?,
+                from_y: parse_u64(tokens.next())?,
+                to_y: parse_u64(tokens.next())?,
+            };
+            Ok(Message::Tile { id, range })
+        }
+        "RESULT" => {
+            let id = parse_u64(tokens.next())?;
+            let n_pixels = parse_u64(tokens.next())?;
+            if n_pixels != result_pixels as u64 {
+                return Err(invalid(format!(
+                    "result of {} pixels for a tile of {}",
+                    n_pixels, result_pixels
+                )));
+            }
+            let size = result_pixels
+                .checked_mul(12)
+                .ok_or_else(|| invalid("result too large".to_string()))?;
+            let mut payload = vec![0u8; size];
+            r.read_exact(&mut payload)?;
+            let pixels = payload
+                .chunks_exact(12)
+                .map(|c| {
+                    let channel = |i: usize| {
+                        f32::from_le_bytes([c[i], c[i + 1], c[i + 2], c[i + 3]]) as f64
+                    };
+                    Color::new(channel(0), channel(4), channel(8))
+                })
+                .collect();
+            Ok(Message::Result { id, pixels })
+        }
+        "DONE" => Ok(Message::Done),
+        _ => Err(invalid(format!("unknown message '{}'", kind))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn result_bytes(pixels: Vec<Color>) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write_message(&mut bytes, &Message::Result { id: 3, pixels }).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn result_round_trip() {
+        let pixels = vec![Color::new(0.5, 1.0, 2.0), Color::new(0.0, 0.25, 8.0)];
+        let bytes = result_bytes(pixels.clone());
+        match read_message(&mut Cursor::new(bytes), 2).unwrap() {
+            Message::Result { id, pixels: read } => {
+                assert_eq!(id, 3);
+                assert_eq!(read, pixels);
+            }
+            _ => panic!("expected a result"),
+        }
+    }
+
+    #[test]
+    fn result_of_wrong_size_is_rejected() {
+        let bytes = result_bytes(vec![Color::new(1.0, 1.0, 1.0); 4]);
+        let error = read_message(&mut Cursor::new(bytes), 2).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        // A header claiming more than fits in memory is turned down without allocating
+        let header = format!("RESULT 3 {}\n", u64::MAX);
+        let error = read_message(&mut Cursor::new(header.into_bytes()), 2)
+            .err()
+            .unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
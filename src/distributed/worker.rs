@@ -0,0 +1,107 @@
+use crate::data::Color;
+use crate::distributed::protocol::{read_message, write_message, Message};
+use crate::engine::{pixel_color, ProducerRange, Scene};
+
+// std imports
+use std::io::{self, BufReader};
+use std::net::TcpStream;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+const RETRY_DELAY: Duration = Duration::from_secs(2);
+const MAX_RETRIES: u32 = 30;
+
+// Renders a tile with n_threads threads, each taking a band of rows.
+// Pixels are returned in row-major order.
+pub fn render_tile(scene: Arc<Scene>, range: ProducerRange, n_threads: u64) -> Vec<Color> {
+    let rows = range.to_y - range.from_y;
+    let band = rows / n_threads.max(1) + 1;
+
+    let mut handles = Vec::new();
+    let mut from_y = range.from_y;
+    while from_y < range.to_y {
+        let to_y = (from_y + band).min(range.to_y);
+        let scene = scene.clone();
+        handles.push(thread::spawn(move || {
+            let mut pixels = Vec::new();
+            for y in from_y..to_y {
+                for x in range.from_x..range.to_x {
+                    pixels.push(pixel_color(x as f64, y as f64, &scene));
+                }
+            }
+            pixels
+        }));
+        from_y = to_y;
+    }
+
+    let mut pixels = Vec::new();
+    for handle in handles {
+        pixels.extend(handle.join().unwrap());
+    }
+    pixels
+}
+
+// Serves tiles on a single connection until the coordinator says it is done
+fn serve(stream: TcpStream, n_threads: u64) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+
+    // Workers are never sent results, so no size of one is expected
+    let scene = match read_message(&mut reader, 0)? {
+        Message::Scene(description) => description
+            .build()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "expected scene description",
+            ))
+        }
+    };
+    let scene = Arc::new(scene);
+
+    loop {
+        match read_message(&mut reader, 0)? {
+            Message::Tile { id, range } => {
+                let pixels = render_tile(scene.clone(), range, n_threads);
+                write_message(&mut writer, &Message::Result { id, pixels })?;
+            }
+            Message::Done => return Ok(()),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "unexpected message",
+                ))
+            }
+        }
+    }
+}
+
+pub fn run_worker(addr: &str, n_threads: u64) {
+    let mut retries = 0;
+    loop {
+        match TcpStream::connect(addr) {
+            Ok(stream) => {
+                println!("Connected to coordinator at {}", addr);
+                retries = 0;
+                match serve(stream, n_threads) {
+                    Ok(_) => {
+                        println!("Coordinator finished, exiting");
+                        return;
+                    }
+                    Err(e) => println!("Lost connection to coordinator: {}", e),
+                }
+            }
+            Err(e) => {
+                retries += 1;
+                if retries > MAX_RETRIES {
+                    println!("Giving up on coordinator at {}: {}", addr, e);
+                    return;
+                }
+                println!("Unable to connect to {} ({}), retrying...", addr, e);
+            }
+        }
+        thread::sleep(RETRY_DELAY);
+    }
+}
@@ -0,0 +1,95 @@
+use crate::data::{upsample, vunit, Color};
+use crate::textures::Texture;
+use nalgebra::Vector3;
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+// What rays leaving the scene see
+pub trait Background: Send + Sync {
+    // Radiance arriving from direction
+    fn radiance(&self, direction: &Vector3<f64>) -> Color;
+    fn share(self) -> Arc<dyn Background>;
+
+    // Backgrounds that can be sampled as lights give a unit direction and its solid
+    // angle density, the others are only found by rays escaping
+    fn sample(&self) -> Option<(Vector3<f64>, f64)> {
+        None
+    }
+
+    fn pdf(&self, _direction: &Vector3<f64>) -> f64 {
+        0.0
+    }
+}
+
+pub struct ConstantBackground {
+    color: Color,
+}
+
+impl ConstantBackground {
+    pub fn new(color: Color) -> ConstantBackground {
+        ConstantBackground { color }
+    }
+}
+
+impl Background for ConstantBackground {
+    fn radiance(&self, _direction: &Vector3<f64>) -> Color {
+        upsample(self.color)
+    }
+
+    fn share(self) -> Arc<dyn Background> {
+        Arc::new(self)
+    }
+}
+
+// Blend from bottom looking straight down to top looking straight up, like the white
+// to blue sky of Ray Tracing in One Weekend
+pub struct GradientBackground {
+    bottom: Color,
+    top: Color,
+}
+
+impl GradientBackground {
+    pub fn new(bottom: Color, top: Color) -> GradientBackground {
+        GradientBackground { bottom, top }
+    }
+}
+
+impl Background for GradientBackground {
+    fn radiance(&self, direction: &Vector3<f64>) -> Color {
+        let t = 0.5 * (vunit(direction)[1] + 1.0);
+        upsample(self.bottom * (1.0 - t) + self.top * t)
+    }
+
+    fn share(self) -> Arc<dyn Background> {
+        Arc::new(self)
+    }
+}
+
+// Any texture wrapped around the scene: u goes around the vertical axis starting
+// behind -z, v from the bottom (0) to the top (1) as for an equirectangular image.
+// The point handed to the texture is the unit direction, for solid textures.
+pub struct TextureBackground {
+    texture: Arc<dyn Texture>,
+}
+
+impl TextureBackground {
+    pub fn new(texture: Arc<dyn Texture>) -> TextureBackground {
+        TextureBackground { texture }
+    }
+}
+
+impl Background for TextureBackground {
+    fn radiance(&self, direction: &Vector3<f64>) -> Color {
+        let d = vunit(direction);
+        let u = (d[0].atan2(-d[2]) / (2.0 * PI) + 0.5).rem_euclid(1.0);
+        let v = 1.0 - d[1].clamp(-1.0, 1.0).acos() / PI;
+        self.texture.value(u, v, &d)
+    }
+
+    fn share(self) -> Arc<dyn Background> {
+        Arc::new(self)
+    }
+}
+
+unsafe impl Send for TextureBackground {}
+unsafe impl Sync for TextureBackground {}
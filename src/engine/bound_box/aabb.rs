@@ -3,6 +3,7 @@ use nalgebra::Vector3;
 use std::cmp::Ordering;
 use std::sync::Arc;
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Copy, Clone)]
 pub struct AABB {
     min: Vector3<f64>,
@@ -25,7 +26,12 @@ impl AABB {
         AABB::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, 0.0))
     }
 
-    pub fn hit(&self, r: &Ray, mut tmin: f64, mut tmax: f64) -> bool {
+    pub fn hit(&self, r: &Ray, tmin: f64, tmax: f64) -> bool {
+        self.intersect(r, tmin, tmax).is_some()
+    }
+
+    // Part of the ray between tmin and tmax that lies inside the box
+    pub fn intersect(&self, r: &Ray, mut tmin: f64, mut tmax: f64) -> Option<(f64, f64)> {
         for a in 0..3 {
             let inv_d = 1.0 / r.direction()[a];
             let mut t0 = (self.min[a] - r.origin()[a]) * inv_d;
@@ -40,10 +46,10 @@ impl AABB {
                 tmax = t1
             }
             if tmax <= tmin {
-                return false;
+                return None;
             }
         }
-        return true;
+        Some((tmin, tmax))
     }
 
     pub fn min(&self) -> Vector3<f64> {
@@ -5,13 +5,19 @@ use crate::engine::{HitRecord, Hittable, Ray};
 
 use std::sync::Arc;
 
-struct BvhNode {
+// Bounding volume hierarchy over objects that all have a bounding box, skipping whole
+// groups of them a ray passes by
+pub struct BvhNode {
     left: Arc<dyn Hittable>,
     right: Arc<dyn Hittable>,
     bbox: AABB,
 }
 
 impl BvhNode {
+    pub fn new(objects: Vec<Arc<dyn Hittable>>) -> BvhNode {
+        BvhNode::build(objects)
+    }
+
     fn build(mut objects: Vec<Arc<dyn Hittable>>) -> BvhNode {
         let axis = rand_int(0, 2);
         let comparator = match axis {
@@ -65,7 +71,9 @@ impl Hittable for BvhNode {
             return false;
         }
 
+        // The right side only matters where it is closer than whatever the left one hit
         let hit_left = self.left.hit(r, tmin, tmax, record);
+        let tmax = if hit_left { record.t } else { tmax };
         let hit_right = self.right.hit(r, tmin, tmax, record);
         return hit_left || hit_right;
     }
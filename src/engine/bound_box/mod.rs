@@ -1,3 +1,4 @@
 pub mod aabb;
 pub use aabb::AABB;
-pub mod bvh;
\ No newline at end of file
+pub mod bvh;
+pub use bvh::BvhNode;
\ No newline at end of file
@@ -0,0 +1,198 @@
+use crate::data::{upsample, vunit, Color, Distribution2D};
+use crate::engine::Background;
+use exr::prelude::read_first_rgba_layer_from_file;
+use image::hdr::HdrDecoder;
+use nalgebra::Vector3;
+use std::f64::consts::PI;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::sync::Arc;
+
+// Light arriving from every direction out of an equirectangular HDR image, such as a
+// captured sky or studio. The top row is straight up, the middle column looks down -z
+// before rotation. Directions are sampled in proportion to the brightness of the image
+// so small bright features like the sun are found.
+pub struct EnvironmentLight {
+    width: usize,
+    height: usize,
+    // Row by row from the top
+    pixels: Vec<Color>,
+    // Turn around the vertical axis, radians
+    rotation: f64,
+    intensity: f64,
+    distribution: Distribution2D,
+}
+
+impl EnvironmentLight {
+    // Reads a Radiance .hdr or an OpenEXR image (its first layer with RGB channels)
+    pub fn load(path: &str) -> Result<EnvironmentLight, String> {
+        let extension = Path::new(path).extension().and_then(|e| e.to_str());
+        match extension.map(|e| e.to_lowercase()).as_deref() {
+            Some("hdr") => EnvironmentLight::load_hdr(path),
+            Some("exr") => EnvironmentLight::load_exr(path),
+            _ => Err(format!("{}: environment maps must be .hdr or .exr", path)),
+        }
+    }
+
+    fn load_hdr(path: &str) -> Result<EnvironmentLight, String> {
+        let file = File::open(path).map_err(|e| format!("Can't read {}: {}", path, e))?;
+        let decoder =
+            HdrDecoder::new(BufReader::new(file)).map_err(|e| format!("{}: {}", path, e))?;
+        let metadata = decoder.metadata();
+        let pixels = decoder
+            .read_image_hdr()
+            .map_err(|e| format!("{}: {}", path, e))?
+            .iter()
+            .map(|p| Color::new(p[0] as f64, p[1] as f64, p[2] as f64))
+            .collect();
+        Ok(EnvironmentLight::new(
+            metadata.width as usize,
+            metadata.height as usize,
+            pixels,
+        ))
+    }
+
+    fn load_exr(path: &str) -> Result<EnvironmentLight, String> {
+        let image = read_first_rgba_layer_from_file(
+            path,
+            |size, _| (size.width(), vec![Color::new(0.0, 0.0, 0.0); size.area()]),
+            |(width, pixels), position, (r, g, b, _): (f32, f32, f32, f32)| {
+                pixels[position.y() * *width + position.x()] =
+                    Color::new(r as f64, g as f64, b as f64);
+            },
+        )
+        .map_err(|e| format!("{}: {}", path, e))?;
+        let size = image.layer_data.size;
+        let (_, pixels) = image.layer_data.channel_data.pixels;
+        Ok(EnvironmentLight::new(size.width(), size.height(), pixels))
+    }
+
+    // Bakes radiance given for every direction into a map, e.g. a procedural sky
+    pub fn from_function<F>(width: usize, height: usize, radiance: F) -> EnvironmentLight
+    where
+        F: Fn(&Vector3<f64>) -> Color,
+    {
+        let pixels = (0..width * height)
+            .map(|i| {
+                let u = ((i % width) as f64 + 0.5) / width as f64;
+                let v = ((i / width) as f64 + 0.5) / height as f64;
+                radiance(&direction(u, v, 0.0))
+            })
+            .collect();
+        EnvironmentLight::new(width, height, pixels)
+    }
+
+    pub fn new(width: usize, height: usize, pixels: Vec<Color>) -> EnvironmentLight {
+        assert!(width > 0 && height > 0, "Empty environment map");
+        assert_eq!(pixels.len(), width * height, "Environment map size mismatch");
+        // Rows near the poles cover less of the sphere
+        let weights: Vec<f64> = (0..width * height)
+            .map(|i| {
+                let theta = PI * ((i / width) as f64 + 0.5) / height as f64;
+                pixels[i].luminance().max(0.0) * theta.sin()
+            })
+            .collect();
+        EnvironmentLight {
+            width,
+            height,
+            pixels,
+            rotation: 0.0,
+            intensity: 1.0,
+            distribution: Distribution2D::new(&weights, width, height),
+        }
+    }
+
+    // Degrees around the vertical axis
+    pub fn with_rotation(mut self, degrees: f64) -> EnvironmentLight {
+        self.rotation = degrees.to_radians();
+        self
+    }
+
+    pub fn with_intensity(mut self, intensity: f64) -> EnvironmentLight {
+        self.intensity = intensity;
+        self
+    }
+
+    // Image coordinates in [0, 1) of a direction
+    fn uv(&self, direction: &Vector3<f64>) -> (f64, f64) {
+        let d = vunit(direction);
+        let phi = d[0].atan2(-d[2]) - self.rotation;
+        let u = (phi / (2.0 * PI) + 0.5).rem_euclid(1.0);
+        let v = d[1].clamp(-1.0, 1.0).acos() / PI;
+        (u, v.min(1.0 - f64::EPSILON))
+    }
+
+    // From density over the image to density over directions
+    fn to_solid_angle(&self, pdf: f64, v: f64) -> f64 {
+        let sin_theta = (v * PI).sin();
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+        pdf / (2.0 * PI * PI * sin_theta)
+    }
+}
+
+impl Background for EnvironmentLight {
+    fn radiance(&self, direction: &Vector3<f64>) -> Color {
+        let (u, v) = self.uv(direction);
+        let x = ((u * self.width as f64) as usize).min(self.width - 1);
+        let y = ((v * self.height as f64) as usize).min(self.height - 1);
+        upsample(self.pixels[y * self.width + x]) * self.intensity
+    }
+
+    fn share(self) -> Arc<dyn Background> {
+        Arc::new(self)
+    }
+
+    fn sample(&self) -> Option<(Vector3<f64>, f64)> {
+        let ((u, v), pdf) = self.distribution.sample();
+        Some((direction(u, v, self.rotation), self.to_solid_angle(pdf, v)))
+    }
+
+    fn pdf(&self, direction: &Vector3<f64>) -> f64 {
+        let (u, v) = self.uv(direction);
+        self.to_solid_angle(self.distribution.pdf(u, v), v)
+    }
+}
+
+// Direction of image coordinates in [0, 1) for a map turned by rotation radians
+fn direction(u: f64, v: f64, rotation: f64) -> Vector3<f64> {
+    let phi = (u - 0.5) * 2.0 * PI + rotation;
+    let theta = v * PI;
+    Vector3::new(
+        theta.sin() * phi.sin(),
+        theta.cos(),
+        -theta.sin() * phi.cos(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use exr::prelude::write_rgb_file;
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn load_exr() {
+        let (width, height) = (6, 3);
+        let color = |x: usize, y: usize| (x as f32 * 0.5, y as f32 * 2.0, 0.25);
+        let path = env::temp_dir().join(format!("environment_{}.exr", std::process::id()));
+        write_rgb_file(&path, width, height, color).unwrap();
+        let environment = EnvironmentLight::load(path.to_str().unwrap());
+        fs::remove_file(&path).unwrap();
+
+        let environment = environment.unwrap();
+        assert_eq!((environment.width, environment.height), (width, height));
+        for (i, pixel) in environment.pixels.iter().enumerate() {
+            let (r, g, b) = color(i % width, i / width);
+            assert_eq!(*pixel, Color::new(r as f64, g as f64, b as f64));
+        }
+    }
+
+    #[test]
+    fn load_rejects_other_formats() {
+        assert!(EnvironmentLight::load("sky.png").is_err());
+    }
+}
@@ -1,5 +1,6 @@
+use crate::data::rand_float;
 use crate::engine::bound_box::AABB;
-use crate::engine::Hittable;
+use crate::engine::{HitRecord, Hittable, Ray};
 use crate::materials::Material;
 use nalgebra::Vector3;
 use std::sync::Arc;
@@ -43,6 +44,8 @@ impl Hittable for XYRect {
         }
         record.u = (x - self.x0) / (self.x1 - self.x0);
         record.v = (y - self.y0) / (self.y1 - self.y0);
+        record.dpdu = Vector3::new(self.x1 - self.x0, 0.0, 0.0);
+        record.dpdv = Vector3::new(0.0, self.y1 - self.y0, 0.0);
         record.t = t;
         let outward_normal = Vector3::new(0.0, 0.0, 1.0);
         record.set_face_normal(r, &outward_normal);
@@ -60,6 +63,46 @@ impl Hittable for XYRect {
         );
         return Option::from(output_box);
     }
+
+    // Solid angle density of a uniformly sampled point on the rectangle
+    fn pdf_value(&self, origin: &Vector3<f64>, direction: &Vector3<f64>) -> f64 {
+        let mut record = HitRecord::new();
+        if !self.hit(&Ray::new(*origin, *direction), 0.001, f64::INFINITY, &mut record) {
+            return 0.0;
+        }
+        let length_squared = direction.dot(direction);
+        let distance_squared = record.t * record.t * length_squared;
+        let cosine = (direction.dot(&record.normal) / length_squared.sqrt()).abs();
+        distance_squared / (cosine * self.area())
+    }
+
+    fn random(&self, origin: &Vector3<f64>) -> Vector3<f64> {
+        let random_point = Vector3::new(
+            rand_float(self.x0, self.x1),
+            rand_float(self.y0, self.y1),
+            self.z,
+        );
+        random_point - origin
+    }
+
+    fn area(&self) -> f64 {
+        (self.x1 - self.x0) * (self.y1 - self.y0)
+    }
+
+    fn sample_surface(&self) -> Option<HitRecord> {
+        let x = rand_float(self.x0, self.x1);
+        let y = rand_float(self.y0, self.y1);
+        let mut record = HitRecord::new();
+        record.p = Vector3::new(x, y, self.z);
+        record.normal = Vector3::new(0.0, 0.0, 1.0);
+        record.front_face = true;
+        record.u = (x - self.x0) / (self.x1 - self.x0);
+        record.v = (y - self.y0) / (self.y1 - self.y0);
+        record.dpdu = Vector3::new(self.x1 - self.x0, 0.0, 0.0);
+        record.dpdv = Vector3::new(0.0, self.y1 - self.y0, 0.0);
+        record.mat_ptr = self.mat_ptr.clone();
+        Some(record)
+    }
 }
 
 unsafe impl Send for XYRect {}
@@ -105,6 +148,8 @@ impl Hittable for XZRect {
         }
         record.u = (x - self.x0) / (self.x1 - self.x0);
         record.v = (z - self.z0) / (self.z1 - self.z0);
+        record.dpdu = Vector3::new(self.x1 - self.x0, 0.0, 0.0);
+        record.dpdv = Vector3::new(0.0, 0.0, self.z1 - self.z0);
         record.t = t;
         let outward_normal = Vector3::new(0.0, 1.0, 0.0);
         record.set_face_normal(r, &outward_normal);
@@ -117,11 +162,50 @@ impl Hittable for XZRect {
     }
     fn get_bounding_box(&self) -> Option<AABB> {
         let output_box = AABB::new(
-            Vector3::new(self.x0, self.y + 0.0001, self.z0),
-            Vector3::new(self.x1, self.y - 0.0001, self.z1),
+            Vector3::new(self.x0, self.y - 0.0001, self.z0),
+            Vector3::new(self.x1, self.y + 0.0001, self.z1),
         );
         return Option::from(output_box);
     }
+
+    fn pdf_value(&self, origin: &Vector3<f64>, direction: &Vector3<f64>) -> f64 {
+        let mut record = HitRecord::new();
+        if !self.hit(&Ray::new(*origin, *direction), 0.001, f64::INFINITY, &mut record) {
+            return 0.0;
+        }
+        let length_squared = direction.dot(direction);
+        let distance_squared = record.t * record.t * length_squared;
+        let cosine = (direction.dot(&record.normal) / length_squared.sqrt()).abs();
+        distance_squared / (cosine * self.area())
+    }
+
+    fn random(&self, origin: &Vector3<f64>) -> Vector3<f64> {
+        let random_point = Vector3::new(
+            rand_float(self.x0, self.x1),
+            self.y,
+            rand_float(self.z0, self.z1),
+        );
+        random_point - origin
+    }
+
+    fn area(&self) -> f64 {
+        (self.x1 - self.x0) * (self.z1 - self.z0)
+    }
+
+    fn sample_surface(&self) -> Option<HitRecord> {
+        let x = rand_float(self.x0, self.x1);
+        let z = rand_float(self.z0, self.z1);
+        let mut record = HitRecord::new();
+        record.p = Vector3::new(x, self.y, z);
+        record.normal = Vector3::new(0.0, 1.0, 0.0);
+        record.front_face = true;
+        record.u = (x - self.x0) / (self.x1 - self.x0);
+        record.v = (z - self.z0) / (self.z1 - self.z0);
+        record.dpdu = Vector3::new(self.x1 - self.x0, 0.0, 0.0);
+        record.dpdv = Vector3::new(0.0, 0.0, self.z1 - self.z0);
+        record.mat_ptr = self.mat_ptr.clone();
+        Some(record)
+    }
 }
 
 unsafe impl Send for XZRect {}
@@ -168,6 +252,8 @@ impl Hittable for YZRect {
         }
         record.u = (y - self.y0) / (self.y1 - self.y0);
         record.v = (z - self.z0) / (self.z1 - self.z0);
+        record.dpdu = Vector3::new(0.0, self.y1 - self.y0, 0.0);
+        record.dpdv = Vector3::new(0.0, 0.0, self.z1 - self.z0);
         record.t = t;
         let outward_normal = Vector3::new(1.0, 0.0, 0.0);
         record.set_face_normal(r, &outward_normal);
@@ -180,11 +266,50 @@ impl Hittable for YZRect {
     }
     fn get_bounding_box(&self) -> Option<AABB> {
         let output_box = AABB::new(
-            Vector3::new(self.x+0.0001, self.y0, self.z0),
-            Vector3::new(self.x-0.0001, self.y1, self.z1),
+            Vector3::new(self.x - 0.0001, self.y0, self.z0),
+            Vector3::new(self.x + 0.0001, self.y1, self.z1),
         );
         return Option::from(output_box);
     }
+
+    fn pdf_value(&self, origin: &Vector3<f64>, direction: &Vector3<f64>) -> f64 {
+        let mut record = HitRecord::new();
+        if !self.hit(&Ray::new(*origin, *direction), 0.001, f64::INFINITY, &mut record) {
+            return 0.0;
+        }
+        let length_squared = direction.dot(direction);
+        let distance_squared = record.t * record.t * length_squared;
+        let cosine = (direction.dot(&record.normal) / length_squared.sqrt()).abs();
+        distance_squared / (cosine * self.area())
+    }
+
+    fn random(&self, origin: &Vector3<f64>) -> Vector3<f64> {
+        let random_point = Vector3::new(
+            self.x,
+            rand_float(self.y0, self.y1),
+            rand_float(self.z0, self.z1),
+        );
+        random_point - origin
+    }
+
+    fn area(&self) -> f64 {
+        (self.y1 - self.y0) * (self.z1 - self.z0)
+    }
+
+    fn sample_surface(&self) -> Option<HitRecord> {
+        let y = rand_float(self.y0, self.y1);
+        let z = rand_float(self.z0, self.z1);
+        let mut record = HitRecord::new();
+        record.p = Vector3::new(self.x, y, z);
+        record.normal = Vector3::new(1.0, 0.0, 0.0);
+        record.front_face = true;
+        record.u = (y - self.y0) / (self.y1 - self.y0);
+        record.v = (z - self.z0) / (self.z1 - self.z0);
+        record.dpdu = Vector3::new(0.0, self.y1 - self.y0, 0.0);
+        record.dpdv = Vector3::new(0.0, 0.0, self.z1 - self.z0);
+        record.mat_ptr = self.mat_ptr.clone();
+        Some(record)
+    }
 }
 
 unsafe impl Send for YZRect {}
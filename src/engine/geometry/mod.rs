@@ -1,4 +1,6 @@
 pub mod sphere;
 pub mod aarect;
+pub mod volume;
 pub use crate::engine::geometry::sphere::Sphere;
-pub use crate::engine::geometry::aarect::{XYRect, XZRect, YZRect};
\ No newline at end of file
+pub use crate::engine::geometry::aarect::{XYRect, XZRect, YZRect};
+pub use crate::engine::geometry::volume::{DensityField, DensityGrid, NoiseDensity, Volume};
\ No newline at end of file
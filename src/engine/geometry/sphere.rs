@@ -1,5 +1,6 @@
 use nalgebra::Vector3;
 
+use crate::data::{vrandom_in_unit_sphere, vrandom_to_sphere, Onb};
 use crate::engine::bound_box::AABB;
 pub use crate::engine::hittable::Hittable;
 use crate::engine::{HitRecord, Ray};
@@ -27,6 +28,23 @@ impl Sphere {
         *u = 1.0 - (phi + PI) / (2.0 * PI);
         *v = (theta + PI / 2.0) / PI;
     }
+
+    // Derivatives of a point on the sphere with respect to the u and v of get_uv
+    pub fn get_tangents(
+        n: &Vector3<f64>,
+        radius: f64,
+        dpdu: &mut Vector3<f64>,
+        dpdv: &mut Vector3<f64>,
+    ) {
+        *dpdu = 2.0 * PI * radius * Vector3::new(n[2], 0.0, -n[0]);
+        let rho = (n[0] * n[0] + n[2] * n[2]).sqrt();
+        if rho == 0.0 {
+            // u is undefined at the poles
+            *dpdv = Vector3::new(0.0, 0.0, 0.0);
+            return;
+        }
+        *dpdv = PI * radius * Vector3::new(-n[1] * n[0] / rho, rho, -n[1] * n[2] / rho);
+    }
 }
 
 impl Hittable for Sphere {
@@ -47,6 +65,12 @@ impl Hittable for Sphere {
                 let outward_normal = (record.p - self.center) / self.radius;
                 record.set_face_normal(r, &outward_normal);
                 Sphere::get_uv(&outward_normal, &mut record.u, &mut record.v);
+                Sphere::get_tangents(
+                    &outward_normal,
+                    self.radius,
+                    &mut record.dpdu,
+                    &mut record.dpdv,
+                );
                 record.mat_ptr = self.mat_ptr.clone();
                 return true;
             }
@@ -57,6 +81,12 @@ impl Hittable for Sphere {
                 let outward_normal = (record.p - self.center) / self.radius;
                 record.set_face_normal(r, &outward_normal);
                 Sphere::get_uv(&outward_normal, &mut record.u, &mut record.v);
+                Sphere::get_tangents(
+                    &outward_normal,
+                    self.radius,
+                    &mut record.dpdu,
+                    &mut record.dpdv,
+                );
                 record.mat_ptr = self.mat_ptr.clone();
                 return true;
             }
@@ -74,6 +104,52 @@ impl Hittable for Sphere {
             self.center + Vector3::new(self.radius, self.radius, self.radius),
         ))
     }
+
+    fn pdf_value(&self, origin: &Vector3<f64>, direction: &Vector3<f64>) -> f64 {
+        let mut record = HitRecord::new();
+        if !self.hit(&Ray::new(*origin, *direction), 0.001, f64::INFINITY, &mut record) {
+            return 0.0;
+        }
+        let distance_squared = (self.center - origin).dot(&(self.center - origin));
+        if distance_squared <= self.radius * self.radius {
+            // Inside the sphere every direction hits it
+            return 1.0 / (4.0 * PI);
+        }
+        let cos_theta_max = (1.0 - self.radius * self.radius / distance_squared).sqrt();
+        let solid_angle = 2.0 * PI * (1.0 - cos_theta_max);
+        1.0 / solid_angle
+    }
+
+    fn random(&self, origin: &Vector3<f64>) -> Vector3<f64> {
+        let direction = self.center - origin;
+        let distance_squared = direction.dot(&direction);
+        if distance_squared <= self.radius * self.radius {
+            return vrandom_in_unit_sphere();
+        }
+        let uvw = Onb::build_from_w(&direction);
+        uvw.local_vec(&vrandom_to_sphere(self.radius, distance_squared))
+    }
+
+    fn area(&self) -> f64 {
+        4.0 * PI * self.radius * self.radius
+    }
+
+    fn sample_surface(&self) -> Option<HitRecord> {
+        let outward_normal = vrandom_in_unit_sphere();
+        let mut record = HitRecord::new();
+        record.p = self.center + self.radius * outward_normal;
+        record.normal = outward_normal;
+        record.front_face = true;
+        Sphere::get_uv(&outward_normal, &mut record.u, &mut record.v);
+        Sphere::get_tangents(
+            &outward_normal,
+            self.radius,
+            &mut record.dpdu,
+            &mut record.dpdv,
+        );
+        record.mat_ptr = self.mat_ptr.clone();
+        Some(record)
+    }
 }
 
 unsafe impl Send for Sphere {}
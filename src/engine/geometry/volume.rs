@@ -0,0 +1,376 @@
+use crate::data::rand_float01;
+use crate::engine::bound_box::AABB;
+use crate::engine::{HitRecord, Hittable, Ray};
+use crate::materials::Material;
+use nalgebra::Vector3;
+use noise::{Fbm, NoiseFn};
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+// Density of a medium over its bounding box, points given in box coordinates running
+// from 0 to 1 along every axis
+pub trait DensityField: Send + Sync {
+    fn density(&self, p: &Vector3<f64>) -> f64;
+    // Upper bound of density over the whole box
+    fn max_density(&self) -> f64;
+    fn share(self) -> Arc<dyn DensityField>;
+}
+
+// Densities sampled on a regular grid spanning the box, interpolated trilinearly
+pub struct DensityGrid {
+    nx: usize,
+    ny: usize,
+    nz: usize,
+    // x varies fastest, then y, then z
+    values: Vec<f64>,
+    max: f64,
+}
+
+impl DensityGrid {
+    pub fn new(nx: usize, ny: usize, nz: usize, values: Vec<f64>) -> DensityGrid {
+        assert!(nx > 0 && ny > 0 && nz > 0, "Empty density grid");
+        assert_eq!(
+            Some(values.len()),
+            cells(nx, ny, nz),
+            "Density grid size mismatch"
+        );
+        assert!(
+            values.iter().all(|v| v.is_finite() && *v >= 0.0),
+            "Density grid values must be finite and not negative"
+        );
+        let max = values.iter().cloned().fold(0.0, f64::max);
+        DensityGrid {
+            nx,
+            ny,
+            nz,
+            values,
+            max,
+        }
+    }
+
+    // Reads a grid from a text file (".txt": the three dimensions, then every value,
+    // separated by whitespace) or a raw binary file (three little endian u32 dimensions,
+    // then little endian f32 values), in both cases in the order values are stored in.
+    // Densities must be finite and not negative.
+    pub fn load(path: &str) -> Result<DensityGrid, String> {
+        let bytes = fs::read(path).map_err(|e| format!("Can't read {}: {}", path, e))?;
+        let text = Path::new(path).extension().is_some_and(|e| e == "txt");
+        let size_error = |dims: [usize; 3], n_values: usize| {
+            format!(
+                "{}: {} values for a {}x{}x{} grid",
+                path, n_values, dims[0], dims[1], dims[2]
+            )
+        };
+        let (dims, values) = match text {
+            true => {
+                let text = String::from_utf8(bytes).map_err(|e| format!("{}: {}", path, e))?;
+                let mut numbers = text.split_whitespace();
+                let mut dims = [0; 3];
+                for dim in dims.iter_mut() {
+                    let number = numbers.next().ok_or(format!("{}: missing size", path))?;
+                    *dim = number
+                        .parse()
+                        .map_err(|_| format!("{}: bad size '{}'", path, number))?;
+                }
+                let values = numbers
+                    .map(|n| {
+                        n.parse()
+                            .map_err(|_| format!("{}: bad value '{}'", path, n))
+                    })
+                    .collect::<Result<Vec<f64>, String>>()?;
+                (dims, values)
+            }
+            false => {
+                if bytes.len() < 12 {
+                    return Err(format!("{}: missing size", path));
+                }
+                let word = |i: usize| [bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]];
+                let mut dims = [0; 3];
+                for (i, dim) in dims.iter_mut().enumerate() {
+                    *dim = u32::from_le_bytes(word(4 * i)) as usize;
+                }
+                // Exactly one f32 for every cell, nothing left over
+                let expected = cells(dims[0], dims[1], dims[2])
+                    .and_then(|n| n.checked_mul(4))
+                    .and_then(|n| n.checked_add(12));
+                if expected != Some(bytes.len()) {
+                    return Err(size_error(dims, (bytes.len() - 12) / 4));
+                }
+                let values = (12..bytes.len())
+                    .step_by(4)
+                    .map(|i| f32::from_le_bytes(word(i)) as f64)
+                    .collect();
+                (dims, values)
+            }
+        };
+        let [nx, ny, nz] = dims;
+        if nx == 0 || ny == 0 || nz == 0 || cells(nx, ny, nz) != Some(values.len()) {
+            return Err(size_error(dims, values.len()));
+        }
+        if let Some(i) = values.iter().position(|v| !v.is_finite() || *v < 0.0) {
+            return Err(format!("{}: bad density {} at {}", path, values[i], i));
+        }
+        Ok(DensityGrid::new(nx, ny, nz, values))
+    }
+
+    fn value(&self, x: usize, y: usize, z: usize) -> f64 {
+        self.values[x + self.nx * (y + self.ny * z)]
+    }
+}
+
+// Number of cells of a grid, None if it doesn't fit in a usize
+fn cells(nx: usize, ny: usize, nz: usize) -> Option<usize> {
+    nx.checked_mul(ny)?.checked_mul(nz)
+}
+
+impl DensityField for DensityGrid {
+    fn density(&self, p: &Vector3<f64>) -> f64 {
+        // Values sit at the centres of the cells
+        let cell = |t: f64, n: usize| {
+            let t = (t * n as f64 - 0.5).clamp(0.0, (n - 1) as f64);
+            let i = (t as usize).min(n.saturating_sub(2));
+            (i, (i + 1).min(n - 1), t - i as f64)
+        };
+        let (x0, x1, fx) = cell(p[0], self.nx);
+        let (y0, y1, fy) = cell(p[1], self.ny);
+        let (z0, z1, fz) = cell(p[2], self.nz);
+        let lerp = |a: f64, b: f64, t: f64| a + (b - a) * t;
+        let plane = |z: usize| {
+            lerp(
+                lerp(self.value(x0, y0, z), self.value(x1, y0, z), fx),
+                lerp(self.value(x0, y1, z), self.value(x1, y1, z), fx),
+                fy,
+            )
+        };
+        lerp(plane(z0), plane(z1), fz)
+    }
+
+    fn max_density(&self) -> f64 {
+        self.max
+    }
+
+    fn share(self) -> Arc<dyn DensityField> {
+        Arc::new(self)
+    }
+}
+
+// Procedural cloud, fractal noise thinning out towards the sides of the box.
+// coverage from 0 to 1 is how much of the box is cloud.
+pub struct NoiseDensity {
+    noise: Fbm,
+    frequency: f64,
+    coverage: f64,
+}
+
+impl NoiseDensity {
+    pub fn new(frequency: f64, coverage: f64) -> NoiseDensity {
+        NoiseDensity {
+            noise: Fbm::new(),
+            frequency,
+            coverage: coverage.clamp(0.0, 1.0),
+        }
+    }
+}
+
+impl DensityField for NoiseDensity {
+    fn density(&self, p: &Vector3<f64>) -> f64 {
+        let q = p * self.frequency;
+        let noise = 0.5 * (self.noise.get([q[0], q[1], q[2]]) + 1.0);
+        // Distance from the centre, 1 at the middle of the faces
+        let r = (2.0 * p - Vector3::new(1.0, 1.0, 1.0)).norm();
+        let falloff = (1.0 - r * r).max(0.0);
+        ((noise + self.coverage - 1.0) * falloff * 2.0).clamp(0.0, 1.0)
+    }
+
+    fn max_density(&self) -> f64 {
+        1.0
+    }
+
+    fn share(self) -> Arc<dyn DensityField> {
+        Arc::new(self)
+    }
+}
+
+// Participating medium filling a box, like smoke or a cloud. Rays passing through
+// scatter at random with a probability following the density, where they do the hit
+// is inside the medium and has no normal. Free paths are found by delta tracking
+// against the maximum density.
+pub struct Volume {
+    bounds: AABB,
+    field: Arc<dyn DensityField>,
+    // Extinction per unit distance where the field is 1
+    density: f64,
+    material: Arc<dyn Material>,
+}
+
+impl Volume {
+    pub fn new(
+        min: Vector3<f64>,
+        max: Vector3<f64>,
+        field: Arc<dyn DensityField>,
+        density: f64,
+        material: Arc<dyn Material>,
+    ) -> Volume {
+        Volume {
+            bounds: AABB::new(min, max),
+            field,
+            density,
+            material,
+        }
+    }
+
+    pub fn share(self) -> Arc<dyn Hittable> {
+        Arc::new(self)
+    }
+
+    fn local(&self, p: &Vector3<f64>) -> Vector3<f64> {
+        let (min, max) = (self.bounds.min(), self.bounds.max());
+        Vector3::new(
+            (p[0] - min[0]) / (max[0] - min[0]),
+            (p[1] - min[1]) / (max[1] - min[1]),
+            (p[2] - min[2]) / (max[2] - min[2]),
+        )
+    }
+}
+
+impl Hittable for Volume {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, record: &mut HitRecord) -> bool {
+        let (t_enter, t_exit) = match self.bounds.intersect(r, t_min, t_max) {
+            Some(interval) => interval,
+            None => return false,
+        };
+        let majorant = self.density * self.field.max_density();
+        let speed = r.direction().norm();
+        if majorant <= 0.0 || speed == 0.0 {
+            return false;
+        }
+
+        // Tentative collisions against the maximum density, real with the chance the
+        // actual density there makes up of it
+        let mut t = t_enter;
+        loop {
+            t -= (1.0 - rand_float01()).ln() / (majorant * speed);
+            if t >= t_exit {
+                return false;
+            }
+            let p = r.at(t);
+            let density = self.density * self.field.density(&self.local(&p));
+            if rand_float01() * majorant < density {
+                record.t = t;
+                record.p = p;
+                record.normal = Vector3::new(0.0, 0.0, 0.0);
+                record.front_face = true;
+                record.mat_ptr = self.material.clone();
+                record.u = 0.0;
+                record.v = 0.0;
+                record.dpdu = Vector3::new(0.0, 0.0, 0.0);
+                record.dpdv = Vector3::new(0.0, 0.0, 0.0);
+                return true;
+            }
+        }
+    }
+
+    fn share(self) -> Arc<dyn Hittable> {
+        Arc::new(self)
+    }
+
+    fn get_bounding_box(&self) -> Option<AABB> {
+        Some(self.bounds)
+    }
+}
+
+unsafe impl Send for Volume {}
+unsafe impl Sync for Volume {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn assert_same(grid: &DensityGrid, values: &[f64]) {
+        assert_eq!((grid.nx, grid.ny, grid.nz), (3, 2, 2));
+        assert_eq!(grid.values, values);
+        assert_eq!(grid.max_density(), 2.25);
+    }
+
+    fn values() -> Vec<f64> {
+        (0..12)
+            .map(|i| i as f64 * 0.25 - 0.5)
+            .map(|v| v.max(0.0))
+            .collect()
+    }
+
+    // Loads bytes written to a file with a name unique to the test
+    fn load_bytes(name: &str, bytes: &[u8]) -> Result<DensityGrid, String> {
+        let path = env::temp_dir().join(format!("density_grid_{}_{}", std::process::id(), name));
+        fs::write(&path, bytes).unwrap();
+        let grid = DensityGrid::load(path.to_str().unwrap());
+        fs::remove_file(&path).unwrap();
+        grid
+    }
+
+    fn binary(dims: [u32; 3], values: &[f32]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for dim in dims.iter() {
+            bytes.extend_from_slice(&dim.to_le_bytes());
+        }
+        for value in values.iter() {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes
+    }
+
+    #[test]
+    fn load_text_grid() {
+        let values = values();
+        let numbers: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+        let text = format!("3 2 2\n{}\n", numbers.join(" "));
+        let grid = load_bytes("text.txt", text.as_bytes());
+        assert_same(&grid.unwrap(), &values);
+    }
+
+    #[test]
+    fn load_binary_grid() {
+        let values = values();
+        let floats: Vec<f32> = values.iter().map(|v| *v as f32).collect();
+        let grid = load_bytes("binary.raw", &binary([3, 2, 2], &floats));
+        assert_same(&grid.unwrap(), &values);
+    }
+
+    #[test]
+    fn load_rejects_wrong_sizes() {
+        assert!(load_bytes("short.txt", b"2 2 2 1 2 3").is_err());
+        assert!(load_bytes("short.raw", &binary([2, 2, 2], &[1.0; 7])).is_err());
+    }
+
+    #[test]
+    fn load_rejects_trailing_bytes() {
+        for extra in 1..4 {
+            let mut bytes = binary([2, 1, 1], &[1.0, 2.0]);
+            bytes.extend(vec![0; extra]);
+            assert!(load_bytes(&format!("trailing_{}.raw", extra), &bytes).is_err());
+        }
+    }
+
+    #[test]
+    fn load_rejects_sizes_that_overflow() {
+        let bytes = binary([u32::MAX, u32::MAX, u32::MAX], &[1.0]);
+        assert!(load_bytes("huge.raw", &bytes).is_err());
+        let text = format!("{} {} 2 1 1", usize::MAX, usize::MAX);
+        assert!(load_bytes("huge.txt", text.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn load_rejects_negative_density() {
+        assert!(load_bytes("negative.txt", b"2 1 1 0.5 -0.1").is_err());
+        assert!(load_bytes("negative.raw", &binary([2, 1, 1], &[0.5, -0.1])).is_err());
+    }
+
+    #[test]
+    fn load_rejects_density_that_is_not_a_number() {
+        assert!(load_bytes("nan.txt", b"2 1 1 NaN 1").is_err());
+        assert!(load_bytes("inf.txt", b"2 1 1 inf 1").is_err());
+        assert!(load_bytes("nan.raw", &binary([2, 1, 1], &[f32::NAN, 1.0])).is_err());
+    }
+}
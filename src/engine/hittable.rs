@@ -14,6 +14,9 @@ pub struct HitRecord {
     pub(crate) mat_ptr: Arc<dyn Material>,
     pub(crate) u: f64,
     pub(crate) v: f64,
+    // Change of p with u and v, the tangent frame normal and bump maps perturb the normal in
+    pub(crate) dpdu: Vector3<f64>,
+    pub(crate) dpdv: Vector3<f64>,
 }
 
 impl HitRecord {
@@ -21,7 +24,7 @@ impl HitRecord {
         self.front_face = r.direction().dot(outward_normal) < 0.0;
         self.normal = *outward_normal;
         if !self.front_face {
-            self.normal = self.normal * -1.0;
+            self.normal *= -1.0;
         }
     }
     pub fn new() -> HitRecord {
@@ -33,6 +36,8 @@ impl HitRecord {
             mat_ptr: Arc::new(Lambertian::from_color(Color::new(0.0, 0.0, 0.0))),
             u: 0.0,
             v: 0.0,
+            dpdu: Vector3::new(0.0, 0.0, 0.0),
+            dpdv: Vector3::new(0.0, 0.0, 0.0),
         }
     }
 }
@@ -41,4 +46,25 @@ pub trait Hittable: Send + Sync {
     fn hit(&self, r: &Ray, t_min: f64, t_max: f64, record: &mut HitRecord) -> bool;
     fn share(self) -> Arc<dyn Hittable>;
     fn get_bounding_box(&self) -> Option<AABB>;
+
+    // Solid angle density of random() for a direction seen from origin,
+    // used by integrators that sample lights explicitly
+    fn pdf_value(&self, _origin: &Vector3<f64>, _direction: &Vector3<f64>) -> f64 {
+        0.0
+    }
+
+    // Random direction from origin towards the object
+    fn random(&self, _origin: &Vector3<f64>) -> Vector3<f64> {
+        Vector3::new(1.0, 0.0, 0.0)
+    }
+
+    fn area(&self) -> f64 {
+        0.0
+    }
+
+    // Uniformly distributed point on the surface with its outward normal,
+    // used to start paths on emitters
+    fn sample_surface(&self) -> Option<HitRecord> {
+        None
+    }
 }
@@ -1,5 +1,7 @@
 use crate::engine::bound_box::AABB;
 use crate::engine::{HitRecord, Hittable, Ray};
+use nalgebra::Vector3;
+use crate::data::rand_float01;
 use std::sync::Arc;
 
 pub struct HittableList {
@@ -16,6 +18,14 @@ impl HittableList {
     pub fn add(&mut self, object: Arc<dyn Hittable>) {
         self.objects.push(object);
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+
+    pub fn objects(&self) -> &[Arc<dyn Hittable>] {
+        &self.objects
+    }
 }
 
 impl Hittable for HittableList {
@@ -48,19 +58,51 @@ impl Hittable for HittableList {
         let mut first_box = true;
 
         for obj in self.objects.clone() {
-            match obj.get_bounding_box() {
-                Some(temp_box) => {
-                    if first_box {
-                        output_box = temp_box;
-                    } else {
-                        output_box = AABB::surrounding_box(output_box, temp_box);
-                    }
-                    first_box = false;
+            if let Some(temp_box) = obj.get_bounding_box() {
+                if first_box {
+                    output_box = temp_box;
+                } else {
+                    output_box = AABB::surrounding_box(output_box, temp_box);
                 }
-                None => (),
+                first_box = false;
             }
         }
 
         return Option::from(output_box);
     }
+
+    // Objects are picked uniformly, so the density is the average of theirs
+    fn pdf_value(&self, origin: &Vector3<f64>, direction: &Vector3<f64>) -> f64 {
+        if self.objects.is_empty() {
+            return 0.0;
+        }
+        let weight = 1.0 / self.objects.len() as f64;
+        self.objects
+            .iter()
+            .map(|obj| weight * obj.pdf_value(origin, direction))
+            .sum()
+    }
+
+    fn random(&self, origin: &Vector3<f64>) -> Vector3<f64> {
+        let n = self.objects.len();
+        let index = ((rand_float01() * n as f64) as usize).min(n - 1);
+        self.objects[index].random(origin)
+    }
+
+    fn area(&self) -> f64 {
+        self.objects.iter().map(|obj| obj.area()).sum()
+    }
+
+    // Objects are picked proportionally to their area, so points are uniform over all of them
+    fn sample_surface(&self) -> Option<HitRecord> {
+        let mut remaining = rand_float01() * self.area();
+        for obj in self.objects.iter() {
+            let area = obj.area();
+            if remaining < area {
+                return obj.sample_surface();
+            }
+            remaining -= area;
+        }
+        self.objects.last().and_then(|obj| obj.sample_surface())
+    }
 }
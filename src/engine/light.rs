@@ -0,0 +1,173 @@
+use crate::data::{rand_float01, upsample, vunit, Color, Onb};
+use nalgebra::Vector3;
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+// Light from a point or a direction, without any surface to hit. Integrators reach
+// these only by sampling them, so they cast no caustics.
+pub trait Light: Send + Sync {
+    // Light arriving at p from this light, None if it doesn't reach p at all
+    fn sample(&self, p: &Vector3<f64>) -> Option<LightSample>;
+    fn share(self) -> Arc<dyn Light>;
+}
+
+pub struct LightSample {
+    // Unit vector from the lit point towards the light
+    pub direction: Vector3<f64>,
+    // How far a blocker may be along direction, infinite for lights at infinity
+    pub distance: f64,
+    // Light arriving along direction, divided by the density it was picked with
+    pub incident: Color,
+}
+
+// Shines equally in every direction, intensity falling off with the squared distance
+pub struct PointLight {
+    position: Vector3<f64>,
+    intensity: Color,
+}
+
+impl PointLight {
+    pub fn new(position: Vector3<f64>, intensity: Color) -> PointLight {
+        PointLight {
+            position,
+            intensity,
+        }
+    }
+}
+
+impl Light for PointLight {
+    fn sample(&self, p: &Vector3<f64>) -> Option<LightSample> {
+        let to_light = self.position - p;
+        let distance_squared = to_light.dot(&to_light);
+        if distance_squared <= 0.0 {
+            return None;
+        }
+        Some(LightSample {
+            direction: to_light / distance_squared.sqrt(),
+            distance: distance_squared.sqrt(),
+            incident: upsample(self.intensity) / distance_squared,
+        })
+    }
+
+    fn share(self) -> Arc<dyn Light> {
+        Arc::new(self)
+    }
+}
+
+// Point light restricted to a cone around direction. angle is the half angle of the
+// cone in degrees, the light fades out over the last falloff degrees of it.
+pub struct SpotLight {
+    position: Vector3<f64>,
+    direction: Vector3<f64>,
+    intensity: Color,
+    cos_total: f64,
+    cos_falloff: f64,
+}
+
+impl SpotLight {
+    pub fn new(
+        position: Vector3<f64>,
+        direction: Vector3<f64>,
+        intensity: Color,
+        angle: f64,
+    ) -> SpotLight {
+        let angle = angle.clamp(0.0, 180.0);
+        SpotLight {
+            position,
+            direction: vunit(&direction),
+            intensity,
+            cos_total: angle.to_radians().cos(),
+            cos_falloff: angle.to_radians().cos(),
+        }
+    }
+
+    pub fn with_falloff(mut self, falloff: f64) -> SpotLight {
+        let total = self.cos_total.acos();
+        let start = (total - falloff.to_radians()).max(0.0);
+        self.cos_falloff = start.cos();
+        self
+    }
+
+    fn falloff(&self, cos_theta: f64) -> f64 {
+        if cos_theta < self.cos_total {
+            return 0.0;
+        }
+        if cos_theta >= self.cos_falloff {
+            return 1.0;
+        }
+        // Smoothstep across the rim
+        let t = (cos_theta - self.cos_total) / (self.cos_falloff - self.cos_total);
+        t * t * (3.0 - 2.0 * t)
+    }
+}
+
+impl Light for SpotLight {
+    fn sample(&self, p: &Vector3<f64>) -> Option<LightSample> {
+        let to_light = self.position - p;
+        let distance_squared = to_light.dot(&to_light);
+        if distance_squared <= 0.0 {
+            return None;
+        }
+        let direction = to_light / distance_squared.sqrt();
+        let falloff = self.falloff(-direction.dot(&self.direction));
+        if falloff <= 0.0 {
+            return None;
+        }
+        Some(LightSample {
+            direction,
+            distance: distance_squared.sqrt(),
+            incident: upsample(self.intensity) * (falloff / distance_squared),
+        })
+    }
+
+    fn share(self) -> Arc<dyn Light> {
+        Arc::new(self)
+    }
+}
+
+// Distant light such as the sun, arriving from direction (pointing towards the light)
+// with irradiance measured facing it. A non zero angular diameter in degrees (0.53 for
+// the sun) spreads it over a disk in the sky, softening the shadows.
+pub struct DirectionalLight {
+    direction: Vector3<f64>,
+    irradiance: Color,
+    cos_max: f64,
+}
+
+impl DirectionalLight {
+    pub fn new(direction: Vector3<f64>, irradiance: Color) -> DirectionalLight {
+        DirectionalLight {
+            direction: vunit(&direction),
+            irradiance,
+            cos_max: 1.0,
+        }
+    }
+
+    pub fn with_angular_diameter(mut self, diameter: f64) -> DirectionalLight {
+        self.cos_max = (diameter.clamp(0.0, 180.0) / 2.0).to_radians().cos();
+        self
+    }
+}
+
+impl Light for DirectionalLight {
+    fn sample(&self, _p: &Vector3<f64>) -> Option<LightSample> {
+        // Uniform over the disk, radiance times its solid angle is the irradiance
+        let cos_theta = 1.0 - rand_float01() * (1.0 - self.cos_max);
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * rand_float01();
+        let direction = Onb::build_from_w(&self.direction).local(
+            sin_theta * phi.cos(),
+            sin_theta * phi.sin(),
+            cos_theta,
+        );
+        Some(LightSample {
+            direction: vunit(&direction),
+            distance: f64::INFINITY,
+            incident: upsample(self.irradiance),
+        })
+    }
+
+    fn share(self) -> Arc<dyn Light> {
+        Arc::new(self)
+    }
+}
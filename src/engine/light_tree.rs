@@ -0,0 +1,362 @@
+use crate::data::{rand_float01, set_sample_source, SeededSource};
+use crate::engine::bound_box::AABB;
+use crate::engine::{HitRecord, Hittable, HittableList, Ray};
+use nalgebra::Vector3;
+use std::cell::RefCell;
+use std::f64::consts::PI;
+use std::rc::Rc;
+use std::sync::Arc;
+
+// Surface points looked at to estimate the power and facing of a light
+const MEASURE_SAMPLES: usize = 16;
+
+// Scene lights in a hierarchy for picking one to sample from a point, in proportion to
+// how much it could contribute there judging by power, distance and which way it faces
+// (after the light BVH of pbrt-v4). Paths starting on lights still pick them by area.
+pub struct LightTree {
+    lights: HittableList,
+    // None when some light has no bounds, those fall back to uniform picking
+    root: Option<Node>,
+}
+
+// What a light or a group of them could give off, conservatively
+#[derive(Clone, Copy)]
+struct LightBounds {
+    bounds: AABB,
+    power: f64,
+    // Every emitted direction is within theta of axis (half angle, PI for all of them)
+    axis: Vector3<f64>,
+    theta: f64,
+}
+
+enum Node {
+    Leaf {
+        light: usize,
+        bounds: LightBounds,
+    },
+    Interior {
+        bounds: LightBounds,
+        left: Box<Node>,
+        right: Box<Node>,
+    },
+}
+
+impl LightTree {
+    pub fn new(lights: HittableList) -> LightTree {
+        // The same points every time, so every process rendering the scene builds the
+        // same tree
+        let previous = set_sample_source(Some(Rc::new(RefCell::new(SeededSource::new(0)))));
+        let measured: Option<Vec<(usize, LightBounds)>> = lights
+            .objects()
+            .iter()
+            .enumerate()
+            .map(|(i, light)| measure(light).map(|bounds| (i, bounds)))
+            .collect();
+        set_sample_source(previous);
+        let root = match measured {
+            Some(mut measured) if !measured.is_empty() => Some(build(&mut measured)),
+            _ => None,
+        };
+        LightTree { lights, root }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lights.is_empty()
+    }
+
+    // Chance of picking the light in each child, from origin
+    fn split(left: &Node, right: &Node, origin: &Vector3<f64>) -> (f64, f64) {
+        let left = left.bounds().importance(origin);
+        let right = right.bounds().importance(origin);
+        match left + right > 0.0 {
+            true => (left / (left + right), right / (left + right)),
+            false => (0.5, 0.5),
+        }
+    }
+
+    // Index of a light picked from origin, walking down from the root
+    fn pick(&self, origin: &Vector3<f64>) -> Option<usize> {
+        let mut node = self.root.as_ref()?;
+        loop {
+            match node {
+                Node::Leaf { light, .. } => return Some(*light),
+                Node::Interior { left, right, .. } => {
+                    let (p_left, _) = LightTree::split(left, right, origin);
+                    node = match rand_float01() < p_left {
+                        true => left,
+                        false => right,
+                    };
+                }
+            }
+        }
+    }
+
+    fn node_pdf(
+        &self,
+        node: &Node,
+        origin: &Vector3<f64>,
+        direction: &Vector3<f64>,
+        pmf: f64,
+    ) -> f64 {
+        // Lights the direction misses add nothing
+        let ray = Ray::new(*origin, *direction);
+        if pmf <= 0.0 || !node.bounds().bounds.hit(&ray, 0.001, f64::INFINITY) {
+            return 0.0;
+        }
+        match node {
+            Node::Leaf { light, .. } => {
+                pmf * self.lights.objects()[*light].pdf_value(origin, direction)
+            }
+            Node::Interior { left, right, .. } => {
+                let (p_left, p_right) = LightTree::split(left, right, origin);
+                self.node_pdf(left, origin, direction, pmf * p_left)
+                    + self.node_pdf(right, origin, direction, pmf * p_right)
+            }
+        }
+    }
+}
+
+impl Hittable for LightTree {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, record: &mut HitRecord) -> bool {
+        self.lights.hit(r, t_min, t_max, record)
+    }
+
+    fn share(self) -> Arc<dyn Hittable> {
+        Arc::new(self)
+    }
+
+    fn get_bounding_box(&self) -> Option<AABB> {
+        self.lights.get_bounding_box()
+    }
+
+    // Sum over every light the direction hits of the chance of picking it times its density
+    fn pdf_value(&self, origin: &Vector3<f64>, direction: &Vector3<f64>) -> f64 {
+        match &self.root {
+            Some(root) => self.node_pdf(root, origin, direction, 1.0),
+            None => self.lights.pdf_value(origin, direction),
+        }
+    }
+
+    fn random(&self, origin: &Vector3<f64>) -> Vector3<f64> {
+        match self.pick(origin) {
+            Some(light) => self.lights.objects()[light].random(origin),
+            None => self.lights.random(origin),
+        }
+    }
+
+    fn area(&self) -> f64 {
+        self.lights.area()
+    }
+
+    fn sample_surface(&self) -> Option<HitRecord> {
+        self.lights.sample_surface()
+    }
+}
+
+impl Node {
+    fn bounds(&self) -> &LightBounds {
+        match self {
+            Node::Leaf { bounds, .. } => bounds,
+            Node::Interior { bounds, .. } => bounds,
+        }
+    }
+}
+
+impl LightBounds {
+    fn center(&self) -> Vector3<f64> {
+        (self.bounds.min() + self.bounds.max()) / 2.0
+    }
+
+    // Upper bound of what the lights could send to p, up to a constant
+    fn importance(&self, p: &Vector3<f64>) -> f64 {
+        let radius = (self.bounds.max() - self.bounds.min()).norm() / 2.0;
+        let to_p = p - self.center();
+        let distance_squared = to_p.dot(&to_p);
+        if distance_squared <= radius * radius {
+            return self.power / (radius * radius).max(1e-12);
+        }
+        // Angle to p off the axis, less the spread of the cone and the angle the
+        // bounds subtend from p
+        let theta_w = match self.theta < PI {
+            true => (self.axis.dot(&to_p) / distance_squared.sqrt())
+                .clamp(-1.0, 1.0)
+                .acos(),
+            false => 0.0,
+        };
+        let theta_b = (radius / distance_squared.sqrt()).asin();
+        let theta = (theta_w - self.theta - theta_b).max(0.0);
+        if theta >= PI / 2.0 {
+            return 0.0;
+        }
+        self.power * theta.cos() / distance_squared
+    }
+
+    fn union(&self, other: &LightBounds) -> LightBounds {
+        let (axis, theta) = union_cones(self.axis, self.theta, other.axis, other.theta);
+        LightBounds {
+            bounds: AABB::surrounding_box(self.bounds, other.bounds),
+            power: self.power + other.power,
+            axis,
+            theta,
+        }
+    }
+}
+
+// Smallest cone around both cones
+fn union_cones(
+    a: Vector3<f64>,
+    theta_a: f64,
+    b: Vector3<f64>,
+    theta_b: f64,
+) -> (Vector3<f64>, f64) {
+    let full = (a, PI);
+    if theta_a >= PI || theta_b >= PI {
+        return full;
+    }
+    let theta_d = a.dot(&b).clamp(-1.0, 1.0).acos();
+    if theta_d + theta_b <= theta_a {
+        return (a, theta_a);
+    }
+    if theta_d + theta_a <= theta_b {
+        return (b, theta_b);
+    }
+    let theta = (theta_a + theta_d + theta_b) / 2.0;
+    let around = a.cross(&b);
+    if theta >= PI || around.norm() < 1e-12 {
+        return full;
+    }
+    // Turn a towards b until it is in the middle of the new cone (Rodrigues)
+    let k = around.normalize();
+    let angle = theta - theta_a;
+    let axis = a * angle.cos() + k.cross(&a) * angle.sin() + k * k.dot(&a) * (1.0 - angle.cos());
+    (axis.normalize(), theta)
+}
+
+// Power and facing of a light estimated from points on it. Flat lights emitting from
+// one face only get a cone around their normal, everything else emits every way.
+fn measure(light: &Arc<dyn Hittable>) -> Option<LightBounds> {
+    let bounds = light.get_bounding_box()?;
+    let mut front = 0.0;
+    let mut back = 0.0;
+    let mut normals = Vec::with_capacity(MEASURE_SAMPLES);
+    for _ in 0..MEASURE_SAMPLES {
+        let mut record = light.sample_surface()?;
+        front += record.mat_ptr.emit(&record).luminance();
+        record.front_face = false;
+        back += record.mat_ptr.emit(&record).luminance();
+        normals.push(record.normal);
+    }
+    let area = light.area();
+    let radiance = (front + back) / MEASURE_SAMPLES as f64;
+    // Never quite zero, emission between the points looked at would be missed for good
+    let power = (PI * area * radiance).max(1e-6 * area);
+
+    let normal = normals[0];
+    let flat = normals.iter().all(|n| (n - normal).norm() < 1e-6);
+    let (axis, theta) = match (flat, front > 0.0, back > 0.0) {
+        (true, true, false) => (normal, 0.0),
+        (true, false, true) => (-normal, 0.0),
+        _ => (normal, PI),
+    };
+    Some(LightBounds {
+        bounds,
+        power,
+        axis,
+        theta,
+    })
+}
+
+// Splits lights at the median of their centres along the axis they spread most along
+fn build(lights: &mut [(usize, LightBounds)]) -> Node {
+    if lights.len() == 1 {
+        return Node::Leaf {
+            light: lights[0].0,
+            bounds: lights[0].1,
+        };
+    }
+    let centers: Vec<Vector3<f64>> = lights.iter().map(|(_, b)| b.center()).collect();
+    let extent = |axis: usize| {
+        let values = centers.iter().map(|c| c[axis]);
+        values.clone().fold(f64::NEG_INFINITY, f64::max) - values.fold(f64::INFINITY, f64::min)
+    };
+    let axis = (0..3)
+        .max_by(|&a, &b| extent(a).partial_cmp(&extent(b)).unwrap())
+        .unwrap();
+    lights.sort_by(|(_, a), (_, b)| a.center()[axis].partial_cmp(&b.center()[axis]).unwrap());
+
+    let (left, right) = lights.split_at_mut(lights.len() / 2);
+    let (left, right) = (build(left), build(right));
+    Node::Interior {
+        bounds: left.bounds().union(right.bounds()),
+        left: Box::new(left),
+        right: Box::new(right),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::Color;
+    use crate::engine::{Sphere, XZRect};
+    use crate::materials::DiffuseLight;
+
+    // Lights in different directions from the origin, of very different power
+    fn lights() -> HittableList {
+        let mut lights = HittableList::new();
+        let glow = |strength: f64| DiffuseLight::from_color(Color::new(1.0, 0.8, 0.6) * strength);
+        let spheres = [
+            (Vector3::new(10.0, 0.0, 0.0), 1.0, 4.0),
+            (Vector3::new(-6.0, 0.0, 0.0), 0.5, 1.0),
+            (Vector3::new(0.0, 0.0, 20.0), 2.0, 0.01),
+            (Vector3::new(0.0, 0.0, -3.0), 0.2, 50.0),
+            (Vector3::new(0.0, -8.0, 0.0), 1.0, 1e-4),
+        ];
+        for &(center, radius, strength) in spheres.iter() {
+            lights.add(Sphere::new(center, radius, glow(strength).share()).share());
+        }
+        lights.add(XZRect::new(-1.0, 1.0, -1.0, 1.0, 5.0, glow(2.0).share()).share());
+        lights
+    }
+
+    #[test]
+    fn pick_frequency_matches_pdf() {
+        let tree = LightTree::new(lights());
+        let root = tree.root.as_ref().unwrap();
+        let origin = Vector3::new(0.5, 0.2, -0.3);
+        let n = tree.lights.objects().len();
+
+        // Directions towards one light miss all the others, so the density of the tree
+        // there is the chance of picking it times its own density
+        let previous = set_sample_source(Some(Rc::new(RefCell::new(SeededSource::new(3)))));
+        let pmf: Vec<f64> = tree
+            .lights
+            .objects()
+            .iter()
+            .map(|light| {
+                let direction = light.random(&origin);
+                tree.node_pdf(root, &origin, &direction, 1.0) / light.pdf_value(&origin, &direction)
+            })
+            .collect();
+        assert!((pmf.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+        for &p in pmf.iter() {
+            assert!(p > 0.0, "light never picked: {:?}", pmf);
+        }
+
+        let samples = 200000;
+        let mut counts = vec![0; n];
+        for _ in 0..samples {
+            counts[tree.pick(&origin).unwrap()] += 1;
+        }
+        set_sample_source(previous);
+        for (count, p) in counts.iter().zip(pmf.iter()) {
+            let frequency = *count as f64 / samples as f64;
+            let sigma = (p * (1.0 - p) / samples as f64).sqrt();
+            assert!(
+                (frequency - p).abs() <= 5.0 * sigma + 1e-9,
+                "picked {} of the time, pdf says {}",
+                frequency,
+                p
+            );
+        }
+    }
+}
@@ -0,0 +1,320 @@
+// extern crates
+extern crate image;
+use indicatif::{ProgressBar, ProgressStyle};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+// crate imports
+use crate::data::{rand_float01, set_sample_source, stream_seed, Color, SampleSource};
+use crate::engine::Scene;
+
+// std imports
+use std::cell::RefCell;
+use std::f64::consts::PI;
+use std::rc::Rc;
+use std::sync::atomic::Ordering::Relaxed;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+// Primary sample space Metropolis light transport (Kelemen et al.).
+// A path is a function of the numbers handed out by rand_float01. Markov chains mutate
+// those numbers, either slightly (small steps) or by drawing all of them again (large
+// steps), and visit paths in proportion to their luminance. Every proposal is splatted
+// to the pixel it lands on, so the film fills up across pixels rather than pixel by pixel.
+
+const N_BOOTSTRAP: u64 = 100000;
+const CHAINS_PER_WORKER: u64 = 16;
+const SIGMA: f64 = 0.01;
+const LARGE_STEP_PROBABILITY: f64 = 0.3;
+
+#[derive(Clone, Copy)]
+struct PrimarySample {
+    value: f64,
+    last_modification: u64,
+    value_backup: f64,
+    modify_backup: u64,
+}
+
+// Lazily grown primary sample vector. Coordinates are only brought up to date with
+// the current iteration once a path asks for them.
+struct MltSampler {
+    rng: StdRng,
+    x: Vec<PrimarySample>,
+    current_iteration: u64,
+    large_step: bool,
+    last_large_step_iteration: u64,
+    sample_index: usize,
+}
+
+impl MltSampler {
+    fn new(seed: u64) -> MltSampler {
+        MltSampler {
+            rng: StdRng::seed_from_u64(seed),
+            x: Vec::new(),
+            current_iteration: 0,
+            large_step: true,
+            last_large_step_iteration: 0,
+            sample_index: 0,
+        }
+    }
+
+    fn start_iteration(&mut self) {
+        self.current_iteration += 1;
+        self.large_step = self.rng.gen::<f64>() < LARGE_STEP_PROBABILITY;
+        self.sample_index = 0;
+    }
+
+    fn accept(&mut self) {
+        if self.large_step {
+            self.last_large_step_iteration = self.current_iteration;
+        }
+    }
+
+    fn reject(&mut self) {
+        for xi in self.x.iter_mut() {
+            if xi.last_modification == self.current_iteration {
+                xi.value = xi.value_backup;
+                xi.last_modification = xi.modify_backup;
+            }
+        }
+        self.current_iteration -= 1;
+    }
+
+    // Box-Muller
+    fn normal(&mut self) -> f64 {
+        let u1: f64 = 1.0 - self.rng.gen::<f64>();
+        let u2: f64 = self.rng.gen();
+        (-2.0 * u1.ln()).sqrt() * (2.0 * PI * u2).cos()
+    }
+
+    fn ensure_ready(&mut self, index: usize) {
+        // Coordinates a path has not asked for before start out uniformly distributed,
+        // as if drawn by the last large step
+        while index >= self.x.len() {
+            let value = self.rng.gen();
+            self.x.push(PrimarySample {
+                value,
+                last_modification: self.last_large_step_iteration,
+                value_backup: value,
+                modify_backup: self.last_large_step_iteration,
+            });
+        }
+        let mut xi = self.x[index];
+        // Coordinates untouched since the last accepted large step are redrawn
+        if xi.last_modification < self.last_large_step_iteration {
+            xi.value = self.rng.gen();
+            xi.last_modification = self.last_large_step_iteration;
+        }
+        xi.value_backup = xi.value;
+        xi.modify_backup = xi.last_modification;
+
+        if self.large_step {
+            xi.value = self.rng.gen();
+        } else {
+            // All small steps missed by this coordinate combined into one
+            let n_small = (self.current_iteration - xi.last_modification) as f64;
+            xi.value += self.normal() * SIGMA * n_small.sqrt();
+            xi.value -= xi.value.floor();
+        }
+        xi.last_modification = self.current_iteration;
+        self.x[index] = xi;
+    }
+}
+
+impl SampleSource for MltSampler {
+    fn next(&mut self) -> f64 {
+        let index = self.sample_index;
+        self.sample_index += 1;
+        self.ensure_ready(index);
+        self.x[index].value
+    }
+}
+
+struct PathSample {
+    pixel: usize,
+    color: Color,
+    luminance: f64,
+}
+
+// Runs the scene integrator with every random number taken from sampler.
+// The first two numbers pick the position on the film.
+fn evaluate(sampler: &Rc<RefCell<MltSampler>>, scene: &Scene) -> PathSample {
+    let source: Rc<RefCell<dyn SampleSource>> = sampler.clone();
+    let previous = set_sample_source(Some(source));
+
+    let x = rand_float01() * scene.image_width;
+    let y = rand_float01() * scene.image_height;
+    let r = scene
+        .cam
+        .get_ray(x / (scene.image_width - 1.0), 1.0 - y / (scene.image_height - 1.0));
+    let color = scene.integrator.ray_color(&r, scene);
+
+    set_sample_source(previous);
+
+    let column = (x as usize).min(scene.image_width as usize - 1);
+    let row = (y as usize).min(scene.image_height as usize - 1);
+    let mut luminance = color.luminance();
+    if color.check_not_nan() || luminance < 0.0 {
+        luminance = 0.0;
+    }
+    PathSample {
+        pixel: row * scene.image_width as usize + column,
+        color,
+        luminance,
+    }
+}
+
+// Luminance of N_BOOTSTRAP independent paths, the sampler of path i is seeded with
+// stream i of the scene seed
+fn bootstrap(scene: Arc<Scene>, n_workers: u64) -> Vec<f64> {
+    let per_worker = N_BOOTSTRAP / n_workers + 1;
+    let handles: Vec<_> = (0..n_workers)
+        .map(|worker| {
+            let scene = scene.clone();
+            thread::spawn(move || {
+                let from = worker * per_worker;
+                let to = ((worker + 1) * per_worker).min(N_BOOTSTRAP);
+                (from..to)
+                    .map(|index| {
+                        let seed = stream_seed(scene.seed, index);
+                        let sampler = Rc::new(RefCell::new(MltSampler::new(seed)));
+                        evaluate(&sampler, &scene).luminance
+                    })
+                    .collect::<Vec<f64>>()
+            })
+        })
+        .collect();
+
+    let mut weights = Vec::new();
+    for handle in handles {
+        weights.extend(handle.join().unwrap());
+    }
+    weights
+}
+
+// Starts from the bootstrap path drawn with start_seed, then mutates it with numbers
+// from rng, which is the chain's own so chains starting from the same path part ways
+fn run_chain(
+    scene: &Scene,
+    start_seed: u64,
+    mut rng: StdRng,
+    n_mutations: u64,
+    film: &mut [Color],
+    progress: &ProgressBar,
+) {
+    let sampler = Rc::new(RefCell::new(MltSampler::new(start_seed)));
+    let mut current = evaluate(&sampler, scene);
+    std::mem::swap(&mut sampler.borrow_mut().rng, &mut rng);
+
+    for i in 0..n_mutations {
+        sampler.borrow_mut().start_iteration();
+        let proposed = evaluate(&sampler, scene);
+        let accept = match current.luminance > 0.0 {
+            true => (proposed.luminance / current.luminance).min(1.0),
+            false => 1.0,
+        };
+
+        // Both states are splatted, weighted by how likely the chain is to be in them
+        if accept > 0.0 && proposed.luminance > 0.0 {
+            film[proposed.pixel] =
+                film[proposed.pixel] + proposed.color * (accept / proposed.luminance);
+        }
+        if accept < 1.0 {
+            film[current.pixel] =
+                film[current.pixel] + current.color * ((1.0 - accept) / current.luminance);
+        }
+
+        if sampler.borrow_mut().rng.gen::<f64>() < accept {
+            current = proposed;
+            sampler.borrow_mut().accept();
+        } else {
+            sampler.borrow_mut().reject();
+        }
+        if i % 1000 == 999 {
+            progress.inc(1000);
+        }
+    }
+}
+
+pub fn render_mlt(scene: Scene, n_workers: u64, path: String) {
+    let n_pixels = (scene.image_width * scene.image_height) as u64;
+    let total_mutations = scene.samples_per_pixel * n_pixels;
+    let scene = Arc::new(scene);
+
+    println!("bootstrapping...");
+    let weights = bootstrap(scene.clone(), n_workers);
+    // Average luminance over the image, the normalization of the chains
+    let b = weights.iter().sum::<f64>() / weights.len() as f64;
+    if b <= 0.0 {
+        println!("No light reaches the camera, nothing to render");
+        return;
+    }
+    let mut cdf = Vec::with_capacity(weights.len());
+    let mut running = 0.0;
+    for w in weights.iter() {
+        running += w;
+        cdf.push(running);
+    }
+    let cdf = Arc::new(cdf);
+
+    let pb = ProgressBar::new(total_mutations);
+    pb.set_style(
+        ProgressStyle::default_bar()
+            .template("{spinner:.green} [{elapsed}] [{wide_bar}] {pos}/{len} ({eta})"),
+    );
+    let pb = Arc::new(pb);
+
+    let film = Arc::new(Mutex::new(vec![Color::new(0.0, 0.0, 0.0); n_pixels as usize]));
+    let n_chains = n_workers * CHAINS_PER_WORKER;
+    let mutations_per_chain = total_mutations / n_chains;
+
+    let handles: Vec<_> = (0..n_workers)
+        .map(|worker| {
+            let scene = scene.clone();
+            let cdf = cdf.clone();
+            let film = film.clone();
+            let pb = pb.clone();
+            thread::spawn(move || {
+                let mut local = vec![Color::new(0.0, 0.0, 0.0); n_pixels as usize];
+                for chain in 0..CHAINS_PER_WORKER {
+                    // Streams after the bootstrap ones belong to the chains
+                    let chain = worker * CHAINS_PER_WORKER + chain;
+                    let chain_seed = stream_seed(scene.seed, N_BOOTSTRAP + chain);
+                    let mut rng = StdRng::seed_from_u64(chain_seed);
+                    // Chains start from bootstrap paths picked in proportion to luminance
+                    let target = rng.gen::<f64>() * cdf[cdf.len() - 1];
+                    let start = cdf.partition_point(|&c| c < target) as u64;
+                    let start_seed = stream_seed(scene.seed, start);
+                    run_chain(
+                        &scene,
+                        start_seed,
+                        rng,
+                        mutations_per_chain,
+                        &mut local,
+                        &pb,
+                    );
+                }
+                let mut film = film.lock().unwrap();
+                for (pixel, splat) in film.iter_mut().zip(local.iter()) {
+                    *pixel = *pixel + splat;
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    pb.finish_with_message("Your image is complete, sir! Enjoy!");
+
+    let scale = n_pixels as f64 * b / (mutations_per_chain * n_chains) as f64;
+    let film = film.lock().unwrap();
+    let width = scene.image_width as u32;
+    let mut imgbuf = image::ImageBuffer::new(width, scene.image_height as u32);
+    for (x, y, pixel) in imgbuf.enumerate_pixels_mut() {
+        let color = film[(y * width + x) as usize] * scale;
+        *pixel = color.gamma_2().to_rgba();
+    }
+    println!("saving...");
+    imgbuf.save(path).unwrap();
+    scene.completed.store(true, Relaxed);
+}
@@ -1,13 +1,30 @@
+pub mod background;
 pub mod camera;
+pub mod environment;
 pub mod hittable_list;
 pub mod geometry;
 pub mod hittable;
+pub mod light;
+pub mod light_tree;
+pub mod mlt;
 pub mod ray;
 pub mod scene;
+pub mod sky;
 pub mod bound_box;
+pub use crate::engine::background::{
+    Background, ConstantBackground, GradientBackground, TextureBackground,
+};
 pub use crate::engine::camera::Camera;
+pub use crate::engine::environment::EnvironmentLight;
 pub use crate::engine::hittable::{HitRecord, Hittable};
 pub use crate::engine::hittable_list::HittableList;
+pub use crate::engine::light::{DirectionalLight, Light, PointLight, SpotLight};
+pub use crate::engine::light_tree::LightTree;
+pub use crate::engine::mlt::render_mlt;
 pub use crate::engine::ray::Ray;
-pub use crate::engine::scene::{render, Scene};
-pub use crate::engine::geometry::{ Sphere, XYRect, XZRect, YZRect };
+pub use crate::engine::scene::{pixel_color, render, ProducerRange, Scene};
+pub use crate::engine::sky::Sky;
+pub use crate::engine::geometry::{
+    DensityField, DensityGrid, NoiseDensity, Sphere, Volume, XYRect, XZRect, YZRect,
+};
+pub use crate::engine::bound_box::BvhNode;
@@ -1,16 +1,17 @@
 // extern crates
 extern crate image;
 use indicatif::{ProgressBar, ProgressStyle};
-use nalgebra::Vector3;
 
 // crate imports
 use crate::data::Color;
-use crate::data::{rand_float01, vunit};
-use crate::engine::{Camera, HitRecord, Hittable, HittableList, Ray};
+use crate::data::{rand_float01, set_sample_source, stream_seed, SampleSource, SeededSource};
+use crate::engine::{Background, Camera, HittableList, Light, LightTree, Ray};
 use crate::gui::render_window;
+use crate::integrators::Integrator;
 
 // std imports
-use std::f64::INFINITY;
+use std::cell::RefCell;
+use std::rc::Rc;
 use std::sync::atomic::{AtomicBool, Ordering::Relaxed};
 use std::sync::{mpsc, Arc, Mutex};
 use std::thread;
@@ -25,48 +26,57 @@ struct SimplePixel {
 pub struct Scene {
     pub cam: Arc<Camera>,
     pub world: Arc<HittableList>,
+    pub lights: Arc<LightTree>,
+    pub punctual_lights: Vec<Arc<dyn Light>>,
+    pub integrator: Arc<dyn Integrator>,
     pub samples_per_pixel: u64,
+    // Every pixel draws its numbers from its own stream derived from this
+    pub seed: u64,
     pub max_depth: i32,
+    pub rr_min_depth: i32,
     pub image_width: f64,
     pub image_height: f64,
     pub prerender_finished: Arc<AtomicBool>,
     pub completed: Arc<AtomicBool>,
-    pub background: Color,
+    // Seen by rays leaving the scene, and sampled as a light if it can be
+    pub background: Arc<dyn Background>,
+    // Seen instead by rays straight from the camera, which light nothing
+    pub camera_background: Option<Arc<dyn Background>>,
 }
 
-fn ray_color(r: &Ray, background: Color, world: Arc<HittableList>, depth: i32) -> Color {
-    let mut record = HitRecord::new();
-    if depth <= 0 {
-        return Color::new(0.0, 0.0, 0.0);
-    }
-    if !world.hit(r, 0.001, INFINITY, &mut record) {
-        return background;
-    }
-    let mut scattered = Ray::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, 0.0));
-    let mut attenuation = Color::new(0.0, 0.0, 0.0);
-    let emmited = record.mat_ptr.emit(record.u, record.v, &record.p);
-    if !record
-        .mat_ptr
-        .scatter(r, &record, &mut attenuation, &mut scattered)
-    {
-        return emmited;
+impl Scene {
+    // Light arriving along a ray that leaves the scene
+    pub fn escaped(&self, r: &Ray, camera_ray: bool) -> Color {
+        match (&self.camera_background, camera_ray) {
+            (Some(background), true) => background.radiance(&r.direction()),
+            _ => self.background.radiance(&r.direction()),
+        }
     }
-    return emmited + attenuation * ray_color(&scattered, background, world, depth - 1);
 }
 
-fn pixel_processor(x: f64, y: f64, scene: Arc<Scene>) -> image::Rgba<u8> {
+// Linear (HDR) average of all samples for the pixel, before gamma correction. A pixel
+// comes out the same whichever thread or worker renders it.
+pub fn pixel_color(x: f64, y: f64, scene: &Scene) -> Color {
+    let stream = y as u64 * scene.image_width as u64 + x as u64;
+    let source: Rc<RefCell<dyn SampleSource>> =
+        Rc::new(RefCell::new(SeededSource::new(stream_seed(scene.seed, stream))));
+    let previous = set_sample_source(Some(source));
+
     let mut pixel_color = Color::new(0.0, 0.0, 0.0);
     for _ in 0..scene.samples_per_pixel {
         let u = (x + rand_float01()) / (scene.image_width - 1.0);
         let v = (y + rand_float01()) / (scene.image_height - 1.0);
         let v = 1.0 - v;
         let r = scene.cam.get_ray(u, v);
-        let rc = ray_color(&r, scene.background, scene.world.clone(), scene.max_depth);
+        let rc = scene.integrator.ray_color(&r, scene);
         pixel_color = pixel_color + rc;
     }
-    pixel_color
-        .normalize_samples(scene.samples_per_pixel)
-        .to_rgba()
+    set_sample_source(previous);
+    pixel_color / scene.samples_per_pixel as f64
+}
+
+fn pixel_processor(x: f64, y: f64, scene: Arc<Scene>) -> image::Rgba<u8> {
+    pixel_color(x, y, &scene).gamma_2().to_rgba()
 }
 
 #[derive(Clone, Copy)]
@@ -88,7 +98,7 @@ fn send(
     scene: Arc<Scene>,
     tx: Arc<Mutex<mpsc::Sender<SimplePixel>>>,
     range: ProducerRange,
-    success_table: &mut Vec<Vec<bool>>,
+    success_table: &mut [Vec<bool>],
 ) {
     let color = pixel_processor(x as f64, y as f64, scene.clone());
     let pixel = SimplePixel { x, y, color };
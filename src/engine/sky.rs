@@ -0,0 +1,204 @@
+use crate::data::{xyz_to_rgb, Color};
+use crate::engine::{DirectionalLight, EnvironmentLight};
+use nalgebra::Vector3;
+use std::f64::consts::PI;
+
+// From the model's kcd/m^2 to scene radiance, lit surfaces come out around 1 at midday
+const SCALE: f64 = 0.03;
+// Illuminance of the sun above the atmosphere, klx
+const SUN_ILLUMINANCE: f64 = 128.0;
+const SUN_DIAMETER: f64 = 0.53;
+
+// Clear daylight sky after Preetham, Shirley and Smits (1999). Azimuths are in degrees
+// from north (-z) towards east (+x). Turbidity runs from 2 (very clear) to about 10
+// (hazy). Below the horizon is a flat ground reflecting ground_albedo of the light
+// falling on it. The sky lights the scene as an environment map and the sun as a
+// directional light, so it doesn't appear in the sky itself.
+pub struct Sky {
+    sun: Vector3<f64>,
+    turbidity: f64,
+    ground_albedo: Color,
+    intensity: f64,
+}
+
+impl Sky {
+    pub fn new(elevation: f64, azimuth: f64) -> Sky {
+        // The model breaks down for a sun below the horizon
+        let elevation = elevation.clamp(0.0, 90.0).to_radians();
+        let azimuth = azimuth.to_radians();
+        Sky {
+            sun: Vector3::new(
+                elevation.cos() * azimuth.sin(),
+                elevation.sin(),
+                -elevation.cos() * azimuth.cos(),
+            ),
+            turbidity: 3.0,
+            ground_albedo: Color::new(0.3, 0.3, 0.3),
+            intensity: 1.0,
+        }
+    }
+
+    // Sun position seen from a place at a time, latitude and longitude in degrees
+    // (north and east positive), day of the year from 1 and hour in UTC
+    pub fn from_location(latitude: f64, longitude: f64, day: f64, hour: f64) -> Sky {
+        let latitude = latitude.to_radians();
+        // Solar time with the equation of time, then the hour angle
+        let solar_time = hour + longitude / 15.0 + 0.170 * (4.0 * PI * (day - 80.0) / 373.0).sin()
+            - 0.129 * (2.0 * PI * (day - 8.0) / 355.0).sin();
+        let hour_angle = PI * (solar_time - 12.0) / 12.0;
+        let declination = 0.4093 * (2.0 * PI * (day - 81.0) / 368.0).sin();
+
+        let up = latitude.sin() * declination.sin()
+            + latitude.cos() * declination.cos() * hour_angle.cos();
+        let east = -declination.cos() * hour_angle.sin();
+        let north = latitude.cos() * declination.sin()
+            - latitude.sin() * declination.cos() * hour_angle.cos();
+        let elevation = up.clamp(-1.0, 1.0).asin().to_degrees();
+        Sky::new(elevation, east.atan2(north).to_degrees())
+    }
+
+    pub fn with_turbidity(mut self, turbidity: f64) -> Sky {
+        self.turbidity = turbidity.clamp(1.7, 10.0);
+        self
+    }
+
+    pub fn with_ground_albedo(mut self, albedo: Color) -> Sky {
+        self.ground_albedo = albedo;
+        self
+    }
+
+    pub fn with_intensity(mut self, intensity: f64) -> Sky {
+        self.intensity = intensity;
+        self
+    }
+
+    // Sky and ground baked into a map of width by width / 2 pixels
+    pub fn environment(&self, width: usize) -> EnvironmentLight {
+        let model = Preetham::new(&self.sun, self.turbidity);
+        let sky = |direction: &Vector3<f64>| model.radiance(direction) * (SCALE * self.intensity);
+
+        // Light falling on the ground, sun plus sky by midpoint integration
+        let (n_theta, n_phi) = (32, 64);
+        let mut irradiance = self.sun_irradiance() * self.sun[1];
+        for i in 0..n_theta {
+            let theta = (i as f64 + 0.5) / n_theta as f64 * PI / 2.0;
+            let solid_angle = theta.sin() * (PI / 2.0 / n_theta as f64) * (2.0 * PI / n_phi as f64);
+            for j in 0..n_phi {
+                let phi = (j as f64 + 0.5) / n_phi as f64 * 2.0 * PI;
+                let direction = Vector3::new(
+                    theta.sin() * phi.cos(),
+                    theta.cos(),
+                    theta.sin() * phi.sin(),
+                );
+                irradiance = irradiance + sky(&direction) * (theta.cos() * solid_angle);
+            }
+        }
+        let ground = self.ground_albedo * irradiance / PI;
+
+        let width = width.max(2);
+        EnvironmentLight::from_function(width, width / 2, |direction| match direction[1] > 0.0 {
+            true => sky(direction),
+            false => ground,
+        })
+    }
+
+    pub fn sun(&self) -> DirectionalLight {
+        DirectionalLight::new(self.sun, self.sun_irradiance()).with_angular_diameter(SUN_DIAMETER)
+    }
+
+    // Facing the sun, after Rayleigh and aerosol extinction along its path through the
+    // atmosphere at 650, 550 and 450nm
+    fn sun_irradiance(&self) -> Color {
+        let theta = self.sun[1].clamp(0.0, 1.0).acos();
+        let air_mass =
+            1.0 / (theta.cos() + 0.15 * (93.885 - theta.to_degrees()).max(1e-3).powf(-1.253));
+        let beta = 0.04608 * self.turbidity - 0.04586;
+        let transmittance = |wavelength: f64| {
+            let rayleigh = (-0.008735 * wavelength.powf(-4.08) * air_mass).exp();
+            let aerosol = (-beta * wavelength.powf(-1.3) * air_mass).exp();
+            rayleigh * aerosol
+        };
+        Color::new(
+            transmittance(0.65),
+            transmittance(0.55),
+            transmittance(0.45),
+        ) * (SUN_ILLUMINANCE * SCALE * self.intensity)
+    }
+}
+
+// Perez distribution of luminance and chromaticity over the sky, fitted for turbidity
+struct Preetham {
+    sun: Vector3<f64>,
+    // Perez coefficients and zenith value, for Y, x and y
+    perez: [[f64; 5]; 3],
+    zenith: [f64; 3],
+}
+
+impl Preetham {
+    fn new(sun: &Vector3<f64>, t: f64) -> Preetham {
+        let perez = [
+            [
+                0.1787 * t - 1.4630,
+                -0.3554 * t + 0.4275,
+                -0.0227 * t + 5.3251,
+                0.1206 * t - 2.5771,
+                -0.0670 * t + 0.3703,
+            ],
+            [
+                -0.0193 * t - 0.2592,
+                -0.0665 * t + 0.0008,
+                -0.0004 * t + 0.2125,
+                -0.0641 * t - 0.8989,
+                -0.0033 * t + 0.0452,
+            ],
+            [
+                -0.0167 * t - 0.2608,
+                -0.0950 * t + 0.0092,
+                -0.0079 * t + 0.2102,
+                -0.0441 * t - 1.6537,
+                -0.0109 * t + 0.0529,
+            ],
+        ];
+
+        let theta = sun[1].clamp(0.0, 1.0).acos();
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta);
+        let luminance = ((4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192).max(0.0);
+        let (t2, th, th2, th3) = (t * t, theta, theta * theta, theta * theta * theta);
+        let x = t2 * (0.00166 * th3 - 0.00375 * th2 + 0.00209 * th)
+            + t * (-0.02903 * th3 + 0.06377 * th2 - 0.03202 * th + 0.00394)
+            + (0.11693 * th3 - 0.21196 * th2 + 0.06052 * th + 0.25886);
+        let y = t2 * (0.00275 * th3 - 0.00610 * th2 + 0.00317 * th)
+            + t * (-0.04214 * th3 + 0.08970 * th2 - 0.04153 * th + 0.00516)
+            + (0.15346 * th3 - 0.26756 * th2 + 0.06670 * th + 0.26688);
+
+        // Values are relative to the zenith, where theta is 0 and gamma the sun angle
+        let mut zenith = [luminance, x, y];
+        for (value, coefficients) in zenith.iter_mut().zip(perez.iter()) {
+            *value /= perez_function(coefficients, 1.0, theta);
+        }
+        Preetham {
+            sun: *sun,
+            perez,
+            zenith,
+        }
+    }
+
+    fn radiance(&self, direction: &Vector3<f64>) -> Color {
+        let d = direction.normalize();
+        // Keep off the horizon where the fit blows up
+        let cos_theta = d[1].max(0.01);
+        let gamma = d.dot(&self.sun).clamp(-1.0, 1.0).acos();
+        let value = |i: usize| self.zenith[i] * perez_function(&self.perez[i], cos_theta, gamma);
+        let (luminance, x, y) = (value(0), value(1), value(2));
+        if y <= 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        let rgb = xyz_to_rgb(x * luminance / y, luminance, (1.0 - x - y) * luminance / y);
+        Color::new(rgb.r().max(0.0), rgb.g().max(0.0), rgb.b().max(0.0))
+    }
+}
+
+fn perez_function(c: &[f64; 5], cos_theta: f64, gamma: f64) -> f64 {
+    (1.0 + c[0] * (c[1] / cos_theta).exp())
+        * (1.0 + c[2] * (c[3] * gamma).exp() + c[4] * gamma.cos() * gamma.cos())
+}
@@ -37,7 +37,7 @@ pub fn render_window(
         let mut last_updated = Instant::now();
 
         while let Some(e) = window.next() {
-            if let Some(_) = e.render_args() {
+            if e.render_args().is_some() {
                 if last_updated.elapsed().as_millis() > update_every_ms {
                     texture
                         .update(&mut texture_context, &canvas.lock().unwrap().clone())
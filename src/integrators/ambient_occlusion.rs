@@ -0,0 +1,41 @@
+use crate::data::{vrandom_cosine_direction, vrandom_in_unit_sphere, Color, Onb};
+use crate::engine::{HitRecord, Hittable, Ray, Scene};
+use crate::integrators::Integrator;
+use std::sync::Arc;
+
+// White where the hemisphere above the first hit is open, black where it is
+// blocked by geometry closer than distance
+pub struct AmbientOcclusion {
+    distance: f64,
+}
+
+impl AmbientOcclusion {
+    pub fn new(distance: f64) -> AmbientOcclusion {
+        AmbientOcclusion { distance }
+    }
+}
+
+impl Integrator for AmbientOcclusion {
+    fn ray_color(&self, r: &Ray, scene: &Scene) -> Color {
+        let mut record = HitRecord::new();
+        if !scene.world.hit(r, 0.001, f64::INFINITY, &mut record) {
+            return Color::new(1.0, 1.0, 1.0);
+        }
+        // Cosine weighted, so one unoccluded sample simply counts as white. Points
+        // inside a medium have no normal, the whole sphere around them counts.
+        let direction = match record.normal.norm() > 0.0 {
+            true => Onb::build_from_w(&record.normal).local_vec(&vrandom_cosine_direction()),
+            false => vrandom_in_unit_sphere(),
+        };
+        let mut occluder = HitRecord::new();
+        let occlusion_ray = Ray::new(record.p, direction);
+        if scene.world.hit(&occlusion_ray, 0.001, self.distance, &mut occluder) {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        Color::new(1.0, 1.0, 1.0)
+    }
+
+    fn share(self) -> Arc<dyn Integrator> {
+        Arc::new(self)
+    }
+}
@@ -0,0 +1,343 @@
+use crate::data::{rand_float01, vrandom_cosine_direction, Color, Onb};
+use crate::engine::{HitRecord, Hittable, Ray, Scene};
+use crate::integrators::lighting::sample_punctual_lights;
+use crate::integrators::Integrator;
+use nalgebra::Vector3;
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+// Bidirectional path tracer (Veach). A camera subpath and a light subpath starting on
+// one of the scene lights are traced, then every prefix of one is connected to every
+// prefix of the other. Each connection strategy is weighted with the power heuristic
+// over all strategies that could have produced the same path.
+//
+// Light subpaths are never connected straight to the camera (t = 1), so there is no
+// splatting onto other pixels, the strategy is simply left out of the weights.
+pub struct BidirectionalPathTracer {}
+
+impl BidirectionalPathTracer {
+    pub fn new() -> BidirectionalPathTracer {
+        BidirectionalPathTracer {}
+    }
+}
+
+#[derive(Clone)]
+struct Vertex {
+    record: HitRecord,
+    // Ray that arrived at the vertex, None for the camera and light origins
+    r_in: Option<Ray>,
+    // Path throughput up to and including this vertex
+    beta: Color,
+    // Area densities of sampling this vertex from the previous one, and in reverse
+    pdf_fwd: f64,
+    pdf_rev: f64,
+    delta: bool,
+}
+
+impl Vertex {
+    fn p(&self) -> Vector3<f64> {
+        self.record.p
+    }
+
+    fn is_light(&self) -> bool {
+        self.r_in.is_none()
+    }
+
+    // Converts a solid angle density at this vertex into an area density at next
+    fn convert_density(&self, pdf: f64, next: &Vertex) -> f64 {
+        let w = next.p() - self.p();
+        let distance_squared = w.dot(&w);
+        if distance_squared == 0.0 {
+            return 0.0;
+        }
+        // Points inside a medium have no normal, nothing to foreshorten
+        let cosine = match next.record.normal.norm() > 0.0 {
+            true => (next.record.normal.dot(&w) / distance_squared.sqrt()).abs(),
+            false => 1.0,
+        };
+        pdf * cosine / distance_squared
+    }
+
+    // Area density at next of continuing the path from prev through this vertex
+    fn pdf(&self, prev: Option<&Vertex>, next: &Vertex) -> f64 {
+        let direction = next.p() - self.p();
+        let pdf = match prev {
+            // Emitted directions are cosine distributed on either side of the light
+            None => {
+                let cosine = self.record.normal.dot(&direction) / direction.norm();
+                cosine.abs() / (2.0 * PI)
+            }
+            Some(prev) => {
+                let r_in = Ray::new(prev.p(), self.p() - prev.p());
+                self.record.mat_ptr.pdf(&r_in, &self.record, &direction)
+            }
+        };
+        self.convert_density(pdf, next)
+    }
+
+    // BSDF times cosine for light travelling between this vertex and towards
+    fn eval(&self, towards: &Vertex) -> Color {
+        let direction = towards.p() - self.p();
+        match &self.r_in {
+            Some(r_in) => self.record.mat_ptr.eval(r_in, &self.record, &direction),
+            None => Color::new(0.0, 0.0, 0.0),
+        }
+    }
+
+    fn emitted(&self) -> Color {
+        self.record.mat_ptr.emit(&self.record)
+    }
+
+    // Radiance a light vertex sends towards another vertex, from whichever face of the
+    // light that one is on. Sampled light records have the outward normal.
+    fn emitted_towards(&self, towards: &Vertex) -> Color {
+        let mut record = self.record.clone();
+        record.front_face = record.normal.dot(&(towards.p() - self.p())) > 0.0;
+        record.mat_ptr.emit(&record)
+    }
+}
+
+fn camera_vertex(r: &Ray) -> Vertex {
+    let mut record = HitRecord::new();
+    record.p = r.origin();
+    Vertex {
+        record,
+        r_in: None,
+        beta: Color::new(1.0, 1.0, 1.0),
+        pdf_fwd: 0.0,
+        pdf_rev: 0.0,
+        delta: false,
+    }
+}
+
+// Extends path from ray until it escapes, is absorbed or max_depth is reached.
+// pdf is the solid angle density with which ray was sampled.
+// Returns the background seen by an escaping path, weighted by its throughput.
+fn random_walk(
+    scene: &Scene,
+    mut ray: Ray,
+    mut beta: Color,
+    pdf: f64,
+    path: &mut Vec<Vertex>,
+) -> Color {
+    let mut pdf_fwd = pdf;
+    for depth in 0..scene.max_depth {
+        let mut record = HitRecord::new();
+        if !scene.world.hit(&ray, 0.001, f64::INFINITY, &mut record) {
+            return beta * scene.escaped(&ray, depth == 0);
+        }
+        let mut vertex = Vertex {
+            record,
+            r_in: Some(ray),
+            beta,
+            pdf_fwd: 0.0,
+            pdf_rev: 0.0,
+            delta: false,
+        };
+        vertex.pdf_fwd = path.last().unwrap().convert_density(pdf_fwd, &vertex);
+
+        let mut scattered = Ray::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, 0.0));
+        let mut attenuation = Color::new(0.0, 0.0, 0.0);
+        let scatters = vertex
+            .record
+            .mat_ptr
+            .scatter(&ray, &vertex.record, &mut attenuation, &mut scattered);
+        if !scatters {
+            path.push(vertex);
+            break;
+        }
+
+        pdf_fwd = vertex.record.mat_ptr.pdf(&ray, &vertex.record, &scattered.direction());
+        let mut pdf_rev = 0.0;
+        if pdf_fwd <= 0.0 {
+            vertex.delta = true;
+        } else {
+            let reverse = Ray::new(vertex.p() + scattered.direction(), -scattered.direction());
+            pdf_rev = vertex
+                .record
+                .mat_ptr
+                .pdf(&reverse, &vertex.record, &(-ray.direction()));
+        }
+        beta = beta * attenuation;
+
+        let n = path.len();
+        path[n - 1].pdf_rev = vertex.convert_density(pdf_rev, &path[n - 1]);
+        path.push(vertex);
+
+        if depth >= scene.rr_min_depth {
+            let survive_prob = beta.max_component().min(0.95);
+            if rand_float01() >= survive_prob {
+                break;
+            }
+            beta = beta / survive_prob;
+        }
+        ray = scattered;
+    }
+    Color::new(0.0, 0.0, 0.0)
+}
+
+fn light_subpath(scene: &Scene, path: &mut Vec<Vertex>) {
+    let total_area = scene.lights.area();
+    let record = match scene.lights.sample_surface() {
+        Some(record) => record,
+        None => return,
+    };
+    let pdf_pos = 1.0 / total_area;
+
+    // Pick a face and a cosine weighted direction on it. One sided lights emit nothing
+    // from the back, which stays in the sampling so the pdfs match either way.
+    let front = rand_float01() >= 0.5;
+    let normal = if front { record.normal } else { -record.normal };
+    let direction = Onb::build_from_w(&normal).local_vec(&vrandom_cosine_direction());
+    let cosine = normal.dot(&direction);
+    let pdf_dir = cosine / (2.0 * PI);
+
+    let origin = record.p;
+    let mut facing = record.clone();
+    facing.front_face = front;
+    let emitted = facing.mat_ptr.emit(&facing);
+    // Emission depends on the direction, so it joins the throughput past the light
+    let light = Vertex {
+        record,
+        r_in: None,
+        beta: Color::new(1.0, 1.0, 1.0) / pdf_pos,
+        pdf_fwd: pdf_pos,
+        pdf_rev: 0.0,
+        delta: false,
+    };
+    let beta = light.beta * emitted * cosine / pdf_dir;
+    path.push(light);
+    random_walk(scene, Ray::new(origin, direction), beta, pdf_dir, path);
+}
+
+fn visible(a: &Vertex, b: &Vertex, scene: &Scene) -> bool {
+    let mut record = HitRecord::new();
+    let ray = Ray::new(a.p(), b.p() - a.p());
+    !scene.world.hit(&ray, 0.001, 0.999, &mut record)
+}
+
+// Balance of the strategy using s light and t camera vertices against every other
+// strategy for the same path, following the incremental scheme from pbrt
+fn mis_weight(scene: &Scene, light: &[Vertex], camera: &[Vertex], s: usize, t: usize) -> f64 {
+    let pt = &camera[t - 1];
+    let pt_minus = &camera[t - 2];
+    let remap = |pdf: f64| if pdf != 0.0 { pdf } else { 1.0 };
+
+    // (pdf_fwd, pdf_rev, delta) as they would be for this particular connection
+    let mut cam: Vec<(f64, f64, bool)> = camera[..t]
+        .iter()
+        .map(|v| (v.pdf_fwd, v.pdf_rev, v.delta))
+        .collect();
+    let mut lig: Vec<(f64, f64, bool)> = light[..s]
+        .iter()
+        .map(|v| (v.pdf_fwd, v.pdf_rev, v.delta))
+        .collect();
+
+    if s == 0 {
+        // pt is an emitter hit by the camera path. Emitters that are not among
+        // the scene lights can't be reached by the light subpaths at all.
+        if scene.lights.pdf_value(&pt_minus.p(), &(pt.p() - pt_minus.p())) <= 0.0 {
+            return 1.0;
+        }
+        cam[t - 1].1 = 1.0 / scene.lights.area();
+        cam[t - 2].1 = pt.pdf(None, pt_minus);
+    } else {
+        let qs = &light[s - 1];
+        let qs_minus = if s > 1 { Some(&light[s - 2]) } else { None };
+        cam[t - 1].1 = qs.pdf(qs_minus, pt);
+        cam[t - 1].2 = false;
+        cam[t - 2].1 = pt.pdf(Some(qs), pt_minus);
+        lig[s - 1].1 = pt.pdf(Some(pt_minus), qs);
+        lig[s - 1].2 = false;
+        if let Some(qs_minus) = qs_minus {
+            lig[s - 2].1 = qs.pdf(Some(pt), qs_minus);
+        }
+    }
+
+    let mut sum_ri = 0.0;
+    let mut ri = 1.0;
+    // Strategies with fewer camera vertices, t = 1 is not used by this integrator
+    for i in (2..t).rev() {
+        ri *= remap(cam[i].1) / remap(cam[i].0);
+        if !cam[i].2 && !cam[i - 1].2 {
+            sum_ri += ri * ri;
+        }
+    }
+    ri = 1.0;
+    for i in (0..s).rev() {
+        ri *= remap(lig[i].1) / remap(lig[i].0);
+        let delta_prev = i > 0 && lig[i - 1].2;
+        if !lig[i].2 && !delta_prev {
+            sum_ri += ri * ri;
+        }
+    }
+    1.0 / (1.0 + sum_ri)
+}
+
+// Unweighted contribution of connecting the first s light and t camera vertices
+fn connect(scene: &Scene, light: &[Vertex], camera: &[Vertex], s: usize, t: usize) -> Color {
+    let black = Color::new(0.0, 0.0, 0.0);
+    let pt = &camera[t - 1];
+    if s == 0 {
+        return pt.beta * pt.emitted();
+    }
+    let qs = &light[s - 1];
+    if pt.delta || qs.delta {
+        return black;
+    }
+
+    let w = qs.p() - pt.p();
+    let distance_squared = w.dot(&w);
+    let f_camera = pt.eval(qs);
+    let f_light = match qs.is_light() {
+        // Light vertex: beta only holds the position pdf
+        true => {
+            let cosine = (qs.record.normal.dot(&w) / distance_squared.sqrt()).abs();
+            qs.emitted_towards(pt) * cosine
+        }
+        false => qs.eval(pt),
+    };
+    let contribution = qs.beta * f_light * pt.beta * f_camera / distance_squared;
+    if contribution.max_component() <= 0.0 || !visible(pt, qs, scene) {
+        return black;
+    }
+    contribution
+}
+
+impl Integrator for BidirectionalPathTracer {
+    fn ray_color(&self, r: &Ray, scene: &Scene) -> Color {
+        let mut camera = vec![camera_vertex(r)];
+        // Nothing else can sample the background, so it counts with full weight
+        let mut radiance = random_walk(scene, *r, Color::new(1.0, 1.0, 1.0), 1.0, &mut camera);
+
+        let mut light = Vec::new();
+        light_subpath(scene, &mut light);
+
+        for t in 2..=camera.len() {
+            // Light subpaths don't start on punctual lights, connecting to them is the
+            // only strategy for paths ending there
+            let pt = &camera[t - 1];
+            if t - 1 <= scene.max_depth as usize && !pt.delta {
+                if let Some(r_in) = &pt.r_in {
+                    let direct = sample_punctual_lights(r_in, &pt.record, scene);
+                    radiance = radiance + pt.beta * direct;
+                }
+            }
+            for s in 0..=light.len() {
+                if s + t - 2 > scene.max_depth as usize {
+                    continue;
+                }
+                let contribution = connect(scene, &light, &camera, s, t);
+                if contribution.max_component() <= 0.0 {
+                    continue;
+                }
+                radiance = radiance + contribution * mis_weight(scene, &light, &camera, s, t);
+            }
+        }
+        radiance
+    }
+
+    fn share(self) -> Arc<dyn Integrator> {
+        Arc::new(self)
+    }
+}
@@ -0,0 +1,56 @@
+use crate::data::Color;
+use crate::engine::{HitRecord, Hittable, Ray, Scene};
+use crate::integrators::Integrator;
+use nalgebra::Vector3;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DebugMode {
+    // Shading normal mapped from [-1, 1] to [0, 1]
+    Normals,
+    // Texture coordinates in red and green
+    Uv,
+    // Attenuation of the first scatter
+    Albedo,
+}
+
+// Shows a single property of the first hit, black where nothing was hit
+pub struct DebugIntegrator {
+    mode: DebugMode,
+}
+
+impl DebugIntegrator {
+    pub fn new(mode: DebugMode) -> DebugIntegrator {
+        DebugIntegrator { mode }
+    }
+}
+
+impl Integrator for DebugIntegrator {
+    fn ray_color(&self, r: &Ray, scene: &Scene) -> Color {
+        let mut record = HitRecord::new();
+        if !scene.world.hit(r, 0.001, f64::INFINITY, &mut record) {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        match self.mode {
+            DebugMode::Normals => {
+                Color::from_vector(&((record.normal + Vector3::new(1.0, 1.0, 1.0)) * 0.5))
+            }
+            DebugMode::Uv => Color::new(record.u, record.v, 0.0),
+            DebugMode::Albedo => {
+                let mut scattered = Ray::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, 0.0));
+                let mut attenuation = Color::new(0.0, 0.0, 0.0);
+                if record
+                    .mat_ptr
+                    .scatter(r, &record, &mut attenuation, &mut scattered)
+                {
+                    return attenuation;
+                }
+                record.mat_ptr.emit(&record)
+            }
+        }
+    }
+
+    fn share(self) -> Arc<dyn Integrator> {
+        Arc::new(self)
+    }
+}
@@ -0,0 +1,56 @@
+use crate::data::{Color};
+use crate::engine::{HitRecord, Hittable, Ray, Scene};
+use crate::integrators::lighting::sample_light;
+use crate::integrators::Integrator;
+use nalgebra::Vector3;
+use std::sync::Arc;
+
+// Single bounce lighting: emission plus light sampled from the scene lights.
+// Specular surfaces (mirrors, glass) are followed until something diffuse is hit.
+pub struct DirectLighting {}
+
+impl DirectLighting {
+    pub fn new() -> DirectLighting {
+        DirectLighting {}
+    }
+}
+
+impl Integrator for DirectLighting {
+    fn ray_color(&self, r: &Ray, scene: &Scene) -> Color {
+        let mut radiance = Color::new(0.0, 0.0, 0.0);
+        let mut throughput = Color::new(1.0, 1.0, 1.0);
+        let mut ray = *r;
+
+        for depth in 0..scene.max_depth {
+            let mut record = HitRecord::new();
+            if !scene.world.hit(&ray, 0.001, f64::INFINITY, &mut record) {
+                return radiance + throughput * scene.escaped(&ray, depth == 0);
+            }
+            let emitted = record.mat_ptr.emit(&record);
+            radiance = radiance + throughput * emitted;
+
+            let mut scattered = Ray::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, 0.0));
+            let mut attenuation = Color::new(0.0, 0.0, 0.0);
+            if !record
+                .mat_ptr
+                .scatter(&ray, &record, &mut attenuation, &mut scattered)
+            {
+                // Ended at random (layered materials do), it is still lit. Lights aren't.
+                if !record.mat_ptr.reflects() {
+                    return radiance;
+                }
+                return radiance + throughput * sample_light(&ray, &record, scene, false);
+            }
+            if record.mat_ptr.pdf(&ray, &record, &scattered.direction()) > 0.0 {
+                return radiance + throughput * sample_light(&ray, &record, scene, false);
+            }
+            throughput = throughput * attenuation;
+            ray = scattered;
+        }
+        radiance
+    }
+
+    fn share(self) -> Arc<dyn Integrator> {
+        Arc::new(self)
+    }
+}
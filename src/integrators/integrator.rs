@@ -0,0 +1,12 @@
+use crate::data::Color;
+use crate::engine::{Ray, Scene};
+use std::sync::Arc;
+
+pub trait Integrator: Send + Sync {
+    // Radiance arriving at the camera along r
+    fn ray_color(&self, r: &Ray, scene: &Scene) -> Color;
+    // Work every pixel shares, such as tracing a photon map, done once before any of
+    // them is rendered
+    fn prepare(&self, _scene: &Scene) {}
+    fn share(self) -> Arc<dyn Integrator>;
+}
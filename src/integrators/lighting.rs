@@ -0,0 +1,102 @@
+use crate::data::Color;
+use crate::engine::{HitRecord, Hittable, Ray, Scene};
+
+// Veach's power heuristic (beta = 2) for combining two sampling strategies
+pub fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let a = pdf * pdf;
+    let b = other_pdf * other_pdf;
+    if a + b == 0.0 {
+        return 0.0;
+    }
+    a / (a + b)
+}
+
+// One sample of light arriving at record.p from the scene lights, reflected towards r_in.
+// With mis the sample is weighted against the material sampling the same direction.
+// Punctual lights are included, they can only be found this way.
+pub fn sample_light(r_in: &Ray, record: &HitRecord, scene: &Scene, mis: bool) -> Color {
+    sample_area_light(r_in, record, scene, mis)
+        + sample_punctual_lights(r_in, record, scene)
+        + sample_background(r_in, record, scene, mis)
+}
+
+// One direction towards the background, if it can be sampled like an environment map
+fn sample_background(r_in: &Ray, record: &HitRecord, scene: &Scene, mis: bool) -> Color {
+    let black = Color::new(0.0, 0.0, 0.0);
+    let (direction, light_pdf) = match scene.background.sample() {
+        Some(sample) => sample,
+        None => return black,
+    };
+    if light_pdf <= 0.0 {
+        return black;
+    }
+    let f = record.mat_ptr.eval(r_in, record, &direction);
+    if f.max_component() <= 0.0 {
+        return black;
+    }
+    let mut blocker = HitRecord::new();
+    let shadow_ray = Ray::new(record.p, direction);
+    if scene.world.hit(&shadow_ray, 0.001, f64::INFINITY, &mut blocker) {
+        return black;
+    }
+    let weight = match mis {
+        true => power_heuristic(light_pdf, record.mat_ptr.pdf(r_in, record, &direction)),
+        false => 1.0,
+    };
+    f * scene.background.radiance(&direction) * (weight / light_pdf)
+}
+
+// Light from every punctual light reaching record.p, reflected towards r_in
+pub fn sample_punctual_lights(r_in: &Ray, record: &HitRecord, scene: &Scene) -> Color {
+    let mut radiance = Color::new(0.0, 0.0, 0.0);
+    for light in scene.punctual_lights.iter() {
+        let sample = match light.sample(&record.p) {
+            Some(sample) => sample,
+            None => continue,
+        };
+        let f = record.mat_ptr.eval(r_in, record, &sample.direction);
+        if f.max_component() <= 0.0 {
+            continue;
+        }
+        let mut blocker = HitRecord::new();
+        let shadow_ray = Ray::new(record.p, sample.direction);
+        if scene
+            .world
+            .hit(&shadow_ray, 0.001, sample.distance * (1.0 - 1e-6), &mut blocker)
+        {
+            continue;
+        }
+        radiance = radiance + f * sample.incident;
+    }
+    radiance
+}
+
+fn sample_area_light(r_in: &Ray, record: &HitRecord, scene: &Scene, mis: bool) -> Color {
+    let black = Color::new(0.0, 0.0, 0.0);
+    if scene.lights.is_empty() {
+        return black;
+    }
+    let direction = scene.lights.random(&record.p);
+    let light_pdf = scene.lights.pdf_value(&record.p, &direction);
+    if light_pdf <= 0.0 {
+        return black;
+    }
+    let f = record.mat_ptr.eval(r_in, record, &direction);
+    if f.max_component() <= 0.0 {
+        return black;
+    }
+
+    // Whatever the shadow ray hits first decides what arrives, blockers emit nothing
+    let mut light_record = HitRecord::new();
+    let shadow_ray = Ray::new(record.p, direction);
+    if !scene.world.hit(&shadow_ray, 0.001, f64::INFINITY, &mut light_record) {
+        return black;
+    }
+    let emitted = light_record.mat_ptr.emit(&light_record);
+
+    let weight = match mis {
+        true => power_heuristic(light_pdf, record.mat_ptr.pdf(r_in, record, &direction)),
+        false => 1.0,
+    };
+    f * emitted * (weight / light_pdf)
+}
@@ -0,0 +1,115 @@
+use crate::data::{rand_float01, Color};
+use crate::engine::{HitRecord, Hittable, Ray, Scene};
+use crate::integrators::lighting::{power_heuristic, sample_light};
+use crate::integrators::photon_map::CausticPhotons;
+use crate::integrators::Integrator;
+use nalgebra::Vector3;
+use std::sync::Arc;
+
+// Path tracer with next event estimation. Every non-specular hit samples the scene
+// lights directly, and emitters hit by material sampling are weighted against that
+// light sample with multiple importance sampling.
+//
+// Optionally caustics (light reaching a diffuse surface through glass or mirrors) come
+// from a photon map instead, and paths from a diffuse surface through specular bounces
+// into a light are not counted again.
+pub struct MisPathTracer {
+    caustics: Option<CausticPhotons>,
+}
+
+impl MisPathTracer {
+    pub fn new() -> MisPathTracer {
+        MisPathTracer { caustics: None }
+    }
+
+    pub fn with_caustics(caustics: CausticPhotons) -> MisPathTracer {
+        MisPathTracer {
+            caustics: Some(caustics),
+        }
+    }
+}
+
+impl Integrator for MisPathTracer {
+    fn ray_color(&self, r: &Ray, scene: &Scene) -> Color {
+        let mut radiance = Color::new(0.0, 0.0, 0.0);
+        let mut throughput = Color::new(1.0, 1.0, 1.0);
+        let mut ray = *r;
+        // Camera rays and specular bounces can't be matched by light sampling
+        let mut specular_bounce = true;
+        let mut bsdf_pdf = 0.0;
+        let mut prev_p = ray.origin();
+        let mut diffuse_seen = false;
+
+        for depth in 0..scene.max_depth {
+            let mut record = HitRecord::new();
+            if !scene.world.hit(&ray, 0.001, f64::INFINITY, &mut record) {
+                // Backgrounds such as environment maps may also be sampled as lights
+                let weight = match specular_bounce {
+                    true => 1.0,
+                    false => power_heuristic(bsdf_pdf, scene.background.pdf(&ray.direction())),
+                };
+                return radiance + throughput * scene.escaped(&ray, depth == 0) * weight;
+            }
+
+            let emitted = record.mat_ptr.emit(&record);
+            let caustic_path = specular_bounce && diffuse_seen && self.caustics.is_some();
+            if emitted.max_component() > 0.0 && !caustic_path {
+                let weight = match specular_bounce {
+                    true => 1.0,
+                    false => {
+                        let light_pdf = scene.lights.pdf_value(&prev_p, &ray.direction());
+                        power_heuristic(bsdf_pdf, light_pdf)
+                    }
+                };
+                radiance = radiance + throughput * emitted * weight;
+            }
+
+            let mut scattered = Ray::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, 0.0));
+            let mut attenuation = Color::new(0.0, 0.0, 0.0);
+            let scatters = record
+                .mat_ptr
+                .scatter(&ray, &record, &mut attenuation, &mut scattered);
+
+            // Materials may also end a path at random (layered ones do), light arriving
+            // directly is reflected all the same. Specular ones evaluate to black for it.
+            bsdf_pdf = match scatters {
+                true => record.mat_ptr.pdf(&ray, &record, &scattered.direction()),
+                false => 0.0,
+            };
+            specular_bounce = bsdf_pdf <= 0.0;
+            let ended_at_random = !scatters && record.mat_ptr.reflects();
+            if !specular_bounce || ended_at_random {
+                radiance = radiance + throughput * sample_light(&ray, &record, scene, true);
+                if let Some(caustics) = &self.caustics {
+                    radiance = radiance + throughput * caustics.estimate(&ray, &record);
+                }
+                diffuse_seen = true;
+            }
+            if !scatters {
+                return radiance;
+            }
+            throughput = throughput * attenuation;
+
+            if depth >= scene.rr_min_depth {
+                let survive_prob = throughput.max_component().min(0.95);
+                if rand_float01() >= survive_prob {
+                    return radiance;
+                }
+                throughput = throughput / survive_prob;
+            }
+            prev_p = record.p;
+            ray = scattered;
+        }
+        radiance
+    }
+
+    fn prepare(&self, scene: &Scene) {
+        if let Some(caustics) = &self.caustics {
+            caustics.prepare(scene);
+        }
+    }
+
+    fn share(self) -> Arc<dyn Integrator> {
+        Arc::new(self)
+    }
+}
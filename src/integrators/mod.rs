@@ -0,0 +1,64 @@
+pub mod ambient_occlusion;
+pub mod bdpt;
+pub mod debug;
+pub mod direct;
+pub mod integrator;
+pub mod lighting;
+pub mod mis_path;
+pub mod path;
+pub mod photon_map;
+pub mod spectral;
+
+pub use crate::integrators::ambient_occlusion::AmbientOcclusion;
+pub use crate::integrators::bdpt::BidirectionalPathTracer;
+pub use crate::integrators::debug::{DebugIntegrator, DebugMode};
+pub use crate::integrators::direct::DirectLighting;
+pub use crate::integrators::integrator::Integrator;
+pub use crate::integrators::mis_path::MisPathTracer;
+pub use crate::integrators::path::PathTracer;
+pub use crate::integrators::photon_map::CausticPhotons;
+pub use crate::integrators::spectral::SpectralIntegrator;
+
+use std::sync::Arc;
+
+// Integrators are picked by name in the scene description, parameters follow
+// separated by colons. Ambient occlusion takes an optional distance ("ao:100"),
+// photon mapping the photon count and gather radius ("photon:500000:20").
+// "spectral:" in front of any of them renders it spectrally ("spectral:photon:500000").
+pub fn from_name(name: &str) -> Option<Arc<dyn Integrator>> {
+    if let Some(inner) = name.strip_prefix("spectral:") {
+        return Some(SpectralIntegrator::new(from_name(inner)?).share());
+    }
+    let mut params = name.split(':');
+    let name = params.next()?;
+    let param = params.next();
+    match name {
+        "path" => Some(PathTracer::new().share()),
+        "mis" => Some(MisPathTracer::new().share()),
+        "bdpt" => Some(BidirectionalPathTracer::new().share()),
+        "direct" => Some(DirectLighting::new().share()),
+        "ao" => {
+            let distance = match param {
+                Some(d) => d.parse().ok()?,
+                None => f64::INFINITY,
+            };
+            Some(AmbientOcclusion::new(distance).share())
+        }
+        "photon" => {
+            let n_photons = match param {
+                Some(n) => n.parse().ok()?,
+                None => 200000,
+            };
+            let max_radius = match params.next() {
+                Some(r) => Some(r.parse().ok()?),
+                None => None,
+            };
+            let caustics = CausticPhotons::new(n_photons, max_radius);
+            Some(MisPathTracer::with_caustics(caustics).share())
+        }
+        "normals" => Some(DebugIntegrator::new(DebugMode::Normals).share()),
+        "uv" => Some(DebugIntegrator::new(DebugMode::Uv).share()),
+        "albedo" => Some(DebugIntegrator::new(DebugMode::Albedo).share()),
+        _ => None,
+    }
+}
@@ -0,0 +1,65 @@
+use crate::data::{rand_float01, Color};
+use crate::engine::{HitRecord, Hittable, Ray, Scene};
+use crate::integrators::lighting::sample_punctual_lights;
+use crate::integrators::Integrator;
+use nalgebra::Vector3;
+use std::sync::Arc;
+
+// Plain path tracer, light is only picked up when a path happens to hit an emitter.
+// Punctual lights can't be hit, so non-specular hits sample them directly.
+pub struct PathTracer {}
+
+impl PathTracer {
+    pub fn new() -> PathTracer {
+        PathTracer {}
+    }
+}
+
+impl Integrator for PathTracer {
+    // Iterative path tracer. Throughput carries the product of attenuations along the path,
+    // after rr_min_depth bounces paths are terminated with probability based on it (Russian roulette).
+    fn ray_color(&self, r: &Ray, scene: &Scene) -> Color {
+        let mut radiance = Color::new(0.0, 0.0, 0.0);
+        let mut throughput = Color::new(1.0, 1.0, 1.0);
+        let mut ray = *r;
+
+        for depth in 0..scene.max_depth {
+            let mut record = HitRecord::new();
+            if !scene.world.hit(&ray, 0.001, f64::INFINITY, &mut record) {
+                return radiance + throughput * scene.escaped(&ray, depth == 0);
+            }
+            let mut scattered = Ray::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, 0.0));
+            let mut attenuation = Color::new(0.0, 0.0, 0.0);
+            let emmited = record.mat_ptr.emit(&record);
+            radiance = radiance + throughput * emmited;
+            let scatters = record
+                .mat_ptr
+                .scatter(&ray, &record, &mut attenuation, &mut scattered);
+            let lit = match scatters {
+                true => record.mat_ptr.pdf(&ray, &record, &scattered.direction()) > 0.0,
+                false => record.mat_ptr.reflects(),
+            };
+            if lit {
+                radiance = radiance + throughput * sample_punctual_lights(&ray, &record, scene);
+            }
+            if !scatters {
+                return radiance;
+            }
+            throughput = throughput * attenuation;
+
+            if depth >= scene.rr_min_depth {
+                let survive_prob = throughput.max_component().min(0.95);
+                if rand_float01() >= survive_prob {
+                    return radiance;
+                }
+                throughput = throughput / survive_prob;
+            }
+            ray = scattered;
+        }
+        radiance
+    }
+
+    fn share(self) -> Arc<dyn Integrator> {
+        Arc::new(self)
+    }
+}
@@ -0,0 +1,304 @@
+use crate::data::{
+    rand_float01, set_sample_source, set_wavelength, stream_seed, upsample,
+    vrandom_cosine_direction, Color, Onb, SeededSource,
+};
+use crate::engine::{HitRecord, Hittable, Ray, Scene};
+use nalgebra::Vector3;
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::f64::consts::PI;
+use std::rc::Rc;
+use std::sync::OnceLock;
+
+pub struct Photon {
+    position: Vector3<f64>,
+    // Direction the photon was travelling in when it landed
+    direction: Vector3<f64>,
+    power: Color,
+}
+
+struct Neighbor {
+    distance_squared: f64,
+    index: usize,
+}
+
+impl PartialEq for Neighbor {
+    fn eq(&self, other: &Self) -> bool {
+        self.distance_squared == other.distance_squared
+    }
+}
+
+impl Eq for Neighbor {}
+
+impl PartialOrd for Neighbor {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Neighbor {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.distance_squared
+            .partial_cmp(&other.distance_squared)
+            .unwrap_or(Ordering::Equal)
+    }
+}
+
+// Balanced kd-tree stored in place: the median of every range is the node
+// splitting it, with the split axis kept alongside
+pub struct PhotonMap {
+    photons: Vec<Photon>,
+    axes: Vec<usize>,
+}
+
+impl PhotonMap {
+    pub fn new(mut photons: Vec<Photon>) -> PhotonMap {
+        let mut axes = vec![0; photons.len()];
+        PhotonMap::build(&mut photons, &mut axes);
+        PhotonMap { photons, axes }
+    }
+
+    fn build(photons: &mut [Photon], axes: &mut [usize]) {
+        if photons.is_empty() {
+            return;
+        }
+        // Split along the axis with the largest extent
+        let mut min = photons[0].position;
+        let mut max = photons[0].position;
+        for photon in photons.iter() {
+            for a in 0..3 {
+                min[a] = min[a].min(photon.position[a]);
+                max[a] = max[a].max(photon.position[a]);
+            }
+        }
+        let extent = max - min;
+        let axis = if extent[0] > extent[1] && extent[0] > extent[2] {
+            0
+        } else if extent[1] > extent[2] {
+            1
+        } else {
+            2
+        };
+
+        let mid = photons.len() / 2;
+        photons.select_nth_unstable_by(mid, |a, b| {
+            a.position[axis]
+                .partial_cmp(&b.position[axis])
+                .unwrap_or(Ordering::Equal)
+        });
+        axes[mid] = axis;
+        let (left, right) = photons.split_at_mut(mid);
+        let (left_axes, right_axes) = axes.split_at_mut(mid);
+        PhotonMap::build(left, left_axes);
+        PhotonMap::build(&mut right[1..], &mut right_axes[1..]);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.photons.is_empty()
+    }
+
+    fn search(
+        &self,
+        lo: usize,
+        hi: usize,
+        p: &Vector3<f64>,
+        k: usize,
+        max_distance_squared: f64,
+        heap: &mut BinaryHeap<Neighbor>,
+    ) {
+        if lo >= hi {
+            return;
+        }
+        let mid = (lo + hi) / 2;
+        let photon = &self.photons[mid];
+        let axis = self.axes[mid];
+        let bound = |heap: &BinaryHeap<Neighbor>| match heap.len() >= k {
+            true => heap.peek().unwrap().distance_squared,
+            false => max_distance_squared,
+        };
+
+        let offset = photon.position - p;
+        let distance_squared = offset.dot(&offset);
+        if distance_squared < bound(heap) {
+            heap.push(Neighbor {
+                distance_squared,
+                index: mid,
+            });
+            if heap.len() > k {
+                heap.pop();
+            }
+        }
+
+        let delta = p[axis] - photon.position[axis];
+        let (near, far) = match delta < 0.0 {
+            true => ((lo, mid), (mid + 1, hi)),
+            false => ((mid + 1, hi), (lo, mid)),
+        };
+        self.search(near.0, near.1, p, k, max_distance_squared, heap);
+        if delta * delta < bound(heap) {
+            self.search(far.0, far.1, p, k, max_distance_squared, heap);
+        }
+    }
+
+    // Up to k photons closest to p within max_distance, and the squared radius
+    // of the disc they were gathered from
+    pub fn nearest(&self, p: &Vector3<f64>, k: usize, max_distance: f64) -> (Vec<&Photon>, f64) {
+        let max_distance_squared = max_distance * max_distance;
+        let mut heap = BinaryHeap::with_capacity(k + 1);
+        self.search(0, self.photons.len(), p, k, max_distance_squared, &mut heap);
+
+        let radius_squared = match heap.len() >= k {
+            true => heap.peek().unwrap().distance_squared,
+            false => max_distance_squared,
+        };
+        let photons = heap.iter().map(|n| &self.photons[n.index]).collect();
+        (photons, radius_squared)
+    }
+}
+
+// Photons which went through at least one specular bounce (glass, mirrors) before
+// landing on a diffuse surface. The map is traced once by prepare, before rendering,
+// from its own stream of the scene seed so every worker gathers from the same photons.
+// Photons are traced in RGB and upsampled when gathered, so with a spectral integrator
+// the caustics they carry don't split into colours, only path traced light disperses.
+pub struct CausticPhotons {
+    n_photons: usize,
+    k: usize,
+    max_radius: Option<f64>,
+    // Photons and the gather radius actually used, once traced
+    map: OnceLock<(PhotonMap, f64)>,
+}
+
+impl CausticPhotons {
+    // Density is estimated from the 50 nearest photons no further than max_radius away,
+    // by default 1% of the scene's extent
+    pub fn new(n_photons: usize, max_radius: Option<f64>) -> CausticPhotons {
+        CausticPhotons {
+            n_photons,
+            k: 50,
+            max_radius,
+            map: OnceLock::new(),
+        }
+    }
+
+    pub fn prepare(&self, scene: &Scene) {
+        self.map.get_or_init(|| self.trace(scene));
+    }
+
+    fn trace(&self, scene: &Scene) -> (PhotonMap, f64) {
+        // Photons don't depend on the pixel streams, and carry RGB
+        let stream = SeededSource::new(stream_seed(scene.seed, u64::MAX));
+        let source = set_sample_source(Some(Rc::new(RefCell::new(stream))));
+        let wavelength = set_wavelength(None);
+        let mut photons = Vec::new();
+        let total_area = scene.lights.area();
+
+        for _ in 0..self.n_photons {
+            let mut record = match scene.lights.sample_surface() {
+                Some(record) => record,
+                None => break,
+            };
+
+            // Same cosine weighted emission from a random face as the light subpaths of
+            // bdpt, the back of one sided lights sends out nothing
+            let mut normal = record.normal;
+            if rand_float01() < 0.5 {
+                normal = -normal;
+                record.front_face = false;
+            }
+            let emitted = record.mat_ptr.emit(&record);
+            let direction = Onb::build_from_w(&normal).local_vec(&vrandom_cosine_direction());
+            let mut power = emitted * (2.0 * PI * total_area / self.n_photons as f64);
+            let mut ray = Ray::new(record.p, direction);
+            let mut specular_seen = false;
+
+            for _ in 0..scene.max_depth {
+                let mut hit = HitRecord::new();
+                if !scene.world.hit(&ray, 0.001, f64::INFINITY, &mut hit) {
+                    break;
+                }
+                let mut scattered = Ray::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, 0.0));
+                let mut attenuation = Color::new(0.0, 0.0, 0.0);
+                if !hit
+                    .mat_ptr
+                    .scatter(&ray, &hit, &mut attenuation, &mut scattered)
+                {
+                    break;
+                }
+                if hit.mat_ptr.pdf(&ray, &hit, &scattered.direction()) > 0.0 {
+                    if specular_seen {
+                        photons.push(Photon {
+                            position: hit.p,
+                            direction: ray.direction(),
+                            power,
+                        });
+                    }
+                    break;
+                }
+                specular_seen = true;
+                power = power * attenuation;
+                ray = scattered;
+            }
+        }
+        let max_radius = match (self.max_radius, scene.world.get_bounding_box()) {
+            (Some(radius), _) => radius,
+            (None, Some(bbox)) => 0.01 * (bbox.max() - bbox.min()).norm(),
+            (None, None) => f64::INFINITY,
+        };
+        set_sample_source(source);
+        set_wavelength(wavelength);
+        (PhotonMap::new(photons), max_radius)
+    }
+
+    // Caustic radiance leaving record.p back along r_in
+    pub fn estimate(&self, r_in: &Ray, record: &HitRecord) -> Color {
+        let (map, max_radius) = self
+            .map
+            .get()
+            .expect("caustic photons are traced before rendering");
+        let mut sum = Color::new(0.0, 0.0, 0.0);
+        if map.is_empty() {
+            return sum;
+        }
+        let (photons, radius_squared) = map.nearest(&record.p, self.k, *max_radius);
+        for photon in photons {
+            let incoming = -photon.direction;
+            let cosine = record.normal.dot(&incoming) / incoming.norm();
+            if cosine <= 1e-6 {
+                continue;
+            }
+            // eval includes the cosine, photon power already is flux through the surface
+            let f = record.mat_ptr.eval(r_in, record, &incoming) / cosine;
+            sum = sum + f * upsample(photon.power);
+        }
+        sum / (PI * radius_squared)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::data::{Color, SceneDescription};
+    use crate::engine::pixel_color;
+    use std::thread;
+
+    // Under the glass ball, where the caustic lands
+    fn caustic_pixel() -> Color {
+        let mut description = SceneDescription::cornell_box();
+        description.scene = "cornell_glass".to_string();
+        description.integrator = "photon:5000".to_string();
+        description.image_width = 40;
+        description.image_height = 40;
+        description.samples_per_pixel = 4;
+        let scene = description.build().unwrap();
+        pixel_color(20.0, 34.0, &scene)
+    }
+
+    #[test]
+    fn caustics_are_the_same_on_every_thread() {
+        let here = caustic_pixel();
+        let there = thread::spawn(caustic_pixel).join().unwrap();
+        assert_eq!(here, there);
+        assert!(here.max_component() > 0.0);
+    }
+}
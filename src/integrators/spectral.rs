@@ -0,0 +1,41 @@
+use crate::data::{
+    rand_float, set_wavelength, wavelength_to_rgb, Color, MAX_WAVELENGTH, MIN_WAVELENGTH,
+};
+use crate::engine::{Ray, Scene};
+use crate::integrators::Integrator;
+use std::sync::Arc;
+
+// Runs another integrator at a single wavelength per camera ray, picked uniformly from
+// the visible range. Materials see the wavelength (dispersive glass bends every colour
+// differently) and RGB inputs are turned into spectra, the spectral sample coming back
+// is converted to RGB for the film.
+pub struct SpectralIntegrator {
+    inner: Arc<dyn Integrator>,
+}
+
+impl SpectralIntegrator {
+    pub fn new(inner: Arc<dyn Integrator>) -> SpectralIntegrator {
+        SpectralIntegrator { inner }
+    }
+}
+
+impl Integrator for SpectralIntegrator {
+    fn ray_color(&self, r: &Ray, scene: &Scene) -> Color {
+        let wavelength = rand_float(MIN_WAVELENGTH, MAX_WAVELENGTH);
+        let previous = set_wavelength(Some(wavelength));
+        let radiance = self.inner.ray_color(r, scene);
+        set_wavelength(previous);
+
+        // All channels carry the same spectral sample
+        let value = (radiance.r() + radiance.g() + radiance.b()) / 3.0;
+        wavelength_to_rgb(wavelength) * value
+    }
+
+    fn prepare(&self, scene: &Scene) {
+        self.inner.prepare(scene);
+    }
+
+    fn share(self) -> Arc<dyn Integrator> {
+        Arc::new(self)
+    }
+}
@@ -1,54 +1,38 @@
+// The code base writes returns out, and materials and textures are shared through
+// Arc without being Send + Sync (the geometry holding them asserts it instead)
+#![allow(clippy::needless_return, clippy::arc_with_non_send_sync)]
+
 mod data;
+mod distributed;
 mod engine;
 mod gui;
+mod integrators;
 mod materials;
 mod textures;
 
-// extern crates
-extern crate nalgebra;
-use nalgebra::Vector3;
-
 // crate imports
-use crate::data::{scenes, Color};
-use crate::engine::{render, Camera, Scene};
+use crate::data::SceneDescription;
+use crate::distributed::{run_coordinator, run_worker};
+use crate::engine::{render, render_mlt};
 // use crate::gui::prerender;
 
 // std imports
-use std::sync::atomic::AtomicBool;
-use std::sync::Arc;
+use std::env;
 
-fn main() {
-    let image_width = 500.0;
-    let aspect_ratio = 1.0;
-    let image_height = image_width / aspect_ratio;
-    let samples_per_pixel = 100;
-    let max_depth = 100;
-    let look_from = Vector3::new(278.0, 278.0, -800.0);
-    let look_at = Vector3::new(278.0, 278.0, 0.0);
-    let vup = Vector3::new(0.0, 1.0, 0.0);
-    let focus_dist = 10.0;
-    let background = Color::new(0.0, 0.0, 0.0);
-
-    let cam = Camera::new(look_from, look_at, vup, 40.0, aspect_ratio, 0.0, focus_dist);
-    let cam = Arc::new(cam);
-
-    let world = scenes::cornell_box();
-    let world = Arc::new(world);
-
-    let completed = Arc::new(AtomicBool::new(false));
-    let prerender_finished = Arc::new(AtomicBool::new(false));
-
-    let scene = Scene {
-        cam,
-        world,
-        samples_per_pixel,
-        max_depth,
-        image_width,
-        image_height,
-        completed,
-        prerender_finished,
-        background,
-    };
+const TILE_SIZE: u64 = 32;
+const N_THREADS: u64 = 4;
+
+fn usage() {
+    println!("usage: raytracer [key=value...]                               render locally");
+    println!("       raytracer mlt [output] [key=value...]                  render locally with metropolis light transport");
+    println!("       raytracer coordinator <addr> [output] [key=value...]   hand out tiles to workers");
+    println!("       raytracer worker <addr> [threads]                      render tiles for a coordinator");
+    println!();
+    println!("key=value pairs override the scene description, e.g. integrator=mis samples=64");
+}
+
+fn render_local(description: SceneDescription) {
+    let scene = description.build().unwrap();
     // TODO: prerender with piston
     //prerender(scene.clone());
     //writeln!("Press enter to render...");
@@ -56,5 +40,37 @@ fn main() {
     scene
         .prerender_finished
         .store(true, std::sync::atomic::Ordering::Relaxed);
-    render(scene, 4, "result.png".to_string())
+    render(scene, N_THREADS, "result.png".to_string())
+}
+
+fn main() {
+    let (overrides, args): (Vec<String>, Vec<String>) =
+        env::args().partition(|arg| arg.contains('='));
+    let mut description = SceneDescription::cornell_box();
+    for pair in overrides.iter() {
+        if let Err(e) = description.set(pair) {
+            println!("{}", e);
+            return usage();
+        }
+    }
+
+    match args.get(1).map(|s| s.as_str()) {
+        None => render_local(description),
+        Some("mlt") => {
+            let path = args.get(2).cloned().unwrap_or_else(|| "result.png".to_string());
+            render_mlt(description.build().unwrap(), N_THREADS, path)
+        }
+        Some("coordinator") if args.len() >= 3 => {
+            let path = args.get(3).cloned().unwrap_or_else(|| "result.png".to_string());
+            run_coordinator(description, &args[2], TILE_SIZE, path)
+        }
+        Some("worker") if args.len() >= 3 => {
+            let n_threads = match args.get(3) {
+                Some(n) => n.parse().unwrap_or(N_THREADS),
+                None => N_THREADS,
+            };
+            run_worker(&args[2], n_threads)
+        }
+        _ => usage(),
+    }
 }
@@ -0,0 +1,189 @@
+use crate::data::{vunit, Color, Onb};
+use crate::engine::{HitRecord, Ray};
+use crate::materials::Material;
+use crate::textures::Texture;
+use nalgebra::Vector3;
+use std::sync::Arc;
+
+// Wrappers that tilt the shading normal of another material to fake small surface
+// detail. The wrapped material shades the hit as if the surface had the tilted normal,
+// the geometry (and so the shadow it casts) stays flat.
+
+// Hit record with a perturbed normal, given on the outside of the surface
+fn shading_record(record: &HitRecord, outward_normal: &Vector3<f64>) -> HitRecord {
+    let mut shading = record.clone();
+    shading.normal = match record.front_face {
+        true => vunit(outward_normal),
+        false => -vunit(outward_normal),
+    };
+    shading
+}
+
+fn outward_normal(record: &HitRecord) -> Vector3<f64> {
+    match record.front_face {
+        true => record.normal,
+        false => -record.normal,
+    }
+}
+
+// Unit tangent and bitangent following u and v, perpendicular to the normal
+fn tangent_frame(record: &HitRecord, normal: &Vector3<f64>) -> (Vector3<f64>, Vector3<f64>) {
+    let tangent = record.dpdu - normal * normal.dot(&record.dpdu);
+    if tangent.norm() == 0.0 {
+        // No uv parametrization here, any frame will do
+        let uvw = Onb::build_from_w(normal);
+        return (uvw.local(1.0, 0.0, 0.0), uvw.local(0.0, 1.0, 0.0));
+    }
+    let tangent = vunit(&tangent);
+    let bitangent = normal.cross(&tangent);
+    match bitangent.dot(&record.dpdv) < 0.0 {
+        true => (tangent, -bitangent),
+        false => (tangent, bitangent),
+    }
+}
+
+// Normals read from a tangent space normal map, the usual blue-ish images with x along
+// u in red, y along v in green and the unperturbed normal in blue
+pub struct NormalMap {
+    inner: Arc<dyn Material>,
+    map: Arc<dyn Texture>,
+}
+
+impl NormalMap {
+    pub fn new(inner: Arc<dyn Material>, map: Arc<dyn Texture>) -> NormalMap {
+        NormalMap { inner, map }
+    }
+
+    pub fn share(self) -> Arc<dyn Material> {
+        Arc::new(self)
+    }
+
+    fn shading_record(&self, record: &HitRecord) -> HitRecord {
+        let normal = outward_normal(record);
+        let (tangent, bitangent) = tangent_frame(record, &normal);
+        let texel = self.map.rgb(record.u, record.v, &record.p);
+        let perturbed = tangent * (2.0 * texel.r() - 1.0)
+            + bitangent * (2.0 * texel.g() - 1.0)
+            + normal * (2.0 * texel.b() - 1.0);
+        if perturbed.dot(&normal) <= 0.0 {
+            return record.clone();
+        }
+        shading_record(record, &perturbed)
+    }
+}
+
+impl Material for NormalMap {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        record: &HitRecord,
+        attenuation: &mut Color,
+        scattered: &mut Ray,
+    ) -> bool {
+        self.inner
+            .scatter(r_in, &self.shading_record(record), attenuation, scattered)
+    }
+
+    fn emit(&self, record: &HitRecord) -> Color {
+        self.inner.emit(record)
+    }
+
+    fn eval(&self, r_in: &Ray, record: &HitRecord, direction: &Vector3<f64>) -> Color {
+        self.inner
+            .eval(r_in, &self.shading_record(record), direction)
+    }
+
+    fn pdf(&self, r_in: &Ray, record: &HitRecord, direction: &Vector3<f64>) -> f64 {
+        self.inner
+            .pdf(r_in, &self.shading_record(record), direction)
+    }
+
+    fn reflects(&self) -> bool {
+        self.inner.reflects()
+    }
+}
+
+// Step in u and v for the finite differences of the bump height
+const BUMP_DELTA: f64 = 0.0005;
+
+// Normals of the surface displaced along its normal by a height texture (red channel)
+// times scale, in world units
+pub struct BumpMap {
+    inner: Arc<dyn Material>,
+    height: Arc<dyn Texture>,
+    scale: f64,
+}
+
+impl BumpMap {
+    pub fn new(inner: Arc<dyn Material>, height: Arc<dyn Texture>, scale: f64) -> BumpMap {
+        BumpMap {
+            inner,
+            height,
+            scale,
+        }
+    }
+
+    pub fn share(self) -> Arc<dyn Material> {
+        Arc::new(self)
+    }
+
+    fn shading_record(&self, record: &HitRecord) -> HitRecord {
+        let normal = outward_normal(record);
+        let (dpdu, dpdv) = (record.dpdu, record.dpdv);
+        if dpdu.cross(&dpdv).norm() == 0.0 {
+            return record.clone();
+        }
+        let height = |u: f64, v: f64, p: &Vector3<f64>| self.height.rgb(u, v, p).r() * self.scale;
+        let h = height(record.u, record.v, &record.p);
+        let h_du = height(
+            record.u + BUMP_DELTA,
+            record.v,
+            &(record.p + dpdu * BUMP_DELTA),
+        );
+        let h_dv = height(
+            record.u,
+            record.v + BUMP_DELTA,
+            &(record.p + dpdv * BUMP_DELTA),
+        );
+        let dhdu = (h_du - h) / BUMP_DELTA;
+        let dhdv = (h_dv - h) / BUMP_DELTA;
+
+        // Normal of the displaced surface, ignoring how the normal itself bends
+        let perturbed = (dpdu + normal * dhdu).cross(&(dpdv + normal * dhdv));
+        match perturbed.dot(&normal) < 0.0 {
+            true => shading_record(record, &-perturbed),
+            false => shading_record(record, &perturbed),
+        }
+    }
+}
+
+impl Material for BumpMap {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        record: &HitRecord,
+        attenuation: &mut Color,
+        scattered: &mut Ray,
+    ) -> bool {
+        self.inner
+            .scatter(r_in, &self.shading_record(record), attenuation, scattered)
+    }
+
+    fn emit(&self, record: &HitRecord) -> Color {
+        self.inner.emit(record)
+    }
+
+    fn eval(&self, r_in: &Ray, record: &HitRecord, direction: &Vector3<f64>) -> Color {
+        self.inner
+            .eval(r_in, &self.shading_record(record), direction)
+    }
+
+    fn pdf(&self, r_in: &Ray, record: &HitRecord, direction: &Vector3<f64>) -> f64 {
+        self.inner
+            .pdf(r_in, &self.shading_record(record), direction)
+    }
+
+    fn reflects(&self) -> bool {
+        self.inner.reflects()
+    }
+}
@@ -0,0 +1,398 @@
+use crate::data::{rand_float01, upsample, vunit, Color, Onb};
+use crate::engine::{HitRecord, Ray};
+use crate::integrators::lighting::power_heuristic;
+use crate::materials::microfacet::{fresnel_dielectric, refract_local, TrowbridgeReitz};
+use crate::materials::rough_dielectric::{eval_rough_dielectric, pdf_rough_dielectric};
+use crate::materials::Material;
+use crate::textures::{SolidColor, Texture};
+use nalgebra::Vector3;
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+// A dielectric coat over any opaque base material: car paint, varnished wood, glazed
+// ceramics. Light may reflect off the coat, or refract in and bounce between base and
+// coat until it gets out again, optionally tinted by an absorbing layer on the way.
+//
+// There is no closed form for that, so the layers are evaluated stochastically by random
+// walks between the two interfaces (Guo et al. 2018, as in pbrt-v4). scatter is exact,
+// eval is an unbiased estimate and pdf an approximation, which is all that weighting
+// samples against each other needs. A perfectly smooth coat would make the blend a delta
+// distribution, so the coat is kept from being smoother than MIN_ROUGHNESS.
+pub struct Coated {
+    base: Arc<dyn Material>,
+    ior: f64,
+    roughness: Arc<dyn Texture>,
+    // Absorption coefficient of the coat per channel (1/distance, not a fraction),
+    // a path of length d through it is attenuated by exp(-absorption * d)
+    absorption: Color,
+    thickness: f64,
+}
+
+const MIN_ROUGHNESS: f64 = 0.04;
+
+// Walks bouncing between the layers longer than this are dropped
+const MAX_DEPTH: usize = 10;
+
+#[derive(Clone, Copy)]
+enum Lobe {
+    Any,
+    Reflection,
+    Transmission,
+}
+
+// The coat's interface in the local shading frame. Unlike the rough dielectric functions
+// it takes directions on either side, from below it is seen with the inverse eta.
+struct Interface {
+    distribution: TrowbridgeReitz,
+    eta: f64,
+}
+
+impl Interface {
+    fn oriented(&self, wo: &Vector3<f64>, wi: &Vector3<f64>) -> (Vector3<f64>, Vector3<f64>, f64) {
+        match wo[2] < 0.0 {
+            true => (
+                Vector3::new(wo[0], wo[1], -wo[2]),
+                Vector3::new(wi[0], wi[1], -wi[2]),
+                1.0 / self.eta,
+            ),
+            false => (*wo, *wi, self.eta),
+        }
+    }
+
+    // Samples wi from the lobe, returned with f * |cos| / pdf and the pdf
+    fn sample(&self, wo: &Vector3<f64>, lobe: Lobe) -> Option<(Vector3<f64>, f64, f64)> {
+        let below = wo[2] < 0.0;
+        let (wo, _, eta) = self.oriented(wo, wo);
+        let wm = self
+            .distribution
+            .sample_visible(&wo, rand_float01(), rand_float01());
+        let reflectance = fresnel_dielectric(wo.dot(&wm), eta);
+        let (reflect, weight) = match lobe {
+            Lobe::Any => (rand_float01() < reflectance, 1.0),
+            Lobe::Reflection => (true, reflectance),
+            Lobe::Transmission => (false, 1.0 - reflectance),
+        };
+        let mut wi = match reflect {
+            true => 2.0 * wo.dot(&wm) * wm - wo,
+            false => refract_local(&wo, &wm, eta)?,
+        };
+        if reflect != (wi[2] > 0.0) || weight <= 0.0 {
+            return None;
+        }
+        let scale = if reflect { 1.0 } else { 1.0 / eta };
+        let weight = weight * scale * self.distribution.g(&wo, &wi) / self.distribution.g1(&wo);
+        let pdf = self.oriented_pdf(&wo, &wi, eta, lobe);
+        if pdf <= 0.0 {
+            return None;
+        }
+        if below {
+            wi[2] = -wi[2];
+        }
+        Some((wi, weight, pdf))
+    }
+
+    // f * |cos|
+    fn eval(&self, wo: &Vector3<f64>, wi: &Vector3<f64>) -> f64 {
+        let (wo, wi, eta) = self.oriented(wo, wi);
+        eval_rough_dielectric(&self.distribution, &wo, &wi, eta)
+    }
+
+    fn pdf(&self, wo: &Vector3<f64>, wi: &Vector3<f64>, lobe: Lobe) -> f64 {
+        let (wo, wi, eta) = self.oriented(wo, wi);
+        self.oriented_pdf(&wo, &wi, eta, lobe)
+    }
+
+    fn oriented_pdf(&self, wo: &Vector3<f64>, wi: &Vector3<f64>, eta: f64, lobe: Lobe) -> f64 {
+        let pdf = pdf_rough_dielectric(&self.distribution, wo, wi, eta);
+        let reflected = wi[2] > 0.0;
+        match lobe {
+            Lobe::Any => return pdf,
+            Lobe::Reflection if !reflected => return 0.0,
+            Lobe::Transmission if reflected => return 0.0,
+            _ => {}
+        }
+        // Without the chance of picking reflection or transmission by the Fresnel term
+        let mut wm = match reflected {
+            true => vunit(&(wo + wi)),
+            false => vunit(&(wi * eta + wo)),
+        };
+        if wm[2] < 0.0 {
+            wm = -wm;
+        }
+        let reflectance = fresnel_dielectric(wo.dot(&wm), eta);
+        let probability = match reflected {
+            true => reflectance,
+            false => 1.0 - reflectance,
+        };
+        match probability > 0.0 {
+            true => pdf / probability,
+            false => 0.0,
+        }
+    }
+}
+
+impl Coated {
+    pub fn new(base: Arc<dyn Material>, ior: f64, roughness: f64) -> Coated {
+        Coated {
+            base,
+            ior,
+            roughness: SolidColor::scalar(roughness).share(),
+            absorption: Color::new(0.0, 0.0, 0.0),
+            thickness: 0.0,
+        }
+    }
+
+    pub fn with_roughness_texture(mut self, roughness: Arc<dyn Texture>) -> Coated {
+        self.roughness = roughness;
+        self
+    }
+
+    // Tinted coat, light crossing it at grazing angles travels further and is absorbed more
+    pub fn with_absorption(mut self, absorption: Color, thickness: f64) -> Coated {
+        self.absorption = absorption;
+        self.thickness = thickness;
+        self
+    }
+
+    pub fn share(self) -> Arc<dyn Material> {
+        Arc::new(self)
+    }
+
+    fn interface(&self, record: &HitRecord) -> Interface {
+        let roughness = self.roughness.rgb(record.u, record.v, &record.p).r();
+        Interface {
+            distribution: TrowbridgeReitz::from_roughness(roughness.clamp(MIN_ROUGHNESS, 1.0)),
+            eta: self.ior,
+        }
+    }
+
+    // Fraction of light crossing the coat along w
+    fn transmittance(&self, w: &Vector3<f64>) -> Color {
+        let absorption = upsample(self.absorption);
+        let distance = self.thickness / w[2].abs();
+        Color::new(
+            (-absorption.r() * distance).exp(),
+            (-absorption.g() * distance).exp(),
+            (-absorption.b() * distance).exp(),
+        )
+    }
+}
+
+// The base material lit through the coat. w is the direction light travels in on the
+// way down, directions are local to the shading frame uvw.
+struct Base<'a> {
+    material: &'a Arc<dyn Material>,
+    record: &'a HitRecord,
+    uvw: Onb,
+}
+
+impl<'a> Base<'a> {
+    fn ray(&self, w: &Vector3<f64>) -> Ray {
+        Ray::new(self.record.p, self.uvw.local_vec(w))
+    }
+
+    // Samples a direction back up, with f * |cos| / pdf and the pdf (0 if specular).
+    // Light leaving through the bottom is lost, the base is taken to be opaque.
+    fn sample(&self, w: &Vector3<f64>) -> Option<(Vector3<f64>, Color, f64)> {
+        let r_in = self.ray(w);
+        let mut attenuation = Color::new(0.0, 0.0, 0.0);
+        let mut scattered = Ray::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, 0.0));
+        if !self
+            .material
+            .scatter(&r_in, self.record, &mut attenuation, &mut scattered)
+        {
+            return None;
+        }
+        let wi = self.uvw.world_to_local(&vunit(&scattered.direction()));
+        if wi[2] <= 0.0 {
+            return None;
+        }
+        let pdf = self
+            .material
+            .pdf(&r_in, self.record, &scattered.direction());
+        Some((wi, attenuation, pdf))
+    }
+
+    fn eval(&self, w: &Vector3<f64>, wi: &Vector3<f64>) -> Color {
+        self.material
+            .eval(&self.ray(w), self.record, &self.uvw.local_vec(wi))
+    }
+
+    fn pdf(&self, w: &Vector3<f64>, wi: &Vector3<f64>) -> f64 {
+        self.material
+            .pdf(&self.ray(w), self.record, &self.uvw.local_vec(wi))
+    }
+}
+
+// Russian roulette for walks that carry little light, false when terminated
+fn survives(beta: &mut Color, depth: usize) -> bool {
+    let max = beta.max_component();
+    if depth <= 3 || max >= 0.25 {
+        return true;
+    }
+    if rand_float01() >= max {
+        return false;
+    }
+    *beta = *beta / max;
+    true
+}
+
+impl Material for Coated {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        record: &HitRecord,
+        attenuation: &mut Color,
+        scattered: &mut Ray,
+    ) -> bool {
+        let uvw = Onb::build_from_w(&record.normal);
+        let wo = uvw.world_to_local(&-vunit(&r_in.direction()));
+        if wo[2] <= 0.0 {
+            return false;
+        }
+        let top = self.interface(record);
+        let base = Base {
+            material: &self.base,
+            record,
+            uvw,
+        };
+
+        let (mut w, weight, _) = match top.sample(&wo, Lobe::Any) {
+            Some(sample) => sample,
+            None => return false,
+        };
+        let mut beta = Color::new(weight, weight, weight);
+        // Follow light refracted into the coat until it makes its way out
+        for depth in 0..MAX_DEPTH {
+            if w[2] > 0.0 {
+                *scattered = Ray::new(record.p, uvw.local_vec(&w));
+                *attenuation = beta;
+                return true;
+            }
+            if !survives(&mut beta, depth) {
+                return false;
+            }
+            beta = beta * self.transmittance(&w);
+            let (up, weight, _) = match base.sample(&w) {
+                Some(sample) => sample,
+                None => return false,
+            };
+            beta = beta * weight * self.transmittance(&up);
+            let (next, weight, _) = match top.sample(&-up, Lobe::Any) {
+                Some(sample) => sample,
+                None => return false,
+            };
+            // Going down again unless it got through
+            beta = beta * weight;
+            w = next;
+        }
+        false
+    }
+
+    fn eval(&self, r_in: &Ray, record: &HitRecord, direction: &Vector3<f64>) -> Color {
+        let uvw = Onb::build_from_w(&record.normal);
+        let wo = uvw.world_to_local(&-vunit(&r_in.direction()));
+        let wi = uvw.world_to_local(&vunit(direction));
+        let black = Color::new(0.0, 0.0, 0.0);
+        if wo[2] <= 0.0 || wi[2] <= 0.0 {
+            return black;
+        }
+        let top = self.interface(record);
+        let base = Base {
+            material: &self.base,
+            record,
+            uvw,
+        };
+
+        // Reflection off the coat
+        let value = top.eval(&wo, &wi);
+        let mut f = Color::new(value, value, value);
+
+        // One walk from wo into the coat, bouncing between base and coat. At every bounce
+        // it is connected to wi, both through a refracted direction sampled up front and
+        // through the direction the base samples, weighted against each other.
+        let (mut w, weight_o, _) = match top.sample(&wo, Lobe::Transmission) {
+            Some(sample) => sample,
+            None => return f,
+        };
+        let (w_exit, weight_i, pdf_i) = match top.sample(&wi, Lobe::Transmission) {
+            Some(sample) => sample,
+            None => return f,
+        };
+        let exit_transmittance = self.transmittance(&w_exit);
+        let mut beta = Color::new(weight_o, weight_o, weight_o);
+        for depth in 0..MAX_DEPTH {
+            if !survives(&mut beta, depth) {
+                break;
+            }
+            beta = beta * self.transmittance(&w);
+            let f_base = base.eval(&w, &-w_exit);
+            if f_base.max_component() > 0.0 {
+                let weight = power_heuristic(pdf_i, base.pdf(&w, &-w_exit));
+                f = f + beta
+                    * f_base
+                    * exit_transmittance
+                    * (weight * weight_i * wi[2] / w_exit[2].abs());
+            }
+
+            let (up, weight, pdf_base) = match base.sample(&w) {
+                Some(sample) => sample,
+                None => break,
+            };
+            beta = beta * weight * self.transmittance(&up);
+            let f_exit = top.eval(&-up, &wi);
+            if f_exit > 0.0 {
+                let weight = match pdf_base > 0.0 {
+                    // Against the chance of the refraction sampled from wi picking up
+                    true => power_heuristic(pdf_base, top.pdf(&wi, &-up, Lobe::Transmission)),
+                    false => 1.0,
+                };
+                f = f + beta * (f_exit * weight);
+            }
+
+            // Reflected back down by the underside of the coat
+            let (next, weight, _) = match top.sample(&-up, Lobe::Reflection) {
+                Some(sample) => sample,
+                None => break,
+            };
+            beta = beta * weight;
+            w = next;
+        }
+        f
+    }
+
+    fn pdf(&self, r_in: &Ray, record: &HitRecord, direction: &Vector3<f64>) -> f64 {
+        let uvw = Onb::build_from_w(&record.normal);
+        let wo = uvw.world_to_local(&-vunit(&r_in.direction()));
+        let wi = uvw.world_to_local(&vunit(direction));
+        if wo[2] <= 0.0 || wi[2] <= 0.0 {
+            return 0.0;
+        }
+        let top = self.interface(record);
+        let base = Base {
+            material: &self.base,
+            record,
+            uvw,
+        };
+
+        // Reflection off the coat plus an estimate of the density of paths through the
+        // base, mixed with a uniform density to be safe where the estimate misses
+        let mut pdf = top.pdf(&wo, &wi, Lobe::Any);
+        let entry = top.sample(&wo, Lobe::Transmission);
+        let exit = top.sample(&wi, Lobe::Transmission);
+        if let (Some((w, _, _)), Some((w_exit, _, pdf_i))) = (entry, exit) {
+            if let Some((up, _, pdf_up)) = base.sample(&w) {
+                let pdf_exit = top.pdf(&-up, &wi, Lobe::Transmission);
+                if pdf_up > 0.0 {
+                    let pdf_base = base.pdf(&w, &-w_exit);
+                    pdf += power_heuristic(pdf_i, pdf_base) * pdf_base;
+                    let pdf_refract = top.pdf(&wi, &-up, Lobe::Transmission);
+                    pdf += power_heuristic(pdf_up, pdf_refract) * pdf_exit;
+                } else {
+                    pdf += pdf_exit;
+                }
+            }
+        }
+        0.9 * pdf + 0.1 / (4.0 * PI)
+    }
+}
@@ -1,18 +1,103 @@
-use crate::data::{reflect, refract, vunit, Color};
+use crate::data::{rand_float01, reflect, refract, upsample, vunit, wavelength, Color};
 use crate::engine::{HitRecord, Ray};
+use crate::materials::thin_film::ThinFilm;
 use crate::materials::Material;
-use rand::distributions::Open01;
-use rand::{thread_rng, Rng};
+use nalgebra::Complex;
 use std::sync::Arc;
 
+// Index of refraction, possibly depending on the wavelength (in micrometres in the
+// formulas). RGB rendering uses the value at the sodium d-line, 587.6nm.
+pub enum Ior {
+    Constant(f64),
+    // a + b / wavelength^2
+    Cauchy { a: f64, b: f64 },
+    // n^2 = 1 + sum of b * wavelength^2 / (wavelength^2 - c)
+    Sellmeier { b: [f64; 3], c: [f64; 3] },
+}
+
+impl Ior {
+    pub fn at(&self, wavelength: Option<f64>) -> f64 {
+        let micrometres = wavelength.unwrap_or(587.6) / 1000.0;
+        let l2 = micrometres * micrometres;
+        match self {
+            Ior::Constant(n) => *n,
+            Ior::Cauchy { a, b } => a + b / l2,
+            Ior::Sellmeier { b, c } => {
+                let n2 = 1.0 + (0..3).map(|i| b[i] * l2 / (l2 - c[i])).sum::<f64>();
+                n2.sqrt()
+            }
+        }
+    }
+}
+
 pub struct Dielectric {
-    ref_idx: f64,
+    ior: Ior,
+    // Beer-Lambert absorption coefficient per channel, in 1/distance. Light travelling
+    // d inside keeps exp(-absorption * d) of itself, values above 1 are fine.
+    absorption: Color,
+    film: Option<ThinFilm>,
 }
 
 impl Dielectric {
     pub fn new(ref_idx: f64) -> Dielectric {
-        Dielectric { ref_idx }
+        Dielectric::from_ior(Ior::Constant(ref_idx))
+    }
+
+    pub fn from_ior(ior: Ior) -> Dielectric {
+        Dielectric {
+            ior,
+            absorption: Color::new(0.0, 0.0, 0.0),
+            film: None,
+        }
+    }
+
+    // Schott BK7 borosilicate crown glass
+    pub fn crown_glass() -> Dielectric {
+        Dielectric::from_ior(Ior::Sellmeier {
+            b: [1.03961212, 0.231792344, 1.01046945],
+            c: [0.00600069867, 0.0200179144, 103.560653],
+        })
+    }
+
+    pub fn fused_silica() -> Dielectric {
+        Dielectric::from_ior(Ior::Cauchy {
+            a: 1.4580,
+            b: 0.00354,
+        })
+    }
+
+    pub fn diamond() -> Dielectric {
+        Dielectric::from_ior(Ior::Sellmeier {
+            b: [0.3306, 4.3356, 0.0],
+            c: [0.030625, 0.011236, 0.0],
+        })
     }
+
+    // Tinted glass, thicker parts absorb more following the Beer-Lambert law
+    pub fn with_absorption(mut self, absorption: Color) -> Dielectric {
+        self.absorption = absorption;
+        self
+    }
+
+    // Coated lenses and, with an ior of 1, soap bubbles
+    pub fn with_thin_film(mut self, film: ThinFilm) -> Dielectric {
+        self.film = Some(film);
+        self
+    }
+
+    // Reflectance of the film for light arriving from the side the ray is on
+    fn film_reflectance(&self, record: &HitRecord, cos_theta: f64) -> Option<Color> {
+        let film = self.film.as_ref()?;
+        let indices = |wavelength: f64| {
+            let n = self.ior.at(Some(wavelength));
+            match record.front_face {
+                true => (1.0, Complex::new(n, 0.0)),
+                false => (n, Complex::new(1.0, 0.0)),
+            }
+        };
+        Some(film.reflectance(film.thickness(record), cos_theta, indices))
+    }
+
     pub fn share(self) -> Arc<dyn Material> {
         Arc::new(self)
     }
@@ -27,9 +112,20 @@ impl Material for Dielectric {
         scattered: &mut Ray,
     ) -> bool {
         *attenuation = Color::new(1.0, 1.0, 1.0);
+        if !record.front_face {
+            // Leaving the surface from within, the ray travelled through the medium
+            let distance = record.t * r_in.direction().norm();
+            let absorption = upsample(self.absorption);
+            *attenuation = Color::new(
+                (-absorption.r() * distance).exp(),
+                (-absorption.g() * distance).exp(),
+                (-absorption.b() * distance).exp(),
+            );
+        }
+        let ref_idx = self.ior.at(wavelength());
         let etai_over_etat = match record.front_face {
-            true => 1.0 / self.ref_idx,
-            false => self.ref_idx,
+            true => 1.0 / ref_idx,
+            false => ref_idx,
         };
 
         let unit_direction = vunit(&r_in.direction());
@@ -38,14 +134,27 @@ impl Material for Dielectric {
             cos_theta = 1.0
         }
         let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
-        let reflect_prob = schlick(cos_theta, etai_over_etat);
-        let rand_sample: f64 = thread_rng().sample(Open01);
+        let cannot_refract = etai_over_etat * sin_theta > 1.0;
+        // With a film the reflectance differs per channel, the choice follows the average
+        let reflectance = self.film_reflectance(record, cos_theta);
+        let reflect_prob = match &reflectance {
+            Some(r) => (r.r() + r.g() + r.b()) / 3.0,
+            None => schlick(cos_theta, etai_over_etat),
+        };
+        let rand_sample = rand_float01();
 
-        if etai_over_etat * sin_theta > 1.0 || (rand_sample < reflect_prob) {
+        if cannot_refract || (rand_sample < reflect_prob) {
+            if let (Some(r), false) = (reflectance, cannot_refract) {
+                *attenuation = *attenuation * r / reflect_prob;
+            }
             let reflected = reflect(&unit_direction, &record.normal);
             *scattered = Ray::new(record.p, reflected);
             return true;
         }
+        if let Some(r) = reflectance {
+            let transmitted = Color::new(1.0 - r.r(), 1.0 - r.g(), 1.0 - r.b());
+            *attenuation = *attenuation * transmitted / (1.0 - reflect_prob);
+        }
         let refracted = refract(&unit_direction, &record.normal, etai_over_etat);
         *scattered = Ray::new(record.p, refracted);
         return true;
@@ -57,3 +166,26 @@ fn schlick(cosine: f64, ref_idx: f64) -> f64 {
     r0 = r0 * r0;
     return r0 + (1.0 - r0) * (1.0 - cosine).powi(5);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blue_bends_more_than_red() {
+        let models = [
+            Ior::Cauchy {
+                a: 1.4580,
+                b: 0.00354,
+            },
+            Ior::Sellmeier {
+                b: [1.03961212, 0.231792344, 1.01046945],
+                c: [0.00600069867, 0.0200179144, 103.560653],
+            },
+        ];
+        for ior in models.iter() {
+            assert!(ior.at(Some(450.0)) > ior.at(Some(650.0)));
+            assert!((ior.at(None) - 1.5).abs() < 0.05);
+        }
+    }
+}
@@ -1,38 +1,93 @@
-use crate::materials::Material;
-use crate::textures::{ Texture, SolidColor };
 use crate::data::Color;
+use crate::engine::{HitRecord, Ray};
+use crate::materials::Material;
+use crate::textures::{Blackbody, SolidColor, Texture};
+use std::f64::consts::PI;
 use std::sync::Arc;
 
+// Luminous efficacy of light at 555nm, lumens per watt
+const LUMENS_PER_WATT: f64 = 683.0;
 
 pub struct DiffuseLight {
     emitter: Arc<dyn Texture>,
+    two_sided: bool,
+    scale: f64,
+    // Set for lights given by their power, which is shared out over the emitting faces
+    from_power: bool,
 }
 
 impl DiffuseLight {
-    pub fn from_color(c: Color) -> DiffuseLight{
-        DiffuseLight {emitter: SolidColor::new(c).share() }
+    pub fn from_color(c: Color) -> DiffuseLight {
+        DiffuseLight::from_texture(SolidColor::new(c).share())
+    }
+
+    pub fn from_texture(t: Arc<dyn Texture>) -> DiffuseLight {
+        DiffuseLight {
+            emitter: t,
+            two_sided: true,
+            scale: 1.0,
+            from_power: false,
+        }
+    }
+
+    // Black body glow at a temperature in Kelvin, e.g. 2700 for a household bulb or
+    // 6500 for daylight, with intensity as its luminance
+    pub fn from_temperature(kelvin: f64, intensity: f64) -> DiffuseLight {
+        DiffuseLight::from_texture(Blackbody::new(kelvin, intensity).share())
+    }
+
+    // Light giving off watts in total from a surface of area (in scene units), tinted
+    // by color. Attach it to a single object of that area.
+    pub fn from_power(color: Color, watts: f64, area: f64) -> DiffuseLight {
+        let luminance = color.luminance();
+        let color = match luminance > 0.0 {
+            true => color / luminance,
+            false => Color::new(0.0, 0.0, 0.0),
+        };
+        let mut light = DiffuseLight::from_color(color);
+        light.scale = watts / (2.0 * PI * area.max(1e-12));
+        light.from_power = true;
+        light
     }
 
-    pub fn from_texture(t: Arc<dyn Texture>) -> DiffuseLight{
-        DiffuseLight { emitter:t }
+    pub fn from_lumens(color: Color, lumens: f64, area: f64) -> DiffuseLight {
+        DiffuseLight::from_power(color, lumens / LUMENS_PER_WATT, area)
     }
+
+    // Emit only from the face the surface normal points out of: the outside of spheres,
+    // the positive axis side of rectangles. Lights given by power keep their total.
+    pub fn one_sided(mut self) -> DiffuseLight {
+        if self.two_sided && self.from_power {
+            self.scale *= 2.0;
+        }
+        self.two_sided = false;
+        self
+    }
+
     pub fn share(self) -> Arc<dyn Material> {
         Arc::new(self)
     }
 }
 
-
 impl Material for DiffuseLight {
-    fn emit(&self, u: f64, v: f64, p: &nalgebra::Vector3<f64>) -> crate::data::Color {
-        return self.emitter.value(u, v, p);
+    fn emit(&self, record: &HitRecord) -> Color {
+        if !self.two_sided && !record.front_face {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        self.emitter.value(record.u, record.v, &record.p) * self.scale
     }
+
     fn scatter(
         &self,
-        r_in: &crate::engine::Ray,
-        record: &crate::engine::HitRecord,
-        attenuation: &mut crate::data::Color,
-        scattered: &mut crate::engine::Ray,
+        _r_in: &Ray,
+        _record: &HitRecord,
+        _attenuation: &mut Color,
+        _scattered: &mut Ray,
     ) -> bool {
-        return false;
+        false
+    }
+
+    fn reflects(&self) -> bool {
+        false
     }
 }
@@ -0,0 +1,494 @@
+use crate::data::{rand_float01, upsample, vunit, Color, Onb};
+use crate::engine::{HitRecord, Ray};
+use crate::materials::microfacet::fresnel_dielectric;
+use crate::materials::Material;
+use nalgebra::Vector3;
+use std::f64::consts::{LN_2, PI};
+use std::sync::Arc;
+
+// Lobes followed explicitly: reflection (R), transmission (TT) and one internal
+// reflection (TRT). Longer paths are lumped into a final isotropic lobe.
+const P_MAX: usize = 3;
+
+// Absorption of the two melanin pigments at unit concentration
+const EUMELANIN_SIGMA_A: (f64, f64, f64) = (0.419, 0.697, 1.37);
+const PHEOMELANIN_SIGMA_A: (f64, f64, f64) = (0.187, 0.4, 1.05);
+
+// Hair and fur fibres as dielectric cylinders with pigment absorbing light inside,
+// following Marschner et al. with the energy conserving lobes of Chiang et al. 2016.
+//
+// The fibre runs along u of the surface and where across it a ray hits is taken from the
+// viewing direction, as for a thin cylinder. Meant for thin geometry standing in for
+// strands, or for surfaces covered with fibres combed along u. Taking the offset from
+// the viewing direction isn't quite reciprocal, light traced from the lights (BDPT,
+// photon maps) sees a slightly different fibre.
+pub struct Hair {
+    // Absorption per unit of fibre diameter
+    sigma_a: Color,
+    // Longitudinal and azimuthal roughness, 0 to 1
+    beta_m: f64,
+    beta_n: f64,
+    // Tilt of the cuticle scales in degrees
+    alpha: f64,
+    eta: f64,
+}
+
+impl Hair {
+    pub fn new(sigma_a: Color, beta_m: f64, beta_n: f64) -> Hair {
+        Hair {
+            sigma_a,
+            beta_m: beta_m.clamp(0.01, 1.0),
+            beta_n: beta_n.clamp(0.01, 1.0),
+            alpha: 2.0,
+            eta: 1.55,
+        }
+    }
+
+    // Natural hair colours, from black (eumelanin around 8) through brown to blond
+    // (around 0.3), pheomelanin makes it red
+    pub fn from_melanin(eumelanin: f64, pheomelanin: f64, beta_m: f64, beta_n: f64) -> Hair {
+        let (e, p) = (EUMELANIN_SIGMA_A, PHEOMELANIN_SIGMA_A);
+        let sigma_a = Color::new(
+            eumelanin * e.0 + pheomelanin * p.0,
+            eumelanin * e.1 + pheomelanin * p.1,
+            eumelanin * e.2 + pheomelanin * p.2,
+        );
+        Hair::new(sigma_a, beta_m, beta_n)
+    }
+
+    // Absorption giving roughly color after many bounces through a mass of fibres
+    pub fn from_color(color: Color, beta_m: f64, beta_n: f64) -> Hair {
+        let beta_n = beta_n.clamp(0.01, 1.0);
+        let d = 5.969 - 0.215 * beta_n + 2.532 * beta_n.powi(2) - 10.73 * beta_n.powi(3)
+            + 5.574 * beta_n.powi(4)
+            + 0.245 * beta_n.powi(5);
+        let sigma_a = |c: f64| (c.clamp(1e-4, 1.0).ln() / d).powi(2);
+        let sigma_a = Color::new(sigma_a(color.r()), sigma_a(color.g()), sigma_a(color.b()));
+        Hair::new(sigma_a, beta_m, beta_n)
+    }
+
+    pub fn with_scale_tilt(mut self, alpha: f64) -> Hair {
+        self.alpha = alpha;
+        self
+    }
+
+    pub fn with_ior(mut self, eta: f64) -> Hair {
+        self.eta = eta;
+        self
+    }
+
+    pub fn share(self) -> Arc<dyn Material> {
+        Arc::new(self)
+    }
+
+    // Fibre frame with x along the fibre and z facing the ray
+    fn frame(record: &HitRecord) -> Onb {
+        Onb::build_from_wu(&record.normal, &record.dpdu)
+    }
+
+    fn lobes(&self, wo: &Vector3<f64>) -> Lobes {
+        // Offset across the fibre of the point seen from wo, -1 to 1
+        let across = (wo[1] * wo[1] + wo[2] * wo[2]).sqrt();
+        let h = match across > 0.0 {
+            true => (-wo[1] / across).clamp(-1.0, 1.0),
+            false => 0.0,
+        };
+
+        let beta_m = self.beta_m;
+        let v0 = (0.726 * beta_m + 0.812 * beta_m.powi(2) + 3.7 * beta_m.powi(20)).powi(2);
+        let beta_n = self.beta_n;
+        let s =
+            (PI / 8.0).sqrt() * (0.265 * beta_n + 1.194 * beta_n.powi(2) + 5.372 * beta_n.powi(22));
+
+        let mut sin_2k_alpha = [(self.alpha.to_radians()).sin(), 0.0, 0.0];
+        let mut cos_2k_alpha = [safe_sqrt(1.0 - sin_2k_alpha[0].powi(2)), 0.0, 0.0];
+        for i in 1..3 {
+            sin_2k_alpha[i] = 2.0 * cos_2k_alpha[i - 1] * sin_2k_alpha[i - 1];
+            cos_2k_alpha[i] = cos_2k_alpha[i - 1].powi(2) - sin_2k_alpha[i - 1].powi(2);
+        }
+
+        Lobes {
+            h,
+            gamma_o: h.asin(),
+            eta: self.eta,
+            sigma_a: upsample(self.sigma_a),
+            v: [v0, 0.25 * v0, 4.0 * v0, 4.0 * v0],
+            s,
+            sin_2k_alpha,
+            cos_2k_alpha,
+        }
+    }
+}
+
+// Scattering of one fibre seen from a given direction, directions in the fibre frame
+struct Lobes {
+    h: f64,
+    gamma_o: f64,
+    eta: f64,
+    sigma_a: Color,
+    // Longitudinal variance of each lobe
+    v: [f64; P_MAX + 1],
+    // Azimuthal logistic scale
+    s: f64,
+    sin_2k_alpha: [f64; 3],
+    cos_2k_alpha: [f64; 3],
+}
+
+impl Lobes {
+    // Angles of light leaving the fibre, the scales tilt the lobes
+    fn tilted(&self, p: usize, sin_theta_o: f64, cos_theta_o: f64) -> (f64, f64) {
+        let (sin, cos) = (self.sin_2k_alpha, self.cos_2k_alpha);
+        let (sin_theta_op, cos_theta_op) = match p {
+            0 => (
+                sin_theta_o * cos[1] - cos_theta_o * sin[1],
+                cos_theta_o * cos[1] + sin_theta_o * sin[1],
+            ),
+            1 => (
+                sin_theta_o * cos[0] + cos_theta_o * sin[0],
+                cos_theta_o * cos[0] - sin_theta_o * sin[0],
+            ),
+            2 => (
+                sin_theta_o * cos[2] + cos_theta_o * sin[2],
+                cos_theta_o * cos[2] - sin_theta_o * sin[2],
+            ),
+            _ => (sin_theta_o, cos_theta_o),
+        };
+        (sin_theta_op, cos_theta_op.abs())
+    }
+
+    // Angle of the ray refracted into the fibre, and its transmittance across it
+    fn refracted(&self, sin_theta_o: f64, cos_theta_o: f64) -> (f64, Color) {
+        let sin_theta_t = sin_theta_o / self.eta;
+        let cos_theta_t = safe_sqrt(1.0 - sin_theta_t * sin_theta_t);
+        let etap = (self.eta * self.eta - sin_theta_o * sin_theta_o).sqrt() / cos_theta_o;
+        let sin_gamma_t = (self.h / etap).clamp(-1.0, 1.0);
+        let cos_gamma_t = safe_sqrt(1.0 - sin_gamma_t * sin_gamma_t);
+        let distance = 2.0 * cos_gamma_t / cos_theta_t;
+        let sigma_a = &self.sigma_a;
+        let transmittance = Color::new(
+            (-sigma_a.r() * distance).exp(),
+            (-sigma_a.g() * distance).exp(),
+            (-sigma_a.b() * distance).exp(),
+        );
+        (sin_gamma_t.asin(), transmittance)
+    }
+
+    // Attenuation of each lobe by Fresnel reflection and absorption
+    fn attenuation(&self, cos_theta_o: f64, transmittance: Color) -> [Color; P_MAX + 1] {
+        let cos_gamma_o = safe_sqrt(1.0 - self.h * self.h);
+        let f = fresnel_dielectric(cos_theta_o * cos_gamma_o, self.eta);
+        let white = Color::new(1.0, 1.0, 1.0);
+        let mut ap = [white * f, white, white, white];
+        ap[1] = transmittance * (1.0 - f).powi(2);
+        for p in 2..P_MAX {
+            ap[p] = ap[p - 1] * transmittance * f;
+        }
+        let tf = transmittance * f;
+        let rest = Color::new(
+            tf.r() / (1.0 - tf.r()),
+            tf.g() / (1.0 - tf.g()),
+            tf.b() / (1.0 - tf.b()),
+        );
+        ap[P_MAX] = ap[P_MAX - 1] * rest;
+        ap
+    }
+
+    // Probability of picking each lobe, following its share of the reflected light
+    fn lobe_pdf(&self, cos_theta_o: f64) -> [f64; P_MAX + 1] {
+        let sin_theta_o = safe_sqrt(1.0 - cos_theta_o * cos_theta_o);
+        let (_, transmittance) = self.refracted(sin_theta_o, cos_theta_o);
+        let ap = self.attenuation(cos_theta_o, transmittance);
+        let total: f64 = ap.iter().map(|a| a.luminance()).sum();
+        let mut pdf = [0.0; P_MAX + 1];
+        for p in 0..=P_MAX {
+            pdf[p] = match total > 0.0 {
+                true => ap[p].luminance() / total,
+                false => 1.0 / (P_MAX + 1) as f64,
+            };
+        }
+        pdf
+    }
+
+    // Scattering function times cosine
+    fn f(&self, wo: &Vector3<f64>, wi: &Vector3<f64>) -> Color {
+        let (sin_theta_o, cos_theta_o, phi_o) = angles(wo);
+        let (sin_theta_i, cos_theta_i, phi_i) = angles(wi);
+        let (gamma_t, transmittance) = self.refracted(sin_theta_o, cos_theta_o);
+        let ap = self.attenuation(cos_theta_o, transmittance);
+        let phi = phi_i - phi_o;
+
+        let mut sum = Color::new(0.0, 0.0, 0.0);
+        for (p, ap) in ap.iter().enumerate().take(P_MAX) {
+            let (sin_theta_op, cos_theta_op) = self.tilted(p, sin_theta_o, cos_theta_o);
+            let m = mp(
+                cos_theta_i,
+                cos_theta_op,
+                sin_theta_i,
+                sin_theta_op,
+                self.v[p],
+            );
+            sum = sum + ap * (m * np(phi, p, self.s, self.gamma_o, gamma_t));
+        }
+        let m = mp(
+            cos_theta_i,
+            cos_theta_o,
+            sin_theta_i,
+            sin_theta_o,
+            self.v[P_MAX],
+        );
+        sum + ap[P_MAX] * (m / (2.0 * PI))
+    }
+
+    fn pdf(&self, wo: &Vector3<f64>, wi: &Vector3<f64>) -> f64 {
+        let (sin_theta_o, cos_theta_o, phi_o) = angles(wo);
+        let (sin_theta_i, cos_theta_i, phi_i) = angles(wi);
+        let (gamma_t, _) = self.refracted(sin_theta_o, cos_theta_o);
+        let lobe_pdf = self.lobe_pdf(cos_theta_o);
+        let phi = phi_i - phi_o;
+
+        let mut pdf = 0.0;
+        for (p, lobe_pdf) in lobe_pdf.iter().enumerate().take(P_MAX) {
+            let (sin_theta_op, cos_theta_op) = self.tilted(p, sin_theta_o, cos_theta_o);
+            let m = mp(
+                cos_theta_i,
+                cos_theta_op,
+                sin_theta_i,
+                sin_theta_op,
+                self.v[p],
+            );
+            pdf += m * lobe_pdf * np(phi, p, self.s, self.gamma_o, gamma_t);
+        }
+        let m = mp(
+            cos_theta_i,
+            cos_theta_o,
+            sin_theta_i,
+            sin_theta_o,
+            self.v[P_MAX],
+        );
+        pdf + m * lobe_pdf[P_MAX] / (2.0 * PI)
+    }
+
+    fn sample(&self, wo: &Vector3<f64>) -> Vector3<f64> {
+        let (sin_theta_o, cos_theta_o, phi_o) = angles(wo);
+        let lobe_pdf = self.lobe_pdf(cos_theta_o);
+        let mut u = rand_float01();
+        let mut p = 0;
+        while p < P_MAX && u >= lobe_pdf[p] {
+            u -= lobe_pdf[p];
+            p += 1;
+        }
+
+        // Longitudinal angle around the tilted lobe
+        let (sin_theta_op, cos_theta_op) = self.tilted(p, sin_theta_o, cos_theta_o);
+        let v = self.v[p];
+        let u1 = rand_float01().max(1e-5);
+        let cos_theta = 1.0 + v * (u1 + (1.0 - u1) * (-2.0 / v).exp()).ln();
+        let sin_theta = safe_sqrt(1.0 - cos_theta * cos_theta);
+        let cos_phi = (2.0 * PI * rand_float01()).cos();
+        let sin_theta_i = -cos_theta * sin_theta_op + sin_theta * cos_phi * cos_theta_op;
+        let cos_theta_i = safe_sqrt(1.0 - sin_theta_i * sin_theta_i);
+
+        // Azimuthal offset around the lobe's exit direction
+        let (gamma_t, _) = self.refracted(sin_theta_o, cos_theta_o);
+        let dphi = match p < P_MAX {
+            true => {
+                phi(p, self.gamma_o, gamma_t)
+                    + sample_trimmed_logistic(rand_float01(), self.s, -PI, PI)
+            }
+            false => 2.0 * PI * rand_float01(),
+        };
+        let phi_i = phi_o + dphi;
+        Vector3::new(
+            sin_theta_i,
+            cos_theta_i * phi_i.cos(),
+            cos_theta_i * phi_i.sin(),
+        )
+    }
+}
+
+// Sine and cosine of the angle to the normal plane of the fibre, and the azimuth
+fn angles(w: &Vector3<f64>) -> (f64, f64, f64) {
+    let sin_theta = w[0].clamp(-1.0, 1.0);
+    (
+        sin_theta,
+        safe_sqrt(1.0 - sin_theta * sin_theta),
+        w[2].atan2(w[1]),
+    )
+}
+
+fn safe_sqrt(x: f64) -> f64 {
+    x.max(0.0).sqrt()
+}
+
+// Modified Bessel function of the first kind, order 0
+fn i0(x: f64) -> f64 {
+    let mut value = 0.0;
+    let mut x2i = 1.0;
+    let mut factorial = 1.0;
+    let mut four_i = 1.0;
+    for i in 0..10 {
+        if i > 1 {
+            factorial *= i as f64;
+        }
+        value += x2i / (four_i * factorial * factorial);
+        x2i *= x * x;
+        four_i *= 4.0;
+    }
+    value
+}
+
+fn log_i0(x: f64) -> f64 {
+    match x > 12.0 {
+        true => x + 0.5 * (-(2.0 * PI).ln() + (1.0 / x).ln() + 1.0 / (8.0 * x)),
+        false => i0(x).ln(),
+    }
+}
+
+// Longitudinal scattering
+fn mp(cos_theta_i: f64, cos_theta_o: f64, sin_theta_i: f64, sin_theta_o: f64, v: f64) -> f64 {
+    let a = cos_theta_i * cos_theta_o / v;
+    let b = sin_theta_i * sin_theta_o / v;
+    match v <= 0.1 {
+        // Written with logarithms to stay finite for narrow lobes
+        true => (log_i0(a) - b - 1.0 / v + LN_2 + (1.0 / (2.0 * v)).ln()).exp(),
+        false => (-b).exp() * i0(a) / ((1.0 / v).sinh() * 2.0 * v),
+    }
+}
+
+// Azimuth by which lobe p leaves the fibre relative to the incoming light
+fn phi(p: usize, gamma_o: f64, gamma_t: f64) -> f64 {
+    let p = p as f64;
+    2.0 * p * gamma_t - 2.0 * gamma_o + p * PI
+}
+
+fn logistic(x: f64, s: f64) -> f64 {
+    let x = x.abs();
+    (-x / s).exp() / (s * (1.0 + (-x / s).exp()).powi(2))
+}
+
+fn logistic_cdf(x: f64, s: f64) -> f64 {
+    1.0 / (1.0 + (-x / s).exp())
+}
+
+fn trimmed_logistic(x: f64, s: f64, a: f64, b: f64) -> f64 {
+    logistic(x, s) / (logistic_cdf(b, s) - logistic_cdf(a, s))
+}
+
+fn sample_trimmed_logistic(u: f64, s: f64, a: f64, b: f64) -> f64 {
+    let k = logistic_cdf(b, s) - logistic_cdf(a, s);
+    let x = -s * (1.0 / (u * k + logistic_cdf(a, s)) - 1.0).ln();
+    x.clamp(a, b)
+}
+
+// Azimuthal scattering of lobe p
+fn np(phi_difference: f64, p: usize, s: f64, gamma_o: f64, gamma_t: f64) -> f64 {
+    let mut dphi = phi_difference - phi(p, gamma_o, gamma_t);
+    while dphi > PI {
+        dphi -= 2.0 * PI;
+    }
+    while dphi < -PI {
+        dphi += 2.0 * PI;
+    }
+    trimmed_logistic(dphi, s, -PI, PI)
+}
+
+impl Material for Hair {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        record: &HitRecord,
+        attenuation: &mut Color,
+        scattered: &mut Ray,
+    ) -> bool {
+        let frame = Hair::frame(record);
+        let wo = frame.world_to_local(&-vunit(&r_in.direction()));
+        let lobes = self.lobes(&wo);
+        let wi = lobes.sample(&wo);
+        let pdf = lobes.pdf(&wo, &wi);
+        if pdf <= 0.0 {
+            return false;
+        }
+        *attenuation = lobes.f(&wo, &wi) / pdf;
+        *scattered = Ray::new(record.p, frame.local_vec(&wi));
+        true
+    }
+
+    fn eval(&self, r_in: &Ray, record: &HitRecord, direction: &Vector3<f64>) -> Color {
+        let frame = Hair::frame(record);
+        let wo = frame.world_to_local(&-vunit(&r_in.direction()));
+        let wi = frame.world_to_local(&vunit(direction));
+        self.lobes(&wo).f(&wo, &wi)
+    }
+
+    fn pdf(&self, r_in: &Ray, record: &HitRecord, direction: &Vector3<f64>) -> f64 {
+        let frame = Hair::frame(record);
+        let wo = frame.world_to_local(&-vunit(&r_in.direction()));
+        let wi = frame.world_to_local(&vunit(direction));
+        self.lobes(&wo).pdf(&wo, &wi)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{set_sample_source, SeededSource};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    // White furnace: a fibre that absorbs nothing scatters all the light hitting it,
+    // whatever the roughness and wherever across the fibre it's seen
+    #[test]
+    fn white_fibre_scatters_all_light() {
+        let previous = set_sample_source(Some(Rc::new(RefCell::new(SeededSource::new(11)))));
+        let white = Color::new(0.0, 0.0, 0.0);
+        for &(beta_m, beta_n) in [(0.1, 0.3), (0.3, 0.3), (0.5, 0.8), (0.9, 0.1)].iter() {
+            let hair = Hair::new(white, beta_m, beta_n);
+            for _ in 0..8 {
+                let wo = vunit(&Vector3::new(
+                    2.0 * rand_float01() - 1.0,
+                    2.0 * rand_float01() - 1.0,
+                    rand_float01(),
+                ));
+                let lobes = hair.lobes(&wo);
+                let n = 2000;
+                let mut sum = 0.0;
+                for _ in 0..n {
+                    let wi = lobes.sample(&wo);
+                    let pdf = lobes.pdf(&wo, &wi);
+                    if pdf > 0.0 {
+                        sum += lobes.f(&wo, &wi).luminance() / pdf;
+                    }
+                }
+                let sampled = sum / n as f64;
+
+                // The same integral on a grid of directions, not relying on sample and
+                // pdf agreeing with f
+                let n = 300;
+                let mut sum = 0.0;
+                for i in 0..n {
+                    let z = 2.0 * (i as f64 + 0.5) / n as f64 - 1.0;
+                    let r = safe_sqrt(1.0 - z * z);
+                    for j in 0..n {
+                        let phi = 2.0 * PI * (j as f64 + 0.5) / n as f64;
+                        let wi = Vector3::new(z, r * phi.cos(), r * phi.sin());
+                        sum += lobes.f(&wo, &wi).luminance() * 4.0 * PI;
+                    }
+                }
+                let uniform = sum / (n * n) as f64;
+
+                for &mean in [sampled, uniform].iter() {
+                    assert!(
+                        (mean - 1.0).abs() < 0.02,
+                        "beta_m {} beta_n {} wo {:?}: {} {}",
+                        beta_m,
+                        beta_n,
+                        wo,
+                        sampled,
+                        uniform
+                    );
+                }
+            }
+        }
+        set_sample_source(previous);
+    }
+}
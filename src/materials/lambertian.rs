@@ -1,7 +1,9 @@
-use crate::data::{vrandom, vunit, Color};
+use crate::data::{vrandom_cosine_direction, vunit, Color, Onb};
 use crate::engine::{HitRecord, Ray};
 use crate::materials::Material;
 use crate::textures::{SolidColor, Texture};
+use nalgebra::Vector3;
+use std::f64::consts::PI;
 use std::sync::Arc;
 
 pub struct Lambertian {
@@ -32,9 +34,24 @@ impl Material for Lambertian {
         attenuation: &mut Color,
         scattered: &mut Ray,
     ) -> bool {
-        let scatter_direction = record.normal + vunit(&vrandom());
-        *scattered = *&mut Ray::new(record.p, scatter_direction);
+        // Cosine weighted, so the cosine and pdf cancel out and only albedo is left
+        let uvw = Onb::build_from_w(&record.normal);
+        let scatter_direction = uvw.local_vec(&vrandom_cosine_direction());
+        *scattered = Ray::new(record.p, scatter_direction);
         *attenuation = self.albedo.value(record.u, record.v, &record.p);
         return true;
     }
+
+    fn eval(&self, _r_in: &Ray, record: &HitRecord, direction: &Vector3<f64>) -> Color {
+        let cosine = record.normal.dot(&vunit(direction));
+        if cosine <= 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        self.albedo.value(record.u, record.v, &record.p) * cosine / PI
+    }
+
+    fn pdf(&self, _r_in: &Ray, record: &HitRecord, direction: &Vector3<f64>) -> f64 {
+        let cosine = record.normal.dot(&vunit(direction));
+        cosine.max(0.0) / PI
+    }
 }
@@ -11,7 +11,26 @@ pub trait Material {
         scattered: &mut Ray,
     ) -> bool;
 
-    fn emit(&self, _u: f64, _v: f64, _p: &Vector3<f64>) -> Color {
+    // Radiance given off at the hit, towards where the ray came from
+    fn emit(&self, _record: &HitRecord) -> Color {
         return Color::new(0.0, 0.0, 0.0);
     }
+
+    // BSDF times cosine for light leaving along direction. Perfectly specular
+    // materials can't be evaluated for a given direction and return black.
+    fn eval(&self, _r_in: &Ray, _record: &HitRecord, _direction: &Vector3<f64>) -> Color {
+        return Color::new(0.0, 0.0, 0.0);
+    }
+
+    // Solid angle density with which scatter picks direction, 0 for specular materials
+    fn pdf(&self, _r_in: &Ray, _record: &HitRecord, _direction: &Vector3<f64>) -> f64 {
+        return 0.0;
+    }
+
+    // Whether the surface reflects any light. When scatter fails on one that does (a
+    // layered walk or a sampled direction ended at random) light arriving directly is
+    // still gathered, lights themselves are skipped.
+    fn reflects(&self) -> bool {
+        return true;
+    }
 }
@@ -0,0 +1,57 @@
+use crate::data::{upsample, vunit, Color};
+use crate::engine::{HitRecord, Ray};
+use crate::materials::{Isotropic, Material, PhaseFunction};
+use nalgebra::Vector3;
+use std::sync::Arc;
+
+// Scattering inside a participating medium such as a Volume. albedo is the fraction of
+// light scattered rather than absorbed at each collision, the phase function picks the
+// new direction. Directions aren't measured against a surface, so there is no cosine.
+pub struct Medium {
+    albedo: Color,
+    phase: Arc<dyn PhaseFunction>,
+}
+
+impl Medium {
+    pub fn new(albedo: Color) -> Medium {
+        Medium {
+            albedo,
+            phase: Isotropic::new().share(),
+        }
+    }
+
+    pub fn with_phase(mut self, phase: Arc<dyn PhaseFunction>) -> Medium {
+        self.phase = phase;
+        self
+    }
+
+    pub fn share(self) -> Arc<dyn Material> {
+        Arc::new(self)
+    }
+}
+
+impl Material for Medium {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        record: &HitRecord,
+        attenuation: &mut Color,
+        scattered: &mut Ray,
+    ) -> bool {
+        *attenuation = upsample(self.albedo);
+        *scattered = Ray::new(record.p, self.phase.sample(&vunit(&r_in.direction())));
+        true
+    }
+
+    fn eval(&self, r_in: &Ray, _record: &HitRecord, direction: &Vector3<f64>) -> Color {
+        let phase = self
+            .phase
+            .eval(&vunit(&r_in.direction()), &vunit(direction));
+        upsample(self.albedo) * phase
+    }
+
+    fn pdf(&self, r_in: &Ray, _record: &HitRecord, direction: &Vector3<f64>) -> f64 {
+        self.phase
+            .eval(&vunit(&r_in.direction()), &vunit(direction))
+    }
+}
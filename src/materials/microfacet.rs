@@ -0,0 +1,146 @@
+use crate::data::vunit;
+use nalgebra::Vector3;
+use std::f64::consts::PI;
+
+// Trowbridge-Reitz (GGX) microfacet distribution with Smith masking-shadowing.
+// Directions are in the local shading frame, the macro surface normal is +z.
+#[derive(Debug, Clone, Copy)]
+pub struct TrowbridgeReitz {
+    alpha_x: f64,
+    alpha_y: f64,
+}
+
+impl TrowbridgeReitz {
+    pub fn new(alpha_x: f64, alpha_y: f64) -> TrowbridgeReitz {
+        TrowbridgeReitz { alpha_x, alpha_y }
+    }
+
+    // Perceptually linear roughness, alpha is its square
+    pub fn from_roughness(roughness: f64) -> TrowbridgeReitz {
+        let alpha = roughness * roughness;
+        TrowbridgeReitz::new(alpha, alpha)
+    }
+
+    // Too narrow to be sampled or evaluated reliably, treated as a perfect mirror
+    pub fn is_smooth(&self) -> bool {
+        self.alpha_x.max(self.alpha_y) < 1e-3
+    }
+
+    // Density of microfacet normals
+    pub fn d(&self, wm: &Vector3<f64>) -> f64 {
+        let x = wm[0] / self.alpha_x;
+        let y = wm[1] / self.alpha_y;
+        let e = x * x + y * y + wm[2] * wm[2];
+        1.0 / (PI * self.alpha_x * self.alpha_y * e * e)
+    }
+
+    fn lambda(&self, w: &Vector3<f64>) -> f64 {
+        let z2 = w[2] * w[2];
+        if z2 == 0.0 {
+            return f64::INFINITY;
+        }
+        let ax = self.alpha_x * w[0];
+        let ay = self.alpha_y * w[1];
+        ((1.0 + (ax * ax + ay * ay) / z2).sqrt() - 1.0) / 2.0
+    }
+
+    // Fraction of microfacets facing w that are visible from w
+    pub fn g1(&self, w: &Vector3<f64>) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    // Height correlated masking-shadowing
+    pub fn g(&self, wo: &Vector3<f64>, wi: &Vector3<f64>) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    // Density of normals visible from w, with respect to wm
+    pub fn visible_pdf(&self, w: &Vector3<f64>, wm: &Vector3<f64>) -> f64 {
+        if w[2] == 0.0 {
+            return 0.0;
+        }
+        self.g1(w) * self.d(wm) * w.dot(wm).max(0.0) / w[2].abs()
+    }
+
+    // Samples a normal from the distribution of normals visible from w (Heitz 2018)
+    pub fn sample_visible(&self, w: &Vector3<f64>, u1: f64, u2: f64) -> Vector3<f64> {
+        let w = if w[2] < 0.0 { -w } else { *w };
+        // Stretch to the hemisphere configuration
+        let wh = vunit(&Vector3::new(
+            self.alpha_x * w[0],
+            self.alpha_y * w[1],
+            w[2],
+        ));
+        let length_squared = wh[0] * wh[0] + wh[1] * wh[1];
+        let t1 = match length_squared > 0.0 {
+            true => Vector3::new(-wh[1], wh[0], 0.0) / length_squared.sqrt(),
+            false => Vector3::new(1.0, 0.0, 0.0),
+        };
+        let t2 = wh.cross(&t1);
+
+        // Uniform point on a disk, squeezed onto the visible half
+        let r = u1.sqrt();
+        let phi = 2.0 * PI * u2;
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + wh[2]);
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * r * phi.sin();
+        let nh = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * wh;
+
+        vunit(&Vector3::new(
+            self.alpha_x * nh[0],
+            self.alpha_y * nh[1],
+            nh[2].max(1e-6),
+        ))
+    }
+}
+
+// Fresnel reflectance of a conductor with complex index of refraction eta + ik
+pub fn fresnel_conductor(cos_theta_i: f64, eta: f64, k: f64) -> f64 {
+    let cos2 = cos_theta_i.clamp(0.0, 1.0).powi(2);
+    let sin2 = 1.0 - cos2;
+    let eta2 = eta * eta;
+    let k2 = k * k;
+
+    let t0 = eta2 - k2 - sin2;
+    let a2_plus_b2 = (t0 * t0 + 4.0 * eta2 * k2).sqrt();
+    let t1 = a2_plus_b2 + cos2;
+    let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+    let t2 = 2.0 * cos_theta_i * a;
+    let rs = (t1 - t2) / (t1 + t2);
+
+    let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let rp = rs * (t3 - t4) / (t3 + t4);
+    0.5 * (rp + rs)
+}
+
+// Fresnel reflectance of a dielectric boundary, eta is the index of refraction on the
+// far side relative to the side cos_theta_i is measured on
+pub fn fresnel_dielectric(cos_theta_i: f64, eta: f64) -> f64 {
+    let (cos_theta_i, eta) = match cos_theta_i < 0.0 {
+        true => (-cos_theta_i, 1.0 / eta),
+        false => (cos_theta_i, eta),
+    };
+    let cos_theta_i = cos_theta_i.min(1.0);
+    let sin2_theta_t = (1.0 - cos_theta_i * cos_theta_i) / (eta * eta);
+    if sin2_theta_t >= 1.0 {
+        // Total internal reflection
+        return 1.0;
+    }
+    let cos_theta_t = (1.0 - sin2_theta_t).sqrt();
+    let r_parallel = (eta * cos_theta_i - cos_theta_t) / (eta * cos_theta_i + cos_theta_t);
+    let r_perpendicular = (cos_theta_i - eta * cos_theta_t) / (cos_theta_i + eta * cos_theta_t);
+    (r_parallel * r_parallel + r_perpendicular * r_perpendicular) / 2.0
+}
+
+// Direction refracted through a surface with normal n (on the same side as w), None on
+// total internal reflection. Both directions point away from the surface.
+pub fn refract_local(w: &Vector3<f64>, n: &Vector3<f64>, eta: f64) -> Option<Vector3<f64>> {
+    let cos_theta_i = w.dot(n);
+    let sin2_theta_t = (1.0 - cos_theta_i * cos_theta_i).max(0.0) / (eta * eta);
+    if sin2_theta_t >= 1.0 {
+        return None;
+    }
+    let cos_theta_t = (1.0 - sin2_theta_t).sqrt();
+    Some(-w / eta + (cos_theta_i / eta - cos_theta_t) * n)
+}
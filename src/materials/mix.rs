@@ -0,0 +1,91 @@
+use crate::data::{rand_float01, Color};
+use crate::engine::{HitRecord, Ray};
+use crate::materials::Material;
+use crate::textures::Texture;
+use nalgebra::Vector3;
+use std::sync::Arc;
+
+// Blend of two materials, e.g. rust patches on metal. The mask's red channel is how much
+// of second shows at a hit, the rest is first.
+//
+// The blend can't tell integrators which of the two sampled a direction. Mixing a
+// perfectly smooth material with a rough one, lights seen in the smooth one are weighted
+// as if the rough one could have sampled them too, so give it a little roughness instead.
+pub struct MixMaterial {
+    first: Arc<dyn Material>,
+    second: Arc<dyn Material>,
+    mask: Arc<dyn Texture>,
+}
+
+impl MixMaterial {
+    pub fn new(
+        first: Arc<dyn Material>,
+        second: Arc<dyn Material>,
+        mask: Arc<dyn Texture>,
+    ) -> MixMaterial {
+        MixMaterial {
+            first,
+            second,
+            mask,
+        }
+    }
+
+    pub fn share(self) -> Arc<dyn Material> {
+        Arc::new(self)
+    }
+
+    fn weight(&self, u: f64, v: f64, p: &Vector3<f64>) -> f64 {
+        self.mask.rgb(u, v, p).r().clamp(0.0, 1.0)
+    }
+}
+
+impl Material for MixMaterial {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        record: &HitRecord,
+        attenuation: &mut Color,
+        scattered: &mut Ray,
+    ) -> bool {
+        let weight = self.weight(record.u, record.v, &record.p);
+        let chosen = match rand_float01() < weight {
+            true => &self.second,
+            false => &self.first,
+        };
+        if !chosen.scatter(r_in, record, attenuation, scattered) {
+            return false;
+        }
+
+        // A direction both could have picked is weighted with the whole blend. Otherwise
+        // the chance of picking the material cancels with its share of the blend.
+        let direction = scattered.direction();
+        if chosen.pdf(r_in, record, &direction) > 0.0 {
+            let pdf = self.pdf(r_in, record, &direction);
+            if pdf > 0.0 {
+                *attenuation = self.eval(r_in, record, &direction) / pdf;
+            }
+        }
+        true
+    }
+
+    fn emit(&self, record: &HitRecord) -> Color {
+        let weight = self.weight(record.u, record.v, &record.p);
+        self.first.emit(record) * (1.0 - weight) + self.second.emit(record) * weight
+    }
+
+    fn eval(&self, r_in: &Ray, record: &HitRecord, direction: &Vector3<f64>) -> Color {
+        let weight = self.weight(record.u, record.v, &record.p);
+        self.first.eval(r_in, record, direction) * (1.0 - weight)
+            + self.second.eval(r_in, record, direction) * weight
+    }
+
+    fn pdf(&self, r_in: &Ray, record: &HitRecord, direction: &Vector3<f64>) -> f64 {
+        let weight = self.weight(record.u, record.v, &record.p);
+        self.first.pdf(r_in, record, direction) * (1.0 - weight)
+            + self.second.pdf(r_in, record, direction) * weight
+    }
+
+    fn reflects(&self) -> bool {
+        self.first.reflects() || self.second.reflects()
+    }
+}
@@ -1,11 +1,32 @@
 pub mod metal;
+pub mod bump;
+pub mod coated;
+pub mod hair;
+pub mod medium;
+pub mod microfacet;
+pub mod mix;
+pub mod phase;
 pub mod lambertian;
 pub mod material;
 pub mod dielectric;
 pub mod diffuse_light;
+pub mod principled;
+pub mod rough_dielectric;
+pub mod subsurface;
+pub mod thin_film;
 
 pub use crate::materials::metal::Metal;
 pub use crate::materials::lambertian::Lambertian;
 pub use crate::materials::dielectric::Dielectric;
+pub use crate::materials::rough_dielectric::RoughDielectric;
+pub use crate::materials::principled::Principled;
+pub use crate::materials::medium::Medium;
+pub use crate::materials::mix::MixMaterial;
+pub use crate::materials::phase::{DoubleHenyeyGreenstein, HenyeyGreenstein, Isotropic, PhaseFunction};
+pub use crate::materials::bump::{BumpMap, NormalMap};
+pub use crate::materials::coated::Coated;
+pub use crate::materials::hair::Hair;
+pub use crate::materials::subsurface::Subsurface;
+pub use crate::materials::thin_film::ThinFilm;
 pub use crate::materials::material::Material;
 pub use crate::materials::diffuse_light::DiffuseLight;
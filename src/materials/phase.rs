@@ -0,0 +1,181 @@
+use crate::data::{rand_float01, Onb};
+use nalgebra::Vector3;
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+// Angular distribution of light scattering in a participating medium. Directions are
+// unit vectors of travel, before and after the scattering event.
+pub trait PhaseFunction {
+    // Density of scattering into the new direction, per steradian
+    fn eval(&self, direction: &Vector3<f64>, scattered: &Vector3<f64>) -> f64;
+    // New direction, distributed following eval
+    fn sample(&self, direction: &Vector3<f64>) -> Vector3<f64>;
+    fn share(self) -> Arc<dyn PhaseFunction>;
+}
+
+// Direction at angle theta to the direction of travel, uniform around it
+fn around(direction: &Vector3<f64>, cos_theta: f64) -> Vector3<f64> {
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * PI * rand_float01();
+    let uvw = Onb::build_from_w(direction);
+    uvw.local(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta)
+}
+
+pub struct Isotropic {}
+
+impl Isotropic {
+    pub fn new() -> Isotropic {
+        Isotropic {}
+    }
+}
+
+impl PhaseFunction for Isotropic {
+    fn eval(&self, _direction: &Vector3<f64>, _scattered: &Vector3<f64>) -> f64 {
+        1.0 / (4.0 * PI)
+    }
+
+    fn sample(&self, direction: &Vector3<f64>) -> Vector3<f64> {
+        around(direction, 1.0 - 2.0 * rand_float01())
+    }
+
+    fn share(self) -> Arc<dyn PhaseFunction> {
+        Arc::new(self)
+    }
+}
+
+// g is the average cosine of the scattering angle, positive scatters forward like
+// clouds and smoke, negative back, 0 is isotropic
+pub struct HenyeyGreenstein {
+    g: f64,
+}
+
+impl HenyeyGreenstein {
+    pub fn new(g: f64) -> HenyeyGreenstein {
+        HenyeyGreenstein {
+            g: g.clamp(-0.99, 0.99),
+        }
+    }
+
+    fn density(g: f64, cos_theta: f64) -> f64 {
+        let denominator = 1.0 + g * g - 2.0 * g * cos_theta;
+        (1.0 - g * g) / (4.0 * PI * denominator * denominator.sqrt())
+    }
+
+    fn sample_cos_theta(g: f64) -> f64 {
+        let r = rand_float01();
+        if g.abs() < 1e-3 {
+            return 1.0 - 2.0 * r;
+        }
+        let s = (1.0 - g * g) / (1.0 - g + 2.0 * g * r);
+        ((1.0 + g * g - s * s) / (2.0 * g)).clamp(-1.0, 1.0)
+    }
+}
+
+impl PhaseFunction for HenyeyGreenstein {
+    fn eval(&self, direction: &Vector3<f64>, scattered: &Vector3<f64>) -> f64 {
+        HenyeyGreenstein::density(self.g, direction.dot(scattered))
+    }
+
+    fn sample(&self, direction: &Vector3<f64>) -> Vector3<f64> {
+        around(direction, HenyeyGreenstein::sample_cos_theta(self.g))
+    }
+
+    fn share(self) -> Arc<dyn PhaseFunction> {
+        Arc::new(self)
+    }
+}
+
+// Blend of a forward and a backward lobe, for the bright rim and the back glow of
+// clouds together. weight is the share of the forward lobe.
+pub struct DoubleHenyeyGreenstein {
+    forward: f64,
+    backward: f64,
+    weight: f64,
+}
+
+impl DoubleHenyeyGreenstein {
+    pub fn new(forward: f64, backward: f64, weight: f64) -> DoubleHenyeyGreenstein {
+        DoubleHenyeyGreenstein {
+            forward: forward.clamp(-0.99, 0.99),
+            backward: backward.clamp(-0.99, 0.99),
+            weight: weight.clamp(0.0, 1.0),
+        }
+    }
+}
+
+impl PhaseFunction for DoubleHenyeyGreenstein {
+    fn eval(&self, direction: &Vector3<f64>, scattered: &Vector3<f64>) -> f64 {
+        let cos_theta = direction.dot(scattered);
+        self.weight * HenyeyGreenstein::density(self.forward, cos_theta)
+            + (1.0 - self.weight) * HenyeyGreenstein::density(self.backward, cos_theta)
+    }
+
+    fn sample(&self, direction: &Vector3<f64>) -> Vector3<f64> {
+        let g = match rand_float01() < self.weight {
+            true => self.forward,
+            false => self.backward,
+        };
+        around(direction, HenyeyGreenstein::sample_cos_theta(g))
+    }
+
+    fn share(self) -> Arc<dyn PhaseFunction> {
+        Arc::new(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{set_sample_source, vunit, SeededSource};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    // Histogram of the cosine of the scattering angle against the density integrated
+    // over each bin, the azimuth doesn't change the density
+    fn assert_sample_matches_eval(phase: &dyn PhaseFunction) {
+        let direction = vunit(&Vector3::new(0.3, -0.5, 0.8));
+        let uvw = Onb::build_from_w(&direction);
+        let bins = 50;
+        let samples = 100000;
+        let mut counts = vec![0; bins];
+        for _ in 0..samples {
+            let scattered = phase.sample(&direction);
+            assert!((scattered.norm() - 1.0).abs() < 1e-9);
+            let cos_theta = direction.dot(&scattered);
+            let bin = ((cos_theta + 1.0) / 2.0 * bins as f64) as usize;
+            counts[bin.min(bins - 1)] += 1;
+        }
+
+        let fine = 100;
+        let width = 2.0 / (bins * fine) as f64;
+        for (i, &count) in counts.iter().enumerate() {
+            let expected: f64 = (0..fine)
+                .map(|j| {
+                    let cos_theta = -1.0 + ((i * fine + j) as f64 + 0.5) * width;
+                    let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+                    let scattered = uvw.local(sin_theta, 0.0, cos_theta);
+                    2.0 * PI * phase.eval(&direction, &scattered) * width
+                })
+                .sum();
+            let frequency = count as f64 / samples as f64;
+            let tolerance = 5.0 * (expected / samples as f64).sqrt() + 1e-4;
+            assert!(
+                (frequency - expected).abs() <= tolerance,
+                "bin {}: {} is not close to {}",
+                i,
+                frequency,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn henyey_greenstein_sample_matches_eval() {
+        let previous = set_sample_source(Some(Rc::new(RefCell::new(SeededSource::new(17)))));
+        for &g in [-0.7, 0.0, 0.7].iter() {
+            assert_sample_matches_eval(&HenyeyGreenstein::new(g));
+        }
+        assert_sample_matches_eval(&DoubleHenyeyGreenstein::new(0.8, -0.3, 0.7));
+        set_sample_source(previous);
+    }
+}
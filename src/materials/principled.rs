@@ -0,0 +1,321 @@
+use crate::data::{rand_float01, vrandom_cosine_direction, vunit, Color, Onb};
+use crate::engine::{HitRecord, Ray};
+use crate::materials::microfacet::TrowbridgeReitz;
+use crate::materials::rough_dielectric::{
+    eval_rough_dielectric, local_directions, pdf_rough_dielectric, sample_rough_dielectric,
+};
+use crate::materials::Material;
+use crate::textures::{SolidColor, Texture};
+use nalgebra::Vector3;
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+// Disney style principled material. Diffuse (with Burley retro-reflection and sheen),
+// specular, clearcoat and rough glass lobes are blended from artist friendly parameters,
+// each of them a texture. Scalar parameters are read from the red channel.
+//
+// metallic fades diffuse and glass out and tints the specular lobe with base_color,
+// transmission fades diffuse into glass tinted by base_color, specular scales the
+// reflectance of the non metallic part (0.5 is 4%, typical for plastics).
+pub struct Principled {
+    base_color: Arc<dyn Texture>,
+    metallic: Arc<dyn Texture>,
+    roughness: Arc<dyn Texture>,
+    specular: Arc<dyn Texture>,
+    clearcoat: Arc<dyn Texture>,
+    clearcoat_roughness: Arc<dyn Texture>,
+    sheen: Arc<dyn Texture>,
+    sheen_tint: Arc<dyn Texture>,
+    transmission: Arc<dyn Texture>,
+    ior: f64,
+}
+
+// Parameters looked up at a hit
+struct Parameters {
+    base_color: Color,
+    metallic: f64,
+    specular: Color,
+    clearcoat: f64,
+    sheen: Color,
+    transmission: f64,
+    roughness: f64,
+    distribution: TrowbridgeReitz,
+    clearcoat_distribution: TrowbridgeReitz,
+    eta: f64,
+}
+
+// Lobe selection probabilities
+struct Lobes {
+    diffuse: f64,
+    specular: f64,
+    clearcoat: f64,
+    glass: f64,
+}
+
+fn schlick(f0: Color, cos_theta: f64) -> Color {
+    let weight = (1.0 - cos_theta).clamp(0.0, 1.0).powi(5);
+    f0 * (1.0 - weight) + weight
+}
+
+// Lobes narrower than this are delta distributions, which a blend can't evaluate
+const MIN_ROUGHNESS: f64 = 0.04;
+
+impl Principled {
+    pub fn new(base_color: Color) -> Principled {
+        Principled {
+            base_color: SolidColor::new(base_color).share(),
+            metallic: SolidColor::scalar(0.0).share(),
+            roughness: SolidColor::scalar(0.5).share(),
+            specular: SolidColor::scalar(0.5).share(),
+            clearcoat: SolidColor::scalar(0.0).share(),
+            clearcoat_roughness: SolidColor::scalar(0.1).share(),
+            sheen: SolidColor::scalar(0.0).share(),
+            sheen_tint: SolidColor::scalar(0.5).share(),
+            transmission: SolidColor::scalar(0.0).share(),
+            ior: 1.5,
+        }
+    }
+
+    pub fn with_base_color(mut self, texture: Arc<dyn Texture>) -> Principled {
+        self.base_color = texture;
+        self
+    }
+
+    pub fn with_metallic(mut self, texture: Arc<dyn Texture>) -> Principled {
+        self.metallic = texture;
+        self
+    }
+
+    pub fn with_roughness(mut self, texture: Arc<dyn Texture>) -> Principled {
+        self.roughness = texture;
+        self
+    }
+
+    pub fn with_specular(mut self, texture: Arc<dyn Texture>) -> Principled {
+        self.specular = texture;
+        self
+    }
+
+    pub fn with_clearcoat(mut self, texture: Arc<dyn Texture>) -> Principled {
+        self.clearcoat = texture;
+        self
+    }
+
+    pub fn with_clearcoat_roughness(mut self, texture: Arc<dyn Texture>) -> Principled {
+        self.clearcoat_roughness = texture;
+        self
+    }
+
+    pub fn with_sheen(mut self, texture: Arc<dyn Texture>) -> Principled {
+        self.sheen = texture;
+        self
+    }
+
+    pub fn with_sheen_tint(mut self, texture: Arc<dyn Texture>) -> Principled {
+        self.sheen_tint = texture;
+        self
+    }
+
+    pub fn with_transmission(mut self, texture: Arc<dyn Texture>) -> Principled {
+        self.transmission = texture;
+        self
+    }
+
+    pub fn with_ior(mut self, ior: f64) -> Principled {
+        self.ior = ior;
+        self
+    }
+
+    pub fn share(self) -> Arc<dyn Material> {
+        Arc::new(self)
+    }
+
+    fn parameters(&self, record: &HitRecord) -> Parameters {
+        let scalar = |texture: &Arc<dyn Texture>| {
+            texture
+                .rgb(record.u, record.v, &record.p)
+                .r()
+                .clamp(0.0, 1.0)
+        };
+        let base_color = self.base_color.value(record.u, record.v, &record.p);
+        let metallic = scalar(&self.metallic);
+        let roughness = scalar(&self.roughness).max(MIN_ROUGHNESS);
+
+        let dielectric_f0 = 0.08 * scalar(&self.specular);
+        let specular = Color::new(dielectric_f0, dielectric_f0, dielectric_f0) * (1.0 - metallic)
+            + base_color * metallic;
+
+        // Sheen is tinted towards the hue of the base color
+        let luminance = base_color.luminance();
+        let tint = match luminance > 0.0 {
+            true => base_color / luminance,
+            false => Color::new(1.0, 1.0, 1.0),
+        };
+        let sheen_tint = scalar(&self.sheen_tint);
+        let sheen = (Color::new(1.0, 1.0, 1.0) * (1.0 - sheen_tint) + tint * sheen_tint)
+            * scalar(&self.sheen);
+
+        Parameters {
+            base_color,
+            metallic,
+            specular,
+            clearcoat: scalar(&self.clearcoat),
+            sheen,
+            transmission: scalar(&self.transmission),
+            roughness,
+            distribution: TrowbridgeReitz::from_roughness(roughness),
+            clearcoat_distribution: TrowbridgeReitz::from_roughness(
+                scalar(&self.clearcoat_roughness).max(MIN_ROUGHNESS),
+            ),
+            eta: match record.front_face {
+                true => self.ior,
+                false => 1.0 / self.ior,
+            },
+        }
+    }
+}
+
+impl Parameters {
+    fn diffuse_weight(&self) -> f64 {
+        (1.0 - self.metallic) * (1.0 - self.transmission)
+    }
+
+    fn glass_weight(&self) -> f64 {
+        (1.0 - self.metallic) * self.transmission
+    }
+
+    // Rough estimates of how much each lobe reflects towards wo
+    fn lobes(&self, wo: &Vector3<f64>) -> Option<Lobes> {
+        let diffuse =
+            self.diffuse_weight() * (self.base_color.luminance() + self.sheen.luminance());
+        let specular = (1.0 - self.glass_weight()) * schlick(self.specular, wo[2]).luminance();
+        let clearcoat = 0.25 * self.clearcoat * schlick(Color::new(0.04, 0.04, 0.04), wo[2]).r();
+        let glass = self.glass_weight();
+        let total = diffuse + specular + clearcoat + glass;
+        if total <= 0.0 {
+            return None;
+        }
+        Some(Lobes {
+            diffuse: diffuse / total,
+            specular: specular / total,
+            clearcoat: clearcoat / total,
+            glass: glass / total,
+        })
+    }
+
+    // f * |cos| in the shading frame, wo is above the surface
+    fn eval(&self, wo: &Vector3<f64>, wi: &Vector3<f64>) -> Color {
+        let black = Color::new(0.0, 0.0, 0.0);
+        if wo[2] <= 0.0 || wi[2] == 0.0 {
+            return black;
+        }
+        let glass =
+            eval_rough_dielectric(&self.distribution, wo, wi, self.eta) * self.glass_weight();
+        if wi[2] < 0.0 {
+            return self.base_color * glass;
+        }
+
+        let wh = vunit(&(wo + wi));
+        let cos_d = wi.dot(&wh);
+
+        // Burley diffuse with retro-reflection at grazing angles, and sheen on top
+        let fd90 = 0.5 + 2.0 * self.roughness * cos_d * cos_d;
+        let fl = (1.0 - wi[2]).powi(5);
+        let fv = (1.0 - wo[2]).powi(5);
+        let fd = (1.0 + (fd90 - 1.0) * fl) * (1.0 + (fd90 - 1.0) * fv);
+        let diffuse = (self.base_color * (fd / PI) + self.sheen * (1.0 - cos_d).powi(5))
+            * (wi[2] * self.diffuse_weight());
+
+        let specular = schlick(self.specular, wo.dot(&wh))
+            * (self.distribution.d(&wh) * self.distribution.g(wo, wi) / (4.0 * wo[2])
+                * (1.0 - self.glass_weight()));
+
+        let coat = &self.clearcoat_distribution;
+        let clearcoat = 0.25
+            * self.clearcoat
+            * schlick(Color::new(0.04, 0.04, 0.04), wo.dot(&wh)).r()
+            * coat.d(&wh)
+            * coat.g(wo, wi)
+            / (4.0 * wo[2]);
+
+        diffuse + specular + (glass + clearcoat)
+    }
+
+    fn pdf(&self, wo: &Vector3<f64>, wi: &Vector3<f64>) -> f64 {
+        let lobes = match self.lobes(wo) {
+            Some(lobes) => lobes,
+            None => return 0.0,
+        };
+        let mut pdf = lobes.glass * pdf_rough_dielectric(&self.distribution, wo, wi, self.eta);
+        if wi[2] <= 0.0 {
+            return pdf;
+        }
+        let wh = vunit(&(wo + wi));
+        pdf += lobes.diffuse * wi[2] / PI;
+        pdf += lobes.specular * self.distribution.visible_pdf(wo, &wh) / (4.0 * wo.dot(&wh));
+        pdf += lobes.clearcoat * self.clearcoat_distribution.visible_pdf(wo, &wh)
+            / (4.0 * wo.dot(&wh));
+        pdf
+    }
+}
+
+fn reflect_about(wo: &Vector3<f64>, distribution: &TrowbridgeReitz) -> Vector3<f64> {
+    let wm = distribution.sample_visible(wo, rand_float01(), rand_float01());
+    2.0 * wo.dot(&wm) * wm - wo
+}
+
+impl Material for Principled {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        record: &HitRecord,
+        attenuation: &mut Color,
+        scattered: &mut Ray,
+    ) -> bool {
+        let uvw = Onb::build_from_w(&record.normal);
+        let wo = uvw.world_to_local(&-vunit(&r_in.direction()));
+        if wo[2] <= 0.0 {
+            return false;
+        }
+        let parameters = self.parameters(record);
+        let lobes = match parameters.lobes(&wo) {
+            Some(lobes) => lobes,
+            None => return false,
+        };
+
+        // One lobe samples the direction, which is then weighted with all of them
+        let u = rand_float01();
+        let wi = if u < lobes.diffuse {
+            vrandom_cosine_direction()
+        } else if u < lobes.diffuse + lobes.specular {
+            reflect_about(&wo, &parameters.distribution)
+        } else if u < lobes.diffuse + lobes.specular + lobes.clearcoat {
+            reflect_about(&wo, &parameters.clearcoat_distribution)
+        } else {
+            match sample_rough_dielectric(&parameters.distribution, &wo, parameters.eta) {
+                Some((wi, _)) => wi,
+                None => return false,
+            }
+        };
+
+        let pdf = parameters.pdf(&wo, &wi);
+        if pdf <= 0.0 {
+            return false;
+        }
+        *scattered = Ray::new(record.p, uvw.local_vec(&wi));
+        *attenuation = parameters.eval(&wo, &wi) / pdf;
+        true
+    }
+
+    fn eval(&self, r_in: &Ray, record: &HitRecord, direction: &Vector3<f64>) -> Color {
+        let parameters = self.parameters(record);
+        let (wo, wi, eta) = local_directions(r_in, record, direction, parameters.eta);
+        Parameters { eta, ..parameters }.eval(&wo, &wi)
+    }
+
+    fn pdf(&self, r_in: &Ray, record: &HitRecord, direction: &Vector3<f64>) -> f64 {
+        let parameters = self.parameters(record);
+        let (wo, wi, eta) = local_directions(r_in, record, direction, parameters.eta);
+        Parameters { eta, ..parameters }.pdf(&wo, &wi)
+    }
+}
@@ -0,0 +1,295 @@
+use crate::data::{rand_float01, vunit, Color, Onb};
+use crate::engine::{HitRecord, Ray};
+use crate::materials::microfacet::{fresnel_dielectric, refract_local, TrowbridgeReitz};
+use crate::materials::Material;
+use crate::textures::{SolidColor, Texture};
+use nalgebra::Vector3;
+use std::sync::Arc;
+
+// Frosted glass: GGX microfacets both reflect and refract, split by the exact Fresnel
+// term of each microfacet. Roughness is read from the red channel of a texture.
+//
+// Transmission is scaled by 1/eta rather than the 1/eta^2 radiance picks up when crossing
+// the boundary. That makes the BSDF symmetric, so bidirectional methods can evaluate it
+// from either end of a path, and the factors cancel once a path enters and leaves again.
+pub struct RoughDielectric {
+    ref_idx: f64,
+    roughness: Arc<dyn Texture>,
+}
+
+impl RoughDielectric {
+    pub fn new(ref_idx: f64, roughness: f64) -> RoughDielectric {
+        RoughDielectric {
+            ref_idx,
+            roughness: SolidColor::scalar(roughness).share(),
+        }
+    }
+
+    pub fn with_roughness_texture(mut self, roughness: Arc<dyn Texture>) -> RoughDielectric {
+        self.roughness = roughness;
+        self
+    }
+
+    pub fn share(self) -> Arc<dyn Material> {
+        Arc::new(self)
+    }
+
+    fn distribution(&self, record: &HitRecord) -> TrowbridgeReitz {
+        let roughness = self.roughness.rgb(record.u, record.v, &record.p).r();
+        TrowbridgeReitz::from_roughness(roughness.clamp(0.0, 1.0))
+    }
+
+    fn eta(&self, record: &HitRecord) -> f64 {
+        match record.front_face {
+            true => self.ref_idx,
+            false => 1.0 / self.ref_idx,
+        }
+    }
+}
+
+// Local shading frame directions of a query, flipped so that wo is above the surface.
+// eta is the index of refraction below the surface relative to above it.
+pub fn local_directions(
+    r_in: &Ray,
+    record: &HitRecord,
+    direction: &Vector3<f64>,
+    eta: f64,
+) -> (Vector3<f64>, Vector3<f64>, f64) {
+    let uvw = Onb::build_from_w(&record.normal);
+    let mut wo = uvw.world_to_local(&-vunit(&r_in.direction()));
+    let mut wi = uvw.world_to_local(&vunit(direction));
+    let mut eta = eta;
+    // Bidirectional methods also ask about light arriving from the far side
+    if wo[2] < 0.0 {
+        wo[2] = -wo[2];
+        wi[2] = -wi[2];
+        eta = 1.0 / eta;
+    }
+    (wo, wi, eta)
+}
+
+// The rough dielectric lobe in the local shading frame, also used by other materials.
+// Samples wi, returned together with f * |cos| / pdf.
+pub fn sample_rough_dielectric(
+    distribution: &TrowbridgeReitz,
+    wo: &Vector3<f64>,
+    eta: f64,
+) -> Option<(Vector3<f64>, f64)> {
+    let (wm, weight) = match distribution.is_smooth() {
+        true => (Vector3::new(0.0, 0.0, 1.0), 1.0),
+        false => {
+            let wm = distribution.sample_visible(wo, rand_float01(), rand_float01());
+            (wm, 1.0 / distribution.g1(wo))
+        }
+    };
+
+    // Reflection and transmission are picked in proportion to the Fresnel term, which
+    // then cancels out of the weight
+    let reflectance = fresnel_dielectric(wo.dot(&wm), eta);
+    let wi = match rand_float01() < reflectance {
+        true => 2.0 * wo.dot(&wm) * wm - wo,
+        false => refract_local(wo, &wm, eta)?,
+    };
+    let reflected = wi[2] > 0.0;
+    if reflected != (wo.dot(&wm) * wi.dot(&wm) > 0.0) {
+        // Left through the wrong side of the macro surface
+        return None;
+    }
+    let g = match distribution.is_smooth() {
+        true => 1.0,
+        false => distribution.g(wo, &wi),
+    };
+    let scale = if reflected { 1.0 } else { 1.0 / eta };
+    Some((wi, g * weight * scale))
+}
+
+// Microfacet normal turning wo into wi by refraction, facing wo
+fn transmission_normal(wo: &Vector3<f64>, wi: &Vector3<f64>, eta: f64) -> Option<Vector3<f64>> {
+    let mut wm = vunit(&(wi * eta + wo));
+    if wm[2] < 0.0 {
+        wm = -wm;
+    }
+    // Refraction through a microfacet seen from behind is impossible
+    if wm.dot(wi) >= 0.0 || wm.dot(wo) <= 0.0 {
+        return None;
+    }
+    Some(wm)
+}
+
+// f * |cos| of the rough dielectric lobe
+pub fn eval_rough_dielectric(
+    distribution: &TrowbridgeReitz,
+    wo: &Vector3<f64>,
+    wi: &Vector3<f64>,
+    eta: f64,
+) -> f64 {
+    if distribution.is_smooth() || wo[2] <= 0.0 || wi[2] == 0.0 {
+        return 0.0;
+    }
+    if wi[2] > 0.0 {
+        let wm = vunit(&(wo + wi));
+        let reflectance = fresnel_dielectric(wo.dot(&wm), eta);
+        return reflectance * distribution.d(&wm) * distribution.g(wo, wi) / (4.0 * wo[2]);
+    }
+    let wm = match transmission_normal(wo, wi, eta) {
+        Some(wm) => wm,
+        None => return 0.0,
+    };
+    let transmittance = 1.0 - fresnel_dielectric(wo.dot(&wm), eta);
+    let denom = (wi.dot(&wm) + wo.dot(&wm) / eta).powi(2);
+    transmittance * distribution.d(&wm) * distribution.g(wo, wi) * (wi.dot(&wm) * wo.dot(&wm)).abs()
+        / (wo[2] * denom * eta)
+}
+
+// Solid angle density with which sample_rough_dielectric picks wi
+pub fn pdf_rough_dielectric(
+    distribution: &TrowbridgeReitz,
+    wo: &Vector3<f64>,
+    wi: &Vector3<f64>,
+    eta: f64,
+) -> f64 {
+    if distribution.is_smooth() || wo[2] <= 0.0 || wi[2] == 0.0 {
+        return 0.0;
+    }
+    if wi[2] > 0.0 {
+        let wm = vunit(&(wo + wi));
+        let reflectance = fresnel_dielectric(wo.dot(&wm), eta);
+        return reflectance * distribution.visible_pdf(wo, &wm) / (4.0 * wo.dot(&wm));
+    }
+    let wm = match transmission_normal(wo, wi, eta) {
+        Some(wm) => wm,
+        None => return 0.0,
+    };
+    let transmittance = 1.0 - fresnel_dielectric(wo.dot(&wm), eta);
+    let denom = (wi.dot(&wm) + wo.dot(&wm) / eta).powi(2);
+    transmittance * distribution.visible_pdf(wo, &wm) * wi.dot(&wm).abs() / denom
+}
+
+impl Material for RoughDielectric {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        record: &HitRecord,
+        attenuation: &mut Color,
+        scattered: &mut Ray,
+    ) -> bool {
+        let uvw = Onb::build_from_w(&record.normal);
+        let wo = uvw.world_to_local(&-vunit(&r_in.direction()));
+        if wo[2] <= 0.0 {
+            return false;
+        }
+        let distribution = self.distribution(record);
+        match sample_rough_dielectric(&distribution, &wo, self.eta(record)) {
+            Some((wi, weight)) => {
+                *scattered = Ray::new(record.p, uvw.local_vec(&wi));
+                *attenuation = Color::new(weight, weight, weight);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn eval(&self, r_in: &Ray, record: &HitRecord, direction: &Vector3<f64>) -> Color {
+        let (wo, wi, eta) = local_directions(r_in, record, direction, self.eta(record));
+        let value = eval_rough_dielectric(&self.distribution(record), &wo, &wi, eta);
+        Color::new(value, value, value)
+    }
+
+    fn pdf(&self, r_in: &Ray, record: &HitRecord, direction: &Vector3<f64>) -> f64 {
+        let (wo, wi, eta) = local_directions(r_in, record, direction, self.eta(record));
+        pdf_rough_dielectric(&self.distribution(record), &wo, &wi, eta)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{set_sample_source, SeededSource};
+    use std::cell::RefCell;
+    use std::f64::consts::PI;
+    use std::rc::Rc;
+
+    // Equal solid angle bins over the whole sphere, z in rows and the azimuth in columns
+    const ROWS: usize = 20;
+    const COLUMNS: usize = 40;
+
+    fn direction(z: f64, phi: f64) -> Vector3<f64> {
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        Vector3::new(r * phi.cos(), r * phi.sin(), z)
+    }
+
+    fn bin(w: &Vector3<f64>) -> usize {
+        let row = ((w[2] + 1.0) / 2.0 * ROWS as f64) as usize;
+        let phi = w[1].atan2(w[0]).rem_euclid(2.0 * PI);
+        let column = (phi / (2.0 * PI) * COLUMNS as f64) as usize;
+        row.min(ROWS - 1) * COLUMNS + column.min(COLUMNS - 1)
+    }
+
+    // Chance of a direction in each bin, integrating the pdf on a finer grid
+    fn expected(pdf: impl Fn(&Vector3<f64>) -> f64) -> Vec<f64> {
+        let fine = 6;
+        let solid_angle = 4.0 * PI / (ROWS * COLUMNS * fine * fine) as f64;
+        let mut expected = vec![0.0; ROWS * COLUMNS];
+        for i in 0..ROWS * fine {
+            let z = (i as f64 + 0.5) / (ROWS * fine) as f64 * 2.0 - 1.0;
+            for j in 0..COLUMNS * fine {
+                let phi = (j as f64 + 0.5) / (COLUMNS * fine) as f64 * 2.0 * PI;
+                let w = direction(z, phi);
+                expected[bin(&w)] += pdf(&w) * solid_angle;
+            }
+        }
+        expected
+    }
+
+    // Principled shares these functions, and Metal the sampling of visible normals
+    // they rest on
+    #[test]
+    fn rough_dielectric_sample_matches_pdf() {
+        let previous = set_sample_source(Some(Rc::new(RefCell::new(SeededSource::new(13)))));
+        let cases = [
+            (0.5, 1.5, 0.8),
+            (0.3, 1.5, 0.3),
+            (0.5, 1.0 / 1.5, 0.9),
+            (0.7, 1.33, 0.5),
+        ];
+        for &(roughness, eta, cos_theta_o) in cases.iter() {
+            let distribution = TrowbridgeReitz::from_roughness(roughness);
+            let wo = direction(cos_theta_o, 0.4);
+            let samples = 100000;
+            let mut counts = vec![0; ROWS * COLUMNS];
+            for _ in 0..samples {
+                let (wi, weight) = match sample_rough_dielectric(&distribution, &wo, eta) {
+                    Some(sample) => sample,
+                    None => continue,
+                };
+                counts[bin(&wi)] += 1;
+                let pdf = pdf_rough_dielectric(&distribution, &wo, &wi, eta);
+                let f = eval_rough_dielectric(&distribution, &wo, &wi, eta);
+                assert!(pdf > 0.0);
+                assert!(
+                    (weight - f / pdf).abs() <= 1e-9 * weight.max(1.0),
+                    "{} is not {} / {}",
+                    weight,
+                    f,
+                    pdf
+                );
+            }
+
+            let expected = expected(|wi| pdf_rough_dielectric(&distribution, &wo, wi, eta));
+            for (i, &count) in counts.iter().enumerate() {
+                let frequency = count as f64 / samples as f64;
+                let tolerance = 5.0 * (expected[i] / samples as f64).sqrt() + 1e-3;
+                assert!(
+                    (frequency - expected[i]).abs() <= tolerance,
+                    "roughness {} eta {}, bin {}: {} is not close to {}",
+                    roughness,
+                    eta,
+                    i,
+                    frequency,
+                    expected[i]
+                );
+            }
+        }
+        set_sample_source(previous);
+    }
+}
@@ -0,0 +1,109 @@
+use crate::data::{rand_float01, upsample, vunit, Color};
+use crate::engine::{HitRecord, Ray};
+use crate::materials::{Dielectric, HenyeyGreenstein, Isotropic, Material, PhaseFunction};
+use std::sync::Arc;
+
+// Translucent solid scattering light below its surface, like skin, wax or marble. The
+// surface is a smooth dielectric, light refracted in takes a random walk through the
+// scattering medium filling the object until it finds its way out again or is absorbed.
+//
+// The walk needs the object's geometry, so it happens one step per ray: a ray travelling
+// inside that reaches the surface first checks whether it would have scattered on the
+// way, and if so carries on from that point inside in a new direction instead. Each such
+// step counts as a bounce, dense media with a high albedo want a higher max depth.
+pub struct Subsurface {
+    boundary: Dielectric,
+    // Fraction of light surviving each scattering event, per channel
+    albedo: Color,
+    // Average distance travelled between scattering events, per channel, in world units
+    mean_free_path: Color,
+    phase: Arc<dyn PhaseFunction>,
+}
+
+impl Subsurface {
+    pub fn new(ior: f64, albedo: Color, mean_free_path: Color) -> Subsurface {
+        Subsurface {
+            boundary: Dielectric::new(ior),
+            albedo,
+            mean_free_path,
+            phase: Isotropic::new().share(),
+        }
+    }
+
+    // Henyey-Greenstein scattering, positive anisotropy scatters forward
+    pub fn with_anisotropy(self, anisotropy: f64) -> Subsurface {
+        self.with_phase(HenyeyGreenstein::new(anisotropy).share())
+    }
+
+    pub fn with_phase(mut self, phase: Arc<dyn PhaseFunction>) -> Subsurface {
+        self.phase = phase;
+        self
+    }
+
+    pub fn share(self) -> Arc<dyn Material> {
+        Arc::new(self)
+    }
+
+    // Extinction coefficient, the density of scattering events per unit distance
+    fn sigma_t(&self) -> [f64; 3] {
+        let mean_free_path = upsample(self.mean_free_path);
+        [mean_free_path.r(), mean_free_path.g(), mean_free_path.b()].map(|m| 1.0 / m.max(1e-6))
+    }
+}
+
+fn transmittance(sigma_t: &[f64; 3], distance: f64) -> Color {
+    Color::new(
+        (-sigma_t[0] * distance).exp(),
+        (-sigma_t[1] * distance).exp(),
+        (-sigma_t[2] * distance).exp(),
+    )
+}
+
+impl Material for Subsurface {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        record: &HitRecord,
+        attenuation: &mut Color,
+        scattered: &mut Ray,
+    ) -> bool {
+        if record.front_face {
+            return self.boundary.scatter(r_in, record, attenuation, scattered);
+        }
+
+        // The ray travelled inside up to the surface. Scattering distances are sampled
+        // with the mean free path of one channel picked at random, weighted with the
+        // average density over all three so every channel stays unbiased.
+        let sigma_t = self.sigma_t();
+        let direction = vunit(&r_in.direction());
+        let distance = record.t * r_in.direction().norm();
+        let channel = ((rand_float01() * 3.0) as usize).min(2);
+        let step = -(1.0 - rand_float01()).ln() / sigma_t[channel];
+
+        if step < distance {
+            let tr = transmittance(&sigma_t, step);
+            let pdf = (sigma_t[0] * tr.r() + sigma_t[1] * tr.g() + sigma_t[2] * tr.b()) / 3.0;
+            let sigma_s = upsample(self.albedo);
+            let sigma_s = Color::new(
+                sigma_s.r() * sigma_t[0],
+                sigma_s.g() * sigma_t[1],
+                sigma_s.b() * sigma_t[2],
+            );
+            *attenuation = sigma_s * tr / pdf;
+            *scattered = Ray::new(
+                r_in.origin() + direction * step,
+                self.phase.sample(&direction),
+            );
+            return true;
+        }
+
+        // Reached the surface unscattered, it either reflects back in or lets the ray out
+        let tr = transmittance(&sigma_t, distance);
+        let pdf = (tr.r() + tr.g() + tr.b()) / 3.0;
+        if pdf <= 0.0 || !self.boundary.scatter(r_in, record, attenuation, scattered) {
+            return false;
+        }
+        *attenuation = *attenuation * tr / pdf;
+        true
+    }
+}
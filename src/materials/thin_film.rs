@@ -0,0 +1,110 @@
+use crate::data::{spectrum_to_rgb, wavelength, Color};
+use crate::engine::HitRecord;
+use crate::textures::{SolidColor, Texture};
+use nalgebra::{Complex, ComplexField};
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+// Transparent film a few hundred nanometres thick on top of a Dielectric or Metal, like
+// soap, oil or the coating of a lens. Light reflected at its top and bottom interferes,
+// so how much is reflected depends on wavelength, film thickness and viewing angle.
+pub struct ThinFilm {
+    // In nanometres
+    thickness: f64,
+    ior: f64,
+    // Red channel scales the thickness, e.g. noise for the swirls on a soap bubble
+    variation: Arc<dyn Texture>,
+}
+
+impl ThinFilm {
+    pub fn new(thickness: f64, ior: f64) -> ThinFilm {
+        ThinFilm {
+            thickness,
+            ior,
+            variation: SolidColor::scalar(1.0).share(),
+        }
+    }
+
+    pub fn with_thickness_texture(mut self, variation: Arc<dyn Texture>) -> ThinFilm {
+        self.variation = variation;
+        self
+    }
+
+    pub(crate) fn thickness(&self, record: &HitRecord) -> f64 {
+        let scale = self.variation.rgb(record.u, record.v, &record.p).r();
+        self.thickness * scale.max(0.0)
+    }
+
+    // Reflectance for light arriving at cos_theta through a medium onto the film over a
+    // substrate (complex for conductors), indices gives both for a wavelength. Spectral
+    // rendering evaluates it at the traced wavelength, RGB averages over the spectrum.
+    pub(crate) fn reflectance<F>(&self, thickness: f64, cos_theta: f64, indices: F) -> Color
+    where
+        F: Fn(f64) -> (f64, Complex<f64>),
+    {
+        let at = |wavelength: f64| {
+            let (incident, substrate) = indices(wavelength);
+            airy_reflectance(
+                cos_theta, incident, self.ior, substrate, thickness, wavelength,
+            )
+        };
+        match wavelength() {
+            Some(wavelength) => {
+                let reflectance = at(wavelength);
+                Color::new(reflectance, reflectance, reflectance)
+            }
+            None => {
+                let rgb = spectrum_to_rgb(at);
+                Color::new(
+                    rgb.r().clamp(0.0, 1.0),
+                    rgb.g().clamp(0.0, 1.0),
+                    rgb.b().clamp(0.0, 1.0),
+                )
+            }
+        }
+    }
+}
+
+// RGB constants of materials (a metal's eta and k) taken as samples at 650, 550 and
+// 450nm, interpolated in between
+pub(crate) fn rgb_at(rgb: &Color, wavelength: f64) -> f64 {
+    let t = ((wavelength - 450.0) / 100.0).clamp(0.0, 2.0);
+    match t < 1.0 {
+        true => rgb.b() + (rgb.g() - rgb.b()) * t,
+        false => rgb.g() + (rgb.r() - rgb.g()) * (t - 1.0),
+    }
+}
+
+// Unpolarized reflectance of a film of index n2 between n1 and n3, summing the waves
+// bouncing inside the film (Airy). Amplitudes and angles are complex to cover
+// conductors and total internal reflection.
+fn airy_reflectance(
+    cos_theta: f64,
+    n1: f64,
+    n2: f64,
+    n3: Complex<f64>,
+    thickness: f64,
+    wavelength: f64,
+) -> f64 {
+    let one = Complex::new(1.0, 0.0);
+    let (n1, n2) = (Complex::new(n1, 0.0), Complex::new(n2, 0.0));
+    let sin2_theta = Complex::new((1.0 - cos_theta * cos_theta).max(0.0), 0.0);
+
+    // Snell's law for the cosines inside the film and the substrate
+    let cos1 = Complex::new(cos_theta, 0.0);
+    let cos2 = (one - n1 * n1 * sin2_theta / (n2 * n2)).sqrt();
+    let cos3 = (one - n1 * n1 * sin2_theta / (n3 * n3)).sqrt();
+
+    let r12_s = (n1 * cos1 - n2 * cos2) / (n1 * cos1 + n2 * cos2);
+    let r23_s = (n2 * cos2 - n3 * cos3) / (n2 * cos2 + n3 * cos3);
+    let r12_p = (n2 * cos1 - n1 * cos2) / (n2 * cos1 + n1 * cos2);
+    let r23_p = (n3 * cos2 - n2 * cos3) / (n3 * cos2 + n2 * cos3);
+
+    // Phase picked up by a round trip through the film
+    let phase = Complex::<f64>::i() * cos2 * n2 * (4.0 * PI * thickness / wavelength);
+    let shift = phase.exp();
+    let airy = |r12: Complex<f64>, r23: Complex<f64>| {
+        ((r12 + r23 * shift) / (one + r12 * r23 * shift)).norm_sqr()
+    };
+    ((airy(r12_s, r23_s) + airy(r12_p, r23_p)) / 2.0).min(1.0)
+}
@@ -24,9 +24,9 @@ impl ImageTexture {
 }
 
 impl Texture for ImageTexture {
-    fn value(&self, mut u: f64, mut v: f64, p: &Vector3<f64>) -> crate::data::Color {
-        u = u.min(1.0).max(0.0);
-        v = 1.0 - v.min(1.0).max(0.0);
+    fn rgb(&self, mut u: f64, mut v: f64, _p: &Vector3<f64>) -> crate::data::Color {
+        u = u.clamp(0.0, 1.0);
+        v = 1.0 - v.clamp(0.0, 1.0);
         let mut i = (u * self.width as f64).round() as u32;
         let mut j = (v * self.height as f64).round() as u32;
         if i >= self.width {
@@ -1,4 +1,4 @@
-use crate::data::Color;
+use crate::data::{blackbody, spectrum_to_rgb, wavelength, Color};
 use crate::textures::Texture;
 use nalgebra::Vector3;
 use noise::{NoiseFn, Perlin, Turbulence};
@@ -12,10 +12,15 @@ impl SolidColor {
     pub fn new(c: Color) -> SolidColor {
         SolidColor { color_value: c }
     }
+
+    // Grey texture for scalar parameters such as roughness
+    pub fn scalar(value: f64) -> SolidColor {
+        SolidColor::new(Color::new(value, value, value))
+    }
 }
 
 impl Texture for SolidColor {
-    fn value(&self, _u: f64, _v: f64, _p: &Vector3<f64>) -> Color {
+    fn rgb(&self, _u: f64, _v: f64, _p: &Vector3<f64>) -> Color {
         return self.color_value;
     }
     fn share(self) -> Arc<dyn Texture> {
@@ -23,6 +28,53 @@ impl Texture for SolidColor {
     }
 }
 
+// Temperature in Kelvin at which hot objects start to glow visibly
+const DRAPER_POINT: f64 = 798.0;
+
+// Color of a black body at a temperature in Kelvin, scaled to a luminance of intensity.
+// Spectral rendering gets Planck's law itself rather than an upsampled RGB. Colder
+// temperatures than the Draper point, where bodies start to glow visibly, are raised to
+// it: below that there is next to no visible light left to scale up.
+pub struct Blackbody {
+    temperature: f64,
+    // From the relative spectrum to the requested intensity
+    scale: f64,
+    rgb: Color,
+}
+
+impl Blackbody {
+    pub fn new(temperature: f64, intensity: f64) -> Blackbody {
+        let temperature = temperature.max(DRAPER_POINT);
+        let rgb = spectrum_to_rgb(|wavelength| blackbody(wavelength, temperature));
+        let scale = intensity / rgb.luminance();
+        Blackbody {
+            temperature,
+            scale,
+            rgb: rgb * scale,
+        }
+    }
+}
+
+impl Texture for Blackbody {
+    fn rgb(&self, _u: f64, _v: f64, _p: &Vector3<f64>) -> Color {
+        self.rgb
+    }
+
+    fn share(self) -> Arc<dyn Texture> {
+        Arc::new(self)
+    }
+
+    fn value(&self, _u: f64, _v: f64, _p: &Vector3<f64>) -> Color {
+        match wavelength() {
+            Some(wavelength) => {
+                let radiance = blackbody(wavelength, self.temperature) * self.scale;
+                Color::new(radiance, radiance, radiance)
+            }
+            None => self.rgb,
+        }
+    }
+}
+
 pub struct CheckerTexture {
     odd: Arc<dyn Texture>,
     even: Arc<dyn Texture>,
@@ -38,12 +90,12 @@ impl CheckerTexture {
 }
 
 impl Texture for CheckerTexture {
-    fn value(&self, u: f64, v: f64, p: &Vector3<f64>) -> Color {
+    fn rgb(&self, u: f64, v: f64, p: &Vector3<f64>) -> Color {
         let sines = (p[0] * 10.0).sin() * (p[1] * 10.0).sin() * (p[2] * 10.0).sin();
         if sines < 0.0 {
-            return self.odd.value(u, v, p);
+            return self.odd.rgb(u, v, p);
         } else {
-            return self.even.value(u, v, p);
+            return self.even.rgb(u, v, p);
         }
     }
 
@@ -67,7 +119,7 @@ impl NoiseTexture {
 }
 
 impl Texture for NoiseTexture {
-    fn value(&self, _u: f64, _v: f64, p: &Vector3<f64>) -> Color {
+    fn rgb(&self, _u: f64, _v: f64, p: &Vector3<f64>) -> Color {
         let ps = p * self.scale;
         let mut noise_val = self.noise.get([ps[0], ps[1], ps[2]]);
         noise_val = (noise_val + 1.0) / 2.0; // [-1, 1] -> [0, 1]
@@ -79,3 +131,60 @@ impl Texture for NoiseTexture {
         Arc::new(self)
     }
 }
+
+// Tangent space normal map of square tiles with bevelled edges, tiles across each unit
+// of u and v. bevel is the fraction of a tile sloping down to the grout on each side.
+pub struct TileNormals {
+    tiles: f64,
+    bevel: f64,
+}
+
+impl TileNormals {
+    pub fn new(tiles: f64, bevel: f64) -> TileNormals {
+        TileNormals {
+            tiles,
+            bevel: bevel.clamp(1e-3, 0.5),
+        }
+    }
+}
+
+impl Texture for TileNormals {
+    fn rgb(&self, u: f64, v: f64, _p: &Vector3<f64>) -> Color {
+        // Bevels rise at the start of a tile and fall at its end, at 45 degrees
+        let tilt = |t: f64| {
+            let t = (t * self.tiles).rem_euclid(1.0);
+            if t < self.bevel {
+                -1.0
+            } else if t > 1.0 - self.bevel {
+                1.0
+            } else {
+                0.0
+            }
+        };
+        let normal = Vector3::new(tilt(u), tilt(v), 1.0).normalize();
+        Color::new(
+            0.5 * (normal[0] + 1.0),
+            0.5 * (normal[1] + 1.0),
+            0.5 * (normal[2] + 1.0),
+        )
+    }
+
+    fn share(self) -> Arc<dyn Texture> {
+        Arc::new(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blackbody_too_cold_to_glow_is_clamped() {
+        let p = Vector3::new(0.0, 0.0, 0.0);
+        let draper = Blackbody::new(DRAPER_POINT, 1.0).rgb(0.0, 0.0, &p);
+        assert!((draper.luminance() - 1.0).abs() < 1e-9);
+        for &kelvin in [0.0, 30.0, 55.0, 500.0].iter() {
+            assert_eq!(Blackbody::new(kelvin, 1.0).rgb(0.0, 0.0, &p), draper);
+        }
+    }
+}
@@ -3,6 +3,8 @@ pub mod misc;
 pub mod texture;
 
 
-pub use crate::textures::misc::{CheckerTexture, NoiseTexture, SolidColor};
+pub use crate::textures::misc::{
+    Blackbody, CheckerTexture, NoiseTexture, SolidColor, TileNormals,
+};
 pub use crate::textures::texture::Texture;
 pub use crate::textures::image::ImageTexture;
\ No newline at end of file
@@ -1,8 +1,14 @@
-use crate::data::Color;
+use crate::data::{upsample, Color};
 use nalgebra::Vector3;
 use std::sync::Arc;
 
 pub trait Texture {
-    fn value(&self, u: f64, v: f64, p: &Vector3<f64>) -> Color;
+    // Color stored in the texture
+    fn rgb(&self, u: f64, v: f64, p: &Vector3<f64>) -> Color;
     fn share(self) -> Arc<dyn Texture>;
+
+    // Color as used for shading, a spectral sample when rendering spectrally
+    fn value(&self, u: f64, v: f64, p: &Vector3<f64>) -> Color {
+        upsample(self.rgb(u, v, p))
+    }
 }